@@ -42,7 +42,7 @@ use cryptoxide::{
 };
 
 use self::rom::RomDigest;
-pub use self::rom::{Rom, RomGenerationType};
+pub use self::rom::{AllocError, Rom, RomGenerationType};
 
 // 1 byte operator
 // 3 bytes operands (src1, src2, dst)
@@ -16,7 +16,23 @@ pub(crate) struct RomDigest(pub(crate) [u8; 64]);
 /// [`hash`]: crate::hash
 pub struct Rom {
     pub(crate) digest: RomDigest,
-    data: Vec<u8>,
+    data: RomData,
+}
+
+/// Storage for the bytes of a [`Rom`]
+enum RomData {
+    Owned(Vec<u8>),
+    External(Box<dyn AsRef<[u8]> + Send + Sync>),
+}
+
+impl RomData {
+    #[inline]
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            RomData::Owned(data) => data,
+            RomData::External(data) => (**data).as_ref(),
+        }
+    }
 }
 
 /// The generation type of the **ROM**.
@@ -74,25 +90,188 @@ impl Rom {
     /// ```
     ///
     pub fn new(key: &[u8], gen_type: RomGenerationType, size: usize) -> Self {
-        let mut data = vec![0; size];
+        Self::new_parallel(key, gen_type, size, 1)
+    }
 
+    /// like [`Rom::new`], but the mixing phase of [`RomGenerationType::TwoStep`]
+    /// is split across `threads` threads
+    ///
+    /// The generated bytes and digest are identical to [`Rom::new`] for any
+    /// number of threads. [`RomGenerationType::FullRandom`] is a single
+    /// hash chain and always runs on the calling thread.
+    ///
+    /// # Panic
+    ///
+    /// same as [`Rom::new`].
+    pub fn new_parallel(
+        key: &[u8],
+        gen_type: RomGenerationType,
+        size: usize,
+        threads: usize,
+    ) -> Self {
+        Self::new_with_progress(key, gen_type, size, threads, &|_, _| {})
+    }
+
+    /// like [`Rom::new_parallel`], calling `progress(done, total)` with the
+    /// number of bytes finished so far as generation goes
+    ///
+    /// For [`RomGenerationType::TwoStep`] it is called after every block
+    /// of the mixing phase, which is most of the work, on the calling
+    /// thread; [`RomGenerationType::FullRandom`] only reports completion.
+    ///
+    /// # Panic
+    ///
+    /// same as [`Rom::new`].
+    pub fn new_with_progress(
+        key: &[u8],
+        gen_type: RomGenerationType,
+        size: usize,
+        threads: usize,
+        progress: &dyn Fn(usize, usize),
+    ) -> Self {
+        Self::generate(key, gen_type, vec![0; size], threads, progress)
+    }
+
+    /// like [`Rom::new_with_progress`], but returns an error instead of
+    /// aborting when the `size` bytes of the ROM can't be allocated
+    ///
+    /// Only the ROM itself is allocated fallibly; the buffers of the
+    /// generation, a small fraction of its size, are not.
+    ///
+    /// # Panic
+    ///
+    /// same as [`Rom::new`].
+    pub fn try_new_with_progress(
+        key: &[u8],
+        gen_type: RomGenerationType,
+        size: usize,
+        threads: usize,
+        progress: &dyn Fn(usize, usize),
+    ) -> Result<Self, AllocError> {
+        let data = try_zeroed(size).ok_or(AllocError { size })?;
+        Ok(Self::generate(key, gen_type, data, threads, progress))
+    }
+
+    fn generate(
+        key: &[u8],
+        gen_type: RomGenerationType,
+        mut data: Vec<u8>,
+        threads: usize,
+        progress: &dyn Fn(usize, usize),
+    ) -> Self {
         let seed = blake2b::Context::<256>::new()
             .update(&(data.len() as u32).to_le_bytes())
             .update(key)
             .finalize();
-        let digest = random_gen(gen_type, seed, &mut data);
+        let digest = random_gen(gen_type, seed, &mut data, threads.max(1), progress);
+
+        Self {
+            digest,
+            data: RomData::Owned(data),
+        }
+    }
+
+    /// create a [`Rom`] from previously generated bytes
+    ///
+    /// The digest is recomputed from `data`, so the bytes of a generated
+    /// [`Rom`] (see [`Rom::as_bytes`]) give back a [`Rom`] that hashes
+    /// identically.
+    ///
+    /// # Panic
+    ///
+    /// this function panics if `data` is shorter than [`DATASET_ACCESS_SIZE`].
+    pub fn from_data(data: Vec<u8>) -> Self {
+        assert!(data.len() >= DATASET_ACCESS_SIZE);
+        let digest = RomDigest(Blake2b::<512>::new().update(&data).finalize());
+        Self {
+            digest,
+            data: RomData::Owned(data),
+        }
+    }
+
+    /// create a [`Rom`] over externally owned bytes, e.g. a memory-mapped
+    /// file, with a digest previously obtained from [`Rom::digest`]
+    ///
+    /// Nothing is recomputed: `digest` must be the digest of exactly these
+    /// bytes, otherwise every hash will be wrong.
+    ///
+    /// # Panic
+    ///
+    /// this function panics if `data` is shorter than [`DATASET_ACCESS_SIZE`].
+    pub fn from_parts(digest: [u8; 64], data: impl AsRef<[u8]> + Send + Sync + 'static) -> Self {
+        assert!(data.as_ref().len() >= DATASET_ACCESS_SIZE);
+        Self {
+            digest: RomDigest(digest),
+            data: RomData::External(Box::new(data)),
+        }
+    }
+
+    /// the digest of the [`Rom`] bytes, as fed to every hash
+    pub fn digest(&self) -> &[u8; 64] {
+        &self.digest.0
+    }
 
-        Self { digest, data }
+    /// the raw bytes of the [`Rom`]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// whether the bytes were handed over with [`Rom::from_parts`] (e.g. a
+    /// memory map) rather than generated into an owned buffer; either way
+    /// they are only ever read
+    pub fn is_external(&self) -> bool {
+        matches!(self.data, RomData::External(_))
     }
 
     pub(crate) fn at(&self, i: u32) -> &[u8; DATASET_ACCESS_SIZE] {
-        let start = i as usize % (self.data.len() / DATASET_ACCESS_SIZE);
-        <&[u8; DATASET_ACCESS_SIZE]>::try_from(&self.data[start..start + DATASET_ACCESS_SIZE])
-            .unwrap()
+        let data = self.data.as_slice();
+        let start = i as usize % (data.len() / DATASET_ACCESS_SIZE);
+        <&[u8; DATASET_ACCESS_SIZE]>::try_from(&data[start..start + DATASET_ACCESS_SIZE]).unwrap()
     }
 }
 
-fn random_gen(gen_type: RomGenerationType, seed: [u8; 32], output: &mut [u8]) -> RomDigest {
+/// the bytes of a [`Rom`] could not be allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError {
+    /// size of the ROM that was asked for
+    pub size: usize,
+}
+
+impl std::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to allocate {} bytes for the ROM", self.size)
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// `size` zeroed bytes, like `vec![0; size]` but `None` when the allocator
+/// fails rather than aborting the process
+fn try_zeroed(size: usize) -> Option<Vec<u8>> {
+    if size == 0 {
+        return Some(Vec::new());
+    }
+    let layout = std::alloc::Layout::array::<u8>(size).ok()?;
+    // SAFETY: `layout` has a non-zero size, and a non-null pointer from
+    // the global allocator for it holds `size` initialized (zero) bytes
+    // that the `Vec` then owns
+    unsafe {
+        let ptr = std::alloc::alloc_zeroed(layout);
+        (!ptr.is_null()).then(|| Vec::from_raw_parts(ptr, size, size))
+    }
+}
+
+/// unit of work of the mixing phase, and of its progress reports
+const BLOCK_LEN: usize = 1024 * 1024;
+
+fn random_gen(
+    gen_type: RomGenerationType,
+    seed: [u8; 32],
+    output: &mut [u8],
+    threads: usize,
+    progress: &dyn Fn(usize, usize),
+) -> RomDigest {
+    let total = output.len();
     if let RomGenerationType::TwoStep {
         pre_size,
         mixing_numbers,
@@ -134,29 +313,84 @@ fn random_gen(gen_type: RomGenerationType, seed: [u8; 32], output: &mut [u8]) ->
 
         let offsets = offsets_bytes;
 
-        let mut digest = Blake2b::<512>::new();
         let nb_source_chunks = (pre_size / 64) as u32;
-        for (i, chunk) in output.chunks_mut(64).enumerate() {
-            let start_idx = offsets[i % offsets.len()] as u32 % nb_source_chunks;
-
-            let idx0 = (i as u32) % nb_source_chunks;
-            let offset = (idx0 as usize).wrapping_mul(64);
-            let input = &mixing_buffer[offset..offset + 64];
-            chunk.copy_from_slice(input);
-
-            for d in 1..mixing_numbers {
-                let idx = start_idx.wrapping_add(offsets_diff[(d - 1) % offsets_diff.len()] as u32)
-                    % nb_source_chunks;
-                let offset = (idx as usize).wrapping_mul(64);
+        // every chunk only depends on its index, so `first` is the index of
+        // the first chunk of `part` in the whole output
+        let mix = |first: usize, part: &mut [u8]| {
+            for (j, chunk) in part.chunks_mut(64).enumerate() {
+                let i = first + j;
+                let start_idx = offsets[i % offsets.len()] as u32 % nb_source_chunks;
+
+                let idx0 = (i as u32) % nb_source_chunks;
+                let offset = (idx0 as usize).wrapping_mul(64);
                 let input = &mixing_buffer[offset..offset + 64];
-                xorbuf(chunk, input);
+                chunk.copy_from_slice(input);
+
+                for d in 1..mixing_numbers {
+                    let idx = start_idx
+                        .wrapping_add(offsets_diff[(d - 1) % offsets_diff.len()] as u32)
+                        % nb_source_chunks;
+                    let offset = (idx as usize).wrapping_mul(64);
+                    let input = &mixing_buffer[offset..offset + 64];
+                    xorbuf(chunk, input);
+                }
             }
+        };
 
-            digest.update_mut(chunk);
+        if threads == 1 {
+            let mut digest = Blake2b::<512>::new();
+            let mut done = 0;
+            for (k, block) in output.chunks_mut(BLOCK_LEN).enumerate() {
+                mix(k * BLOCK_LEN / 64, block);
+                digest.update_mut(block);
+                done += block.len();
+                progress(done, total);
+            }
+            return RomDigest(digest.finalize());
         }
+
+        // the digest is over the chunks in order and costs about as much as
+        // the mixing, so the calling thread digests each block as soon as
+        // the blocks before it are done while `threads - 1` workers mix
+        let workers = threads - 1;
+        let mut digest = Blake2b::<512>::new();
+        let (done, ready) = std::sync::mpsc::channel::<(usize, &[u8])>();
+        std::thread::scope(|scope| {
+            let mut assigned: Vec<Vec<(usize, &mut [u8])>> = (0..workers).map(|_| vec![]).collect();
+            for (k, block) in output.chunks_mut(BLOCK_LEN).enumerate() {
+                assigned[k % workers].push((k, block));
+            }
+            for blocks in assigned {
+                let (mix, done) = (&mix, done.clone());
+                scope.spawn(move || {
+                    for (k, block) in blocks {
+                        mix(k * BLOCK_LEN / 64, block);
+                        let block: &[u8] = block;
+                        if done.send((k, block)).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(done);
+
+            let mut pending = std::collections::BTreeMap::new();
+            let mut next = 0;
+            let mut digested = 0;
+            for (k, block) in ready {
+                pending.insert(k, block);
+                while let Some(block) = pending.remove(&next) {
+                    digest.update_mut(block);
+                    next += 1;
+                    digested += block.len();
+                    progress(digested, total);
+                }
+            }
+        });
         RomDigest(digest.finalize())
     } else {
         argon2::hprime(output, &seed);
+        progress(total, total);
         RomDigest(Blake2b::<512>::new().update(output).finalize())
     }
 }
@@ -187,6 +421,57 @@ fn xorbuf(out: &mut [u8], input: &[u8]) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn rom_parallel_matches_sequential() {
+        const SIZE: usize = 1_024 * 1_024 + 64;
+        let gen_type = RomGenerationType::TwoStep {
+            pre_size: 64 * 1024,
+            mixing_numbers: 4,
+        };
+
+        let rom = Rom::new(b"password", gen_type, SIZE);
+        for threads in [2, 3, 7] {
+            let parallel = Rom::new_parallel(b"password", gen_type, SIZE, threads);
+            assert!(parallel.as_bytes() == rom.as_bytes());
+            assert_eq!(parallel.digest(), rom.digest());
+        }
+    }
+
+    #[test]
+    fn rom_progress_reaches_total() {
+        const SIZE: usize = 2 * 1_024 * 1_024 + 64;
+        let gen_type = RomGenerationType::TwoStep {
+            pre_size: 64 * 1024,
+            mixing_numbers: 4,
+        };
+
+        for threads in [1, 3] {
+            let reports = std::cell::RefCell::new(vec![]);
+            let report = |done, total| {
+                assert_eq!(total, SIZE);
+                reports.borrow_mut().push(done);
+            };
+            let rom = Rom::new_with_progress(b"password", gen_type, SIZE, threads, &report);
+            assert_eq!(*reports.borrow(), [1_024 * 1_024, 2 * 1_024 * 1_024, SIZE]);
+            assert_eq!(rom.digest(), Rom::new(b"password", gen_type, SIZE).digest());
+        }
+    }
+
+    #[test]
+    fn rom_allocation_failure() {
+        let gen_type = RomGenerationType::TwoStep {
+            pre_size: 16 * 1024,
+            mixing_numbers: 4,
+        };
+        let size = isize::MAX as usize & !63;
+        let err = Rom::try_new_with_progress(b"password", gen_type, size, 1, &|_, _| {});
+        assert_eq!(err.err(), Some(AllocError { size }));
+
+        let rom = Rom::try_new_with_progress(b"password", gen_type, 256 * 1024, 1, &|_, _| {});
+        let expected = Rom::new(b"password", gen_type, 256 * 1024);
+        assert!(rom.unwrap().as_bytes() == expected.as_bytes());
+    }
+
     #[test]
     fn rom_random_distribution() {
         let mut distribution = [0; 256];
@@ -202,7 +487,7 @@ mod tests {
             SIZE,
         );
 
-        for byte in rom.data {
+        for &byte in rom.as_bytes() {
             let index = byte as usize;
             distribution[index] += 1;
         }
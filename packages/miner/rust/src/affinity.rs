@@ -0,0 +1,84 @@
+/*!
+# CPU affinity
+
+Pins each mining thread to its own CPU with `sched_setaffinity(2)` so the
+scheduler doesn't move it between cores and throw away its cache. Worker
+`i` gets the `i`-th CPU the process may run on, wrapping around when there
+are more workers than CPUs. Linux only; elsewhere [`SUPPORTED`] is false
+and pinning fails.
+*/
+
+use anyhow::Result;
+
+/// Whether this platform can pin threads
+pub const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// Pin the calling thread to the CPU for worker `index`, returning it
+pub fn pin_current_thread(index: usize) -> Result<usize> {
+    imp::pin(index)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::io;
+    use std::mem;
+
+    use anyhow::{bail, Result};
+
+    pub fn pin(index: usize) -> Result<usize> {
+        let cpus = allowed_cpus()?;
+        if cpus.is_empty() {
+            bail!("the process may not run on any CPU");
+        }
+        let cpu = cpus[index % cpus.len()];
+
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+        if unsafe { libc::sched_setaffinity(0, mem::size_of_val(&set), &set) } != 0 {
+            bail!(
+                "sched_setaffinity to CPU {} failed: {}",
+                cpu,
+                io::Error::last_os_error()
+            );
+        }
+        Ok(cpu)
+    }
+
+    /// CPUs in the calling thread's affinity mask, in increasing order
+    pub fn allowed_cpus() -> Result<Vec<usize>> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        if unsafe { libc::sched_getaffinity(0, mem::size_of_val(&set), &mut set) } != 0 {
+            bail!("sched_getaffinity failed: {}", io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+            .collect())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    pub fn pin(_index: usize) -> Result<usize> {
+        bail!("--pin-threads is only supported on Linux")
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_thread_runs_on_one_cpu() {
+        let allowed = imp::allowed_cpus().unwrap();
+        std::thread::spawn(move || {
+            let cpu = pin_current_thread(allowed.len()).unwrap();
+            // Wrapped around to the first allowed CPU
+            assert_eq!(cpu, allowed[0]);
+            assert_eq!(imp::allowed_cpus().unwrap(), vec![cpu]);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+/*!
+# Hashrate benchmark
+
+Times ROM generation and then the same per-attempt work the miner does
+(writing the nonce into the preimage, hashing, checking the digest against
+the difficulty) for a fixed duration,
+so the hashrate is comparable to a real run, and estimates how long the
+challenge's difficulty takes at that rate.
+
+[`calibrate_threads`] runs the same loop on several threads at once for
+`--auto-threads`, since past some count the threads mostly wait on memory
+bandwidth to the shared ROM and more of them stop paying off.
+*/
+
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::estimate::Estimate;
+use crate::hasher::{AshMaizeHasher, DIGEST_SIZE};
+use crate::miner::{self, Challenge, NonceFormat, PreimageBuffer, Target};
+
+/// Timings for one benchmark run
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub hashes: u64,
+    pub hashrate: f64,
+    pub rom_build_ms: u64,
+    pub hashing_ms: u64,
+    /// Time to solve the challenge's difficulty on one thread
+    pub estimate: Estimate,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} H/s ({} hashes in {:.1}s, ROM built in {:.1}s); {}",
+            self.hashrate,
+            self.hashes,
+            self.hashing_ms as f64 / 1000.0,
+            self.rom_build_ms as f64 / 1000.0,
+            self.estimate
+        )
+    }
+}
+
+/// Hash `challenge` with successive nonces on one thread until `duration`
+/// has passed; `rom_build` is reported alongside
+pub fn run(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    duration: Duration,
+    rom_build: Duration,
+) -> BenchReport {
+    let started = Instant::now();
+    let mut hashes = 0u64;
+    let mut preimage = PreimageBuffer::new(challenge, NonceFormat::default());
+    let mut digest = [0; DIGEST_SIZE];
+    // Checked like the miner checks every digest, when the mask is valid
+    let target = Target::mask(&challenge.difficulty).ok();
+    let mut distance = Vec::new();
+    while hashes == 0 || started.elapsed() < duration {
+        preimage.set_nonce(hashes);
+        hasher.hash_into(preimage.as_str().as_bytes(), &mut digest);
+        if let Some(target) = &target {
+            target.distance_into(&digest, &mut distance);
+            std::hint::black_box((target.meets(&digest), &distance));
+        }
+        hashes += 1;
+    }
+    let hashing = started.elapsed();
+    let hashrate = hashes as f64 / hashing.as_secs_f64();
+
+    BenchReport {
+        hashes,
+        hashrate,
+        estimate: Estimate::new(
+            miner::mask_probability(&challenge.difficulty),
+            Some(hashrate),
+        ),
+        rom_build_ms: rom_build.as_millis() as u64,
+        hashing_ms: hashing.as_millis() as u64,
+    }
+}
+
+/// Combined hashrate of `threads` threads hashing `challenge` with
+/// successive nonces for `duration`, at least one hash each
+pub fn hashrate_with_threads(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    threads: usize,
+    duration: Duration,
+) -> f64 {
+    let started = Instant::now();
+    let hashes: u64 = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1) as u64)
+            .map(|index| {
+                scope.spawn(move || {
+                    let mut preimage = PreimageBuffer::new(challenge, NonceFormat::default());
+                    let mut digest = [0; DIGEST_SIZE];
+                    let mut hashes = 0u64;
+                    while hashes == 0 || started.elapsed() < duration {
+                        // Threads try disjoint nonces, like partitioned workers
+                        preimage.set_nonce((hashes << 8) | index);
+                        hasher.hash_into(preimage.as_str().as_bytes(), &mut digest);
+                        std::hint::black_box(&digest);
+                        hashes += 1;
+                    }
+                    hashes
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|w| w.join().expect("benchmark thread panicked"))
+            .sum()
+    });
+    hashes as f64 / started.elapsed().as_secs_f64()
+}
+
+/// Thread counts `--auto-threads` tries on a machine with `cpus` logical
+/// CPUs: one, half of them and all of them
+pub fn thread_candidates(cpus: usize) -> Vec<usize> {
+    let mut candidates = vec![1, cpus / 2, cpus];
+    candidates.retain(|&n| n > 0);
+    candidates.dedup();
+    candidates
+}
+
+/// Measure each of `candidates` for `duration` and return the thread count
+/// with the highest hashrate, along with every measurement
+pub fn calibrate_threads(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    candidates: &[usize],
+    duration: Duration,
+) -> (usize, Vec<(usize, f64)>) {
+    let samples: Vec<(usize, f64)> = candidates
+        .iter()
+        .map(|&threads| {
+            (
+                threads,
+                hashrate_with_threads(hasher, challenge, threads, duration),
+            )
+        })
+        .collect();
+    let best = samples
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(1, |&(threads, _)| threads);
+    (best, samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors;
+
+    #[test]
+    fn test_run_hashes_at_least_once() {
+        let challenge = vectors::spec_challenge();
+        let hasher = AshMaizeHasher::small(&challenge.no_pre_mine);
+        let report = run(
+            &hasher,
+            &challenge,
+            Duration::ZERO,
+            Duration::from_millis(1500),
+        );
+
+        assert_eq!(report.hashes, 1);
+        assert!(report.hashrate > 0.0);
+        assert_eq!(report.rom_build_ms, 1500);
+        assert!(report
+            .to_string()
+            .contains("ROM built in 1.5s); expected ~4.1K hashes"));
+    }
+
+    #[test]
+    fn test_calibrate_threads() {
+        assert_eq!(thread_candidates(1), [1]);
+        assert_eq!(thread_candidates(3), [1, 3]);
+        assert_eq!(thread_candidates(16), [1, 8, 16]);
+
+        let challenge = vectors::spec_challenge();
+        let hasher = AshMaizeHasher::small(&challenge.no_pre_mine);
+        let (best, samples) = calibrate_threads(&hasher, &challenge, &[1, 2], Duration::ZERO);
+        assert!(best == 1 || best == 2);
+        assert_eq!(samples.len(), 2);
+        assert!(samples.iter().all(|&(_, hashrate)| hashrate > 0.0));
+    }
+}
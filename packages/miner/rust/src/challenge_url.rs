@@ -0,0 +1,223 @@
+/*!
+# Challenge from a URL
+
+`--challenge-url` fetches the current challenge instead of taking its
+fields as flags: challenge_id, difficulty, no_pre_mine, latest_submission
+and no_pre_mine_hour come from the response, the address still from
+`--address`. The response may be the Scavenger Mine `GET /challenge`
+object (`{"code": "active", "challenge": {...}}`) or a bare challenge.
+
+The request goes through the system `curl`, which brings TLS and proxy
+support without linking an HTTP client. Rate limiting (429), server errors
+(5xx), timeouts and failures to connect are retried with exponential
+backoff; any other status fails at once, since asking again won't change
+it. Connecting and the whole request are bounded by [`Timeouts`], so a
+hung server costs a retry instead of stalling the miner, and a timeout is
+reported as such rather than as a server error.
+*/
+
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// Attempts after the first before giving up
+const RETRIES: u32 = 5;
+/// Wait before the first retry; each later one waits twice as long
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// curl's exit status when `--connect-timeout` or `--max-time` runs out
+const CURL_TIMED_OUT: i32 = 28;
+
+/// Limits on a single request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing the connection
+    pub connect: Duration,
+    /// The whole request, connection included
+    pub request: Duration,
+}
+
+/// Challenge fields a response provides
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChallengeFields {
+    pub challenge_id: String,
+    pub difficulty: String,
+    pub no_pre_mine: String,
+    pub latest_submission: String,
+    pub no_pre_mine_hour: String,
+}
+
+/// Why a GET got no usable response
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    /// A limit in [`Timeouts`] ran out
+    TimedOut(String),
+    /// Anything else curl gave up on, such as a refused connection
+    Unreachable(String),
+}
+
+impl Failure {
+    /// Classify a failed curl run by its exit `code` and `stderr`
+    fn from_curl(code: Option<i32>, stderr: &str) -> Self {
+        let stderr = stderr.trim().to_string();
+        if code == Some(CURL_TIMED_OUT) {
+            Self::TimedOut(stderr)
+        } else {
+            Self::Unreachable(stderr)
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::TimedOut(detail) => write!(f, "timed out: {}", detail),
+            Self::Unreachable(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+/// Fetch and parse the challenge at `url`, retrying transient failures
+pub fn fetch(url: &str, timeouts: Timeouts) -> Result<ChallengeFields> {
+    let mut retries = 0;
+    loop {
+        let error = match get(url, timeouts)? {
+            Ok((200..=299, body)) => return parse(&body),
+            Ok((status, _)) if !transient(status) => {
+                bail!("{} answered HTTP {}", url, status)
+            }
+            Ok((status, _)) => format!("HTTP {}", status),
+            Err(failure) => failure.to_string(),
+        };
+        if retries == RETRIES {
+            bail!("{} still failing after {} retries: {}", url, RETRIES, error);
+        }
+        let delay = backoff(retries);
+        retries += 1;
+        warn!(
+            "Fetching the challenge failed ({}); retry {} of {} in {}s",
+            error,
+            retries,
+            RETRIES,
+            delay.as_secs()
+        );
+        thread::sleep(delay);
+    }
+}
+
+/// One GET: the status and body, or why no response arrived
+fn get(url: &str, timeouts: Timeouts) -> Result<Result<(u16, String), Failure>> {
+    info!("Fetching the challenge from {}", url);
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--location"])
+        .args([
+            "--connect-timeout",
+            &timeouts.connect.as_secs_f64().to_string(),
+        ])
+        .args(["--max-time", &timeouts.request.as_secs_f64().to_string()])
+        .args(["--header", "Accept: application/json"])
+        .args(["--write-out", "\n%{http_code}"])
+        .arg("--")
+        .arg(url)
+        .output()
+        .context("--challenge-url needs curl on the PATH")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Ok(Err(Failure::from_curl(output.status.code(), &stderr)));
+    }
+
+    let stdout = String::from_utf8(output.stdout).context("Response is not UTF-8")?;
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let status = status
+        .parse()
+        .with_context(|| format!("curl printed no HTTP status: {:?}", status))?;
+    Ok(Ok((status, body.to_string())))
+}
+
+/// Statuses that may succeed if asked again later
+fn transient(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Wait before retry `retry` (counting from 0)
+fn backoff(retry: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(retry))
+        .min(MAX_BACKOFF)
+}
+
+/// Challenge fields of a `GET /challenge` response or a bare challenge
+fn parse(body: &str) -> Result<ChallengeFields> {
+    let value: Value = serde_json::from_str(body).context("Response is not JSON")?;
+    let challenge = match (
+        value.get("code").and_then(Value::as_str),
+        value.get("challenge"),
+    ) {
+        (_, Some(challenge)) => challenge.clone(),
+        (Some(code), None) => bail!("No active challenge (code '{}')", code),
+        (None, None) => value,
+    };
+    serde_json::from_value(challenge).context("Response is missing challenge fields")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_responses() {
+        let expected = ChallengeFields {
+            challenge_id: "**D07C10".to_string(),
+            difficulty: "000FFFFF".to_string(),
+            no_pre_mine: "fd651ac2".to_string(),
+            latest_submission: "2025-10-19T08:59:59.000Z".to_string(),
+            no_pre_mine_hour: "509681483".to_string(),
+        };
+        let challenge = r#"{"challenge_id": "**D07C10", "challenge_number": 3, "day": 1,
+            "difficulty": "000FFFFF", "no_pre_mine": "fd651ac2",
+            "latest_submission": "2025-10-19T08:59:59.000Z", "no_pre_mine_hour": "509681483"}"#;
+        let active = format!(
+            r#"{{"code": "active", "challenge": {}, "max_day": 21}}"#,
+            challenge
+        );
+        assert_eq!(parse(&active).unwrap(), expected);
+        assert_eq!(parse(challenge).unwrap(), expected);
+
+        let err = parse(r#"{"code": "before", "starts_at": "2025-10-30T00:00:00Z"}"#).unwrap_err();
+        assert!(err.to_string().contains("'before'"));
+        assert!(parse(r#"{"challenge_id": "**D07C10"}"#).is_err());
+        assert!(parse("<html>").is_err());
+
+        assert!(transient(429) && transient(503));
+        assert!(!transient(404) && !transient(200));
+        assert_eq!(backoff(0), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), 4 * INITIAL_BACKOFF);
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_timeouts_are_reported_apart() {
+        let timed_out = Failure::from_curl(
+            Some(CURL_TIMED_OUT),
+            "curl: (28) Connection timed out after 5001 milliseconds\n",
+        );
+        assert_eq!(
+            timed_out.to_string(),
+            "timed out: curl: (28) Connection timed out after 5001 milliseconds"
+        );
+        let refused = Failure::from_curl(Some(7), "curl: (7) Failed to connect");
+        assert_eq!(
+            refused,
+            Failure::Unreachable("curl: (7) Failed to connect".to_string())
+        );
+        assert!(matches!(
+            Failure::from_curl(None, ""),
+            Failure::Unreachable(_)
+        ));
+    }
+}
@@ -0,0 +1,346 @@
+/*!
+# Checkpoints
+
+`--checkpoint <path>` keeps a run's progress on disk, so a crash or reboot
+resumes the grind instead of starting over. The file is rewritten
+atomically every `--checkpoint-interval` and once more when mining stops.
+
+A partitioned (sequential-nonce) run saves the [`AttemptCursor`]'s resume
+point, so at worst a restart repeats the batches threads were in the
+middle of. A random run with `--seed` saves how many nonces each thread's
+generator has drawn, along with the seed and the generator's name
+([`NONCE_RNG`]); a restart replays each generator past its count and
+repeats at most the nonce each thread was hashing. Counts are only
+meaningful for that exact generator, so a checkpoint naming another one is
+refused rather than replayed into different nonces. Threads added on a
+restart start their own fresh streams, and the counts of threads dropped
+are kept for a later run.
+
+The file also records the job it belongs to; resuming with a different
+address, challenge, partition or seed is refused, since its progress would
+skip nonces that job never tried.
+*/
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::miner::{AttemptCursor, NONCE_RNG};
+use crate::output;
+
+/// The run a checkpoint belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    pub address: String,
+    pub challenge_id: String,
+    /// Partition, absent for a random run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_count: Option<u64>,
+}
+
+/// How far a run got
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Progress {
+    Sequential {
+        /// First attempt number the run may not have tried
+        next_attempt: u64,
+    },
+    Random {
+        /// [`NONCE_RNG`] of the build that drew them
+        rng: String,
+        seed: u64,
+        /// Nonces drawn by each thread's generator
+        draws: Vec<u64>,
+    },
+}
+
+/// Contents of a checkpoint file
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    #[serde(flatten)]
+    job: Job,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+/// Progress saved for `job` in `path`, or `None` if there is no checkpoint
+/// yet
+fn load(path: &Path, job: &Job) -> Result<Option<Progress>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let checkpoint: Checkpoint = serde_json::from_str(&text)
+        .with_context(|| format!("Checkpoint {} is not valid", path.display()))?;
+    if checkpoint.job != *job {
+        bail!(
+            "Checkpoint {} belongs to another job ({:?}); remove it to start over",
+            path.display(),
+            checkpoint.job
+        );
+    }
+    Ok(Some(checkpoint.progress))
+}
+
+/// Attempt number to resume `job` from: the one in `path`, or 0 if there is
+/// no checkpoint yet
+pub fn resume(path: &Path, job: &Job) -> Result<u64> {
+    match load(path, job)? {
+        None => Ok(0),
+        Some(Progress::Sequential { next_attempt }) => Ok(next_attempt),
+        Some(Progress::Random { .. }) => {
+            bail!(
+                "Checkpoint {} is of a random run, not a partitioned one",
+                path.display()
+            )
+        }
+    }
+}
+
+/// Nonces each thread of `job`, a random run seeded with `seed`, drew
+/// before: the counts in `path`, or none if there is no checkpoint yet
+pub fn resume_draws(path: &Path, job: &Job, seed: u64) -> Result<Vec<u64>> {
+    match load(path, job)? {
+        None => Ok(Vec::new()),
+        Some(Progress::Random { rng, .. }) if rng != NONCE_RNG => bail!(
+            "Checkpoint {} counts draws of {}, but this build draws nonces from {}; \
+             remove it to start over",
+            path.display(),
+            rng,
+            NONCE_RNG
+        ),
+        Some(Progress::Random { seed: saved, .. }) if saved != seed => bail!(
+            "Checkpoint {} was saved with --seed {}; resume with that seed or remove it",
+            path.display(),
+            saved
+        ),
+        Some(Progress::Random { draws, .. }) => Ok(draws),
+        Some(Progress::Sequential { .. }) => {
+            bail!(
+                "Checkpoint {} is of a partitioned run, not a random one",
+                path.display()
+            )
+        }
+    }
+}
+
+/// Where a run's progress is read from while it mines
+pub enum Source {
+    /// A partitioned run's attempt numbers
+    Cursor(Arc<AttemptCursor>),
+    /// A random run's draws: the counts it resumed from, plus what each
+    /// thread has hashed since (see
+    /// [`MineOptions::thread_attempts`](crate::miner::MineOptions::thread_attempts))
+    Draws {
+        seed: u64,
+        resumed: Vec<u64>,
+        hashed: Arc<[AtomicU64]>,
+    },
+}
+
+impl Source {
+    fn progress(&self) -> Progress {
+        match self {
+            Source::Cursor(cursor) => Progress::Sequential {
+                next_attempt: cursor.resume_point(),
+            },
+            Source::Draws {
+                seed,
+                resumed,
+                hashed,
+            } => Progress::Random {
+                rng: NONCE_RNG.to_string(),
+                seed: *seed,
+                draws: (0..resumed.len().max(hashed.len()))
+                    .map(|i| {
+                        let before = resumed.get(i).copied().unwrap_or(0);
+                        before + hashed.get(i).map_or(0, |h| h.load(Ordering::Relaxed))
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Background thread saving the cursor's resume point until dropped, which
+/// saves it one last time
+pub struct CheckpointWriter {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CheckpointWriter {
+    /// Start saving `job`'s progress from `source` to `path` every
+    /// `interval`
+    pub fn spawn(path: PathBuf, job: Job, source: Source, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(&path, job, &source, interval, &stop))
+        };
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for CheckpointWriter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(path: &Path, job: Job, source: &Source, interval: Duration, stop: &AtomicBool) {
+    let mut checkpoint = Checkpoint {
+        job,
+        progress: source.progress(),
+    };
+    loop {
+        thread::park_timeout(interval);
+        let stopping = stop.load(Ordering::Relaxed);
+
+        let progress = source.progress();
+        if progress != checkpoint.progress {
+            checkpoint.progress = progress;
+            if let Err(e) = save(path, &checkpoint) {
+                warn!("Failed to save checkpoint: {:#}", e);
+            }
+        }
+        if stopping {
+            return;
+        }
+    }
+}
+
+fn save(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let json = serde_json::to_string(checkpoint)?;
+    output::write_atomic(path, format!("{}\n", json).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = std::env::temp_dir().join(format!("night-cloud-ckpt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint.json");
+        let job = Job {
+            address: "addr_test1".to_string(),
+            challenge_id: "**D07C10".to_string(),
+            worker_index: Some(1),
+            worker_count: Some(4),
+        };
+        assert_eq!(resume(&path, &job).unwrap(), 0);
+
+        let cursor = Arc::new(AttemptCursor::new(1234));
+        let writer = CheckpointWriter::spawn(
+            path.clone(),
+            job.clone(),
+            Source::Cursor(cursor.clone()),
+            Duration::from_secs(3600),
+        );
+        // Nothing tried yet, so nothing to save
+        drop(writer);
+        assert!(!path.exists());
+
+        save(
+            &path,
+            &Checkpoint {
+                job: job.clone(),
+                progress: Progress::Sequential { next_attempt: 5678 },
+            },
+        )
+        .unwrap();
+        assert_eq!(resume(&path, &job).unwrap(), 5678);
+        assert!(resume_draws(&path, &job, 42).is_err());
+        let other = Job {
+            worker_index: Some(2),
+            ..job
+        };
+        assert!(resume(&path, &other).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_random_checkpoint_round_trip() {
+        let dir = std::env::temp_dir().join(format!("night-cloud-ckpt-rng-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint.json");
+        let job = Job {
+            address: "addr_test1".to_string(),
+            challenge_id: "**D07C10".to_string(),
+            worker_index: None,
+            worker_count: None,
+        };
+        assert!(resume_draws(&path, &job, 42).unwrap().is_empty());
+
+        // Restarted with two threads where the last run had three: the
+        // third thread's count is kept for a later run
+        let hashed: Arc<[AtomicU64]> = Arc::from([AtomicU64::new(5), AtomicU64::new(0)]);
+        let source = Source::Draws {
+            seed: 42,
+            resumed: vec![10, 20, 30],
+            hashed,
+        };
+        let writer =
+            CheckpointWriter::spawn(path.clone(), job.clone(), source, Duration::from_secs(3600));
+        // Nothing hashed since the writer started, so nothing to save
+        drop(writer);
+        assert!(!path.exists());
+        save(
+            &path,
+            &Checkpoint {
+                job: job.clone(),
+                progress: Source::Draws {
+                    seed: 42,
+                    resumed: vec![10, 20, 30],
+                    hashed: Arc::from([AtomicU64::new(5), AtomicU64::new(0)]),
+                }
+                .progress(),
+            },
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(&format!(
+            r#""rng":"{}","seed":42,"draws":[15,20,30]"#,
+            NONCE_RNG
+        )));
+        assert!(!text.contains("worker_index"));
+        assert_eq!(resume_draws(&path, &job, 42).unwrap(), [15, 20, 30]);
+        assert!(resume_draws(&path, &job, 43)
+            .unwrap_err()
+            .to_string()
+            .contains("--seed 42"));
+        assert!(resume(&path, &job).is_err());
+
+        // Counts of another generator would replay into other nonces
+        fs::write(&path, text.replace(NONCE_RNG, "chacha")).unwrap();
+        assert!(resume_draws(&path, &job, 42)
+            .unwrap_err()
+            .to_string()
+            .contains("chacha"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
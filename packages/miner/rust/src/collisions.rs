@@ -0,0 +1,125 @@
+/*!
+# Hash prefix collisions
+
+Hashes random preimages against one ROM and reports every pair whose
+digests share a prefix. Used to sanity-check how uniform AshMaize output
+is, not for mining.
+*/
+
+use std::collections::HashMap;
+use std::io;
+use std::time::Instant;
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::hasher::AshMaizeHasher;
+
+/// A preimage and its digest
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub preimage: String,
+    pub hash: String,
+}
+
+/// Two samples whose hashes share a prefix
+#[derive(Debug, Serialize)]
+pub struct Collision {
+    pub prefix: String,
+    pub first: Sample,
+    pub second: Sample,
+}
+
+/// Outcome of a collision search
+#[derive(Debug, Serialize)]
+pub struct CollisionReport {
+    pub samples: u64,
+    pub prefix_bytes: usize,
+    /// Number of colliding pairs, counting every pair within a prefix group
+    pub collisions: u64,
+    /// Expected number of colliding pairs for a uniform hash (birthday bound)
+    pub expected_collisions: f64,
+    pub elapsed_ms: u128,
+    pub hashrate: f64,
+}
+
+/// Hash `samples` random preimages and pass every sample whose first
+/// `prefix_bytes` bytes of output match an earlier one to `on_collision`,
+/// paired with the first sample seen for that prefix
+///
+/// Collisions are handed over as they are found rather than collected, so
+/// callers can stream them. `prefix_bytes` must be between 1 and 8.
+pub fn search(
+    hasher: &AshMaizeHasher,
+    samples: u64,
+    prefix_bytes: usize,
+    mut on_collision: impl FnMut(Collision) -> io::Result<()>,
+) -> io::Result<CollisionReport> {
+    assert!(
+        (1..=8).contains(&prefix_bytes),
+        "prefix_bytes must be 1..=8"
+    );
+
+    let mut rng = rand::thread_rng();
+    // First sample seen per prefix, and how many have shared it
+    let mut seen: HashMap<u64, (Sample, u64)> = HashMap::new();
+    let mut collisions = 0;
+    let started = Instant::now();
+
+    for _ in 0..samples {
+        let preimage = format!("{:032x}", rng.gen::<u128>());
+        let hash = hasher.hash(preimage.as_bytes());
+        let key = hash[..prefix_bytes]
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        let sample = Sample {
+            preimage,
+            hash: hex::encode(&hash),
+        };
+
+        match seen.get_mut(&key) {
+            Some((first, count)) => {
+                collisions += *count;
+                *count += 1;
+                on_collision(Collision {
+                    prefix: hex::encode(&hash[..prefix_bytes]),
+                    first: first.clone(),
+                    second: sample,
+                })?;
+            }
+            None => {
+                seen.insert(key, (sample, 1));
+            }
+        }
+    }
+
+    let elapsed = started.elapsed();
+    Ok(CollisionReport {
+        samples,
+        prefix_bytes,
+        collisions,
+        expected_collisions: expected_collisions(samples, prefix_bytes),
+        elapsed_ms: elapsed.as_millis(),
+        hashrate: samples as f64 / elapsed.as_secs_f64(),
+    })
+}
+
+/// Expected colliding pairs among `samples` uniform values of `prefix_bytes` bytes
+fn expected_collisions(samples: u64, prefix_bytes: usize) -> f64 {
+    let n = samples as f64;
+    let space = 2f64.powi(8 * prefix_bytes as i32);
+    n * (n - 1.0) / 2.0 / space
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_collisions() {
+        // 2^16 samples over a 2^16 space give ~2^15 pairs
+        let expected = expected_collisions(1 << 16, 2);
+        assert!((expected - 32767.5).abs() < 1e-6);
+        assert_eq!(expected_collisions(1, 1), 0.0);
+    }
+}
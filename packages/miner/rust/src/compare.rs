@@ -0,0 +1,111 @@
+/*!
+# Hasher parity
+
+Hashes the same preimages through two hashers and reports where they
+disagree, to check whether two configurations (seed bytes, seed encoding,
+ROM size) are interchangeable.
+*/
+
+use serde::Serialize;
+
+use crate::hasher::AshMaizeHasher;
+
+/// How the seed string is turned into ROM key bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SeedEncoding {
+    /// The UTF-8 bytes of the string, as the miner does today
+    String,
+    /// The bytes the hex string decodes to, as the spec examples assume
+    Hex,
+}
+
+impl SeedEncoding {
+    pub fn key(self, seed: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            SeedEncoding::String => seed.as_bytes().to_vec(),
+            SeedEncoding::Hex => hex::decode(seed)?,
+        })
+    }
+}
+
+/// First preimage the hashers disagree on
+#[derive(Debug, Serialize)]
+pub struct Divergence {
+    pub index: u64,
+    pub preimage: String,
+    pub hash_a: String,
+    pub hash_b: String,
+}
+
+/// Outcome of a parity run
+#[derive(Debug, Serialize)]
+pub struct CompareReport {
+    pub compared: u64,
+    pub mismatches: u64,
+    pub first_divergence: Option<Divergence>,
+}
+
+/// Hash every preimage through both hashers and count disagreements
+pub fn compare(
+    a: &AshMaizeHasher,
+    b: &AshMaizeHasher,
+    preimages: impl IntoIterator<Item = String>,
+) -> CompareReport {
+    let mut report = CompareReport {
+        compared: 0,
+        mismatches: 0,
+        first_divergence: None,
+    };
+
+    for preimage in preimages {
+        let hash_a = a.hash(preimage.as_bytes());
+        let hash_b = b.hash(preimage.as_bytes());
+        if hash_a != hash_b {
+            report.mismatches += 1;
+            if report.first_divergence.is_none() {
+                report.first_divergence = Some(Divergence {
+                    index: report.compared,
+                    preimage,
+                    hash_a: hex::encode(hash_a),
+                    hash_b: hex::encode(hash_b),
+                });
+            }
+        }
+        report.compared += 1;
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hasher::{HashParams, SMALL_PRE_SIZE, SMALL_ROM_SIZE};
+
+    #[test]
+    fn test_compare() {
+        let preimages = || (0..3).map(|i| format!("{:016x}preimage", i));
+        let seed = "fd651ac2";
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE);
+
+        let string =
+            AshMaizeHasher::from_seed_bytes(&SeedEncoding::String.key(seed).unwrap(), &params);
+        let same = AshMaizeHasher::with_sizes(seed, SMALL_PRE_SIZE, SMALL_ROM_SIZE);
+        let report = compare(&string, &same, preimages());
+        assert_eq!(report.compared, 3);
+        assert_eq!(report.mismatches, 0);
+        assert!(report.first_divergence.is_none());
+
+        let decoded =
+            AshMaizeHasher::from_seed_bytes(&SeedEncoding::Hex.key(seed).unwrap(), &params);
+        let report = compare(&string, &decoded, preimages());
+        assert_eq!(report.mismatches, 3);
+        let first = report.first_divergence.unwrap();
+        assert_eq!(first.index, 0);
+        assert_eq!(first.preimage, "0000000000000000preimage");
+        assert_ne!(first.hash_a, first.hash_b);
+
+        assert!(SeedEncoding::Hex.key("xyz").is_err());
+    }
+}
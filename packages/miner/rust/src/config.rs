@@ -0,0 +1,157 @@
+/*!
+# Config files
+
+`--config <path>` reads a JSON object whose keys are the command line's
+flags in snake_case, e.g. `{"address": "addr1...", "max_attempts": 1000}`:
+the same names a challenge stream line uses. The values become arguments
+placed ahead of the real ones, skipping any flag that was given explicitly,
+so the command line wins and clap checks both alike; a required field
+missing from both gets clap's usual error.
+*/
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, Command};
+use serde_json::Value;
+
+/// Id of the argument naming the config file
+pub const CONFIG_ARG: &str = "config";
+
+/// `args` with the fields of the `--config` file they name spliced in, or
+/// unchanged without one, and a warning for each unknown field, which is
+/// skipped
+///
+/// The warnings are returned rather than logged because this runs before
+/// the parsed flags can set up logging.
+pub fn expand_args(command: &Command, args: Vec<OsString>) -> Result<(Vec<OsString>, Vec<String>)> {
+    // Errors are reported by the real parse
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+    else {
+        return Ok((args, Vec::new()));
+    };
+    let Some(path) = matches
+        .get_raw(CONFIG_ARG)
+        .and_then(|mut values| values.next())
+    else {
+        return Ok((args, Vec::new()));
+    };
+    let path = Path::new(path);
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    let fields: serde_json::Map<String, Value> = serde_json::from_str(&text)
+        .with_context(|| format!("Config {} is not a JSON object", path.display()))?;
+
+    let mut args = args.into_iter();
+    let mut expanded: Vec<OsString> = args.next().into_iter().collect();
+    let mut warnings = Vec::new();
+    for (key, value) in fields {
+        let Some(arg) = find_flag(command, &key) else {
+            warnings.push(format!(
+                "Ignoring unknown field '{}' in config {}",
+                key,
+                path.display()
+            ));
+            continue;
+        };
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().unwrap_or_default());
+        let values = match value {
+            Value::Array(items) => items,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Null | Value::Bool(false) => {}
+                Value::Bool(true) => expanded.push(flag.clone().into()),
+                Value::String(s) => expanded.extend([flag.clone().into(), s.into()]),
+                Value::Number(n) => expanded.extend([flag.clone().into(), n.to_string().into()]),
+                Value::Array(_) | Value::Object(_) => {
+                    bail!("Config field '{}' must be a string, number or boolean", key)
+                }
+            }
+        }
+    }
+    expanded.extend(args);
+    Ok((expanded, warnings))
+}
+
+/// The flag a config key stands for: its argument id, or its long name with
+/// underscores, as in `seed`
+fn find_flag<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|arg| {
+        arg.get_id() != CONFIG_ARG
+            && arg
+                .get_long()
+                .is_some_and(|long| arg.get_id() == key || long.replace('-', "_") == key)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+    use clap::ArgAction;
+
+    fn command() -> Command {
+        Command::new("night-cloud")
+            .arg(Arg::new("address").long("address").required(true))
+            .arg(
+                Arg::new("max_attempts")
+                    .long("max-attempts")
+                    .default_value("10"),
+            )
+            .arg(Arg::new("rng_seed").long("seed"))
+            .arg(
+                Arg::new("verify_after")
+                    .long("verify-after")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new(CONFIG_ARG).long("config"))
+    }
+
+    #[test]
+    fn test_command_line_overrides_config() {
+        let dir = std::env::temp_dir().join(format!("night-cloud-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let args = |extra: &[&str]| -> Vec<OsString> {
+            let mut args = vec!["night-cloud", "--config", path.to_str().unwrap()];
+            args.extend(extra);
+            args.into_iter().map(OsString::from).collect()
+        };
+
+        fs::write(
+            &path,
+            r#"{"address": "addr_test1", "max_attempts": 5, "seed": 42, "verify_after": true, "colour": "red"}"#,
+        )
+        .unwrap();
+        let (expanded, warnings) = expand_args(&command(), args(&["--max-attempts", "7"])).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("'colour'"));
+        let matches = command().try_get_matches_from(expanded).unwrap();
+        assert_eq!(matches.get_one::<String>("address").unwrap(), "addr_test1");
+        assert_eq!(matches.get_one::<String>("max_attempts").unwrap(), "7");
+        assert_eq!(matches.get_one::<String>("rng_seed").unwrap(), "42");
+        assert!(matches.get_flag("verify_after"));
+
+        // Missing from both: the same error as without a config
+        fs::write(&path, r#"{"max_attempts": 5}"#).unwrap();
+        let (expanded, _) = expand_args(&command(), args(&[])).unwrap();
+        let err = command().try_get_matches_from(expanded).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::MissingRequiredArgument);
+
+        fs::write(&path, "[1, 2]").unwrap();
+        assert!(expand_args(&command(), args(&[])).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
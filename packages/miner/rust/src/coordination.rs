@@ -0,0 +1,281 @@
+/*!
+# Coordination file
+
+`--coordination-file` is an NDJSON file partitioned workers share. Before
+mining, each appends a `claim` line with the attempts its partition will
+try, `{"claim": {"index", "count", "start", "end"}}` (attempts `start` up
+to but excluding `end`, nonce `attempt * count + index`), and refuses to
+start if any of those nonces is already claimed: a misconfigured range
+would only repeat another worker's hashes. A checkpointed run may claim
+its own partition again when it restarts.
+
+The first worker to find a solution appends a `solution` line holding its
+result; the others see it within [`STOP_FILE_CHECK_INTERVAL`] attempts and
+stop. Every read and append holds an exclusive lock on the file, so two
+workers starting or finishing at once can't both get through. Remove the
+file before starting a new round.
+
+[`STOP_FILE_CHECK_INTERVAL`]: crate::miner::STOP_FILE_CHECK_INTERVAL
+*/
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Attempts a partitioned worker announces it will try
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claim {
+    pub index: u64,
+    pub count: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Claim {
+    /// Whether the two claims share a nonce
+    pub fn overlaps(&self, other: &Claim) -> bool {
+        let (Some((lo_a, hi_a)), Some((lo_b, hi_b))) = (self.nonces(), other.nonces()) else {
+            return false;
+        };
+        let (lo, hi) = (lo_a.max(lo_b), hi_a.min(hi_b));
+        if lo > hi {
+            return false;
+        }
+
+        // Nonces that are `index` modulo `count` for both, by the Chinese
+        // remainder theorem: none unless the indexes agree modulo the gcd
+        let (count_a, count_b) = (self.count as u128, other.count as u128);
+        let (index_a, index_b) = (self.index as u128, other.index as u128);
+        let gcd = gcd(count_a, count_b);
+        let diff = (index_b + count_b - index_a % count_b) % count_b;
+        if diff % gcd != 0 {
+            return false;
+        }
+        let (step_a, step_b) = (count_a / gcd, count_b / gcd);
+        let k = (diff / gcd) * inverse(step_a % step_b, step_b) % step_b;
+        let lcm = count_a * step_b;
+        let residue = (index_a + count_a * k) % lcm;
+
+        // The first common nonce at or above `lo`
+        let offset = match residue.checked_sub(lo % lcm) {
+            Some(offset) => offset,
+            None => lcm - (lo % lcm - residue),
+        };
+        let first = lo.checked_add(offset);
+        first.is_some_and(|first| first <= hi)
+    }
+
+    /// First and last nonce, `None` for an empty claim
+    fn nonces(&self) -> Option<(u128, u128)> {
+        if self.start >= self.end {
+            return None;
+        }
+        let nonce = |attempt: u64| attempt as u128 * self.count as u128 + self.index as u128;
+        Some((nonce(self.start), nonce(self.end - 1)))
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Inverse of `a` modulo `m`, for coprime `a` and `m`
+fn inverse(a: u128, m: u128) -> u128 {
+    if m == 1 {
+        return 0;
+    }
+    let (mut old_r, mut r) = (a as i128, m as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    old_s.rem_euclid(m as i128) as u128
+}
+
+/// One line of the coordination file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Claim(Claim),
+    Solution(Value),
+}
+
+/// Open `path` for appending, creating it, and lock it for the caller
+fn open_locked(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+    Ok(file)
+}
+
+/// Every record in `file`, read from the start
+fn records(file: &mut File, path: &Path) -> Result<Vec<Record>> {
+    file.rewind()?;
+    let mut records = Vec::new();
+    for line in BufReader::new(&*file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .with_context(|| format!("{} is not a coordination file", path.display()))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn append(file: &mut File, record: &Record) -> Result<()> {
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Append `claim` to `path`, or fail naming the claim it overlaps
+///
+/// With `resuming`, earlier claims of the same partition are taken to be
+/// this worker's own from before a restart.
+pub fn claim(path: &Path, claim: Claim, resuming: bool) -> Result<()> {
+    let mut file = open_locked(path)?;
+    for record in records(&mut file, path)? {
+        match record {
+            Record::Solution(_) => bail!(
+                "{} already holds a solution; remove it before starting a new round",
+                path.display()
+            ),
+            Record::Claim(other) => {
+                let own = resuming && (other.index, other.count) == (claim.index, claim.count);
+                if !own && claim.overlaps(&other) {
+                    bail!(
+                        "Attempts {}..{} of worker {} of {} overlap attempts {}..{} of worker {} \
+                         of {} already claimed in {}; fix --worker-index/--worker-count or \
+                         remove the file",
+                        claim.start,
+                        claim.end,
+                        claim.index,
+                        claim.count,
+                        other.start,
+                        other.end,
+                        other.index,
+                        other.count,
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+    append(&mut file, &Record::Claim(claim))
+}
+
+/// Append `solution` to `path` unless another worker's is already there;
+/// whether this one was first
+pub fn publish(path: &Path, solution: &Value) -> Result<bool> {
+    let mut file = open_locked(path)?;
+    let solved = records(&mut file, path)?
+        .iter()
+        .any(|record| matches!(record, Record::Solution(_)));
+    if solved {
+        return Ok(false);
+    }
+    append(&mut file, &Record::Solution(solution.clone()))?;
+    Ok(true)
+}
+
+/// Whether a worker has published a solution to `path`; a file that is
+/// missing or can't be read counts as not yet
+pub fn solved(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    open_locked(path)
+        .and_then(|mut file| records(&mut file, path))
+        .is_ok_and(|records| {
+            records
+                .iter()
+                .any(|record| matches!(record, Record::Solution(_)))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim_of(index: u64, count: u64, start: u64, end: u64) -> Claim {
+        Claim {
+            index,
+            count,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_overlaps() {
+        // Same partitioning: only the same index, and only where the
+        // attempts meet
+        assert!(claim_of(1, 4, 0, 100).overlaps(&claim_of(1, 4, 99, 200)));
+        assert!(!claim_of(1, 4, 0, 100).overlaps(&claim_of(1, 4, 100, 200)));
+        assert!(!claim_of(1, 4, 0, 100).overlaps(&claim_of(2, 4, 0, 100)));
+        assert!(!claim_of(0, 4, 5, 5).overlaps(&claim_of(0, 4, 0, 100)));
+
+        // Worker 1 of 2 tries odd nonces, which worker 3 of 4 shares and
+        // worker 2 of 4 doesn't
+        assert!(claim_of(1, 2, 0, 10).overlaps(&claim_of(3, 4, 0, 10)));
+        assert!(!claim_of(1, 2, 0, 10).overlaps(&claim_of(2, 4, 0, 10)));
+        // 5 of 6 tries 5, 11, 17, ..; 2 of 3 tries 2, 5, 8, .. from attempt 1
+        assert!(claim_of(5, 6, 0, 1).overlaps(&claim_of(2, 3, 1, 2)));
+        assert!(!claim_of(5, 6, 0, 1).overlaps(&claim_of(2, 3, 2, 10)));
+        // Coprime counts always meet somewhere once the ranges are wide
+        assert!(claim_of(2, 3, 0, 10).overlaps(&claim_of(4, 5, 0, 10)));
+        assert!(!claim_of(2, 3, 0, 4).overlaps(&claim_of(4, 5, 0, 1)));
+        // Unbounded runs
+        assert!(claim_of(0, 1, 0, u64::MAX).overlaps(&claim_of(7, 8, 0, u64::MAX)));
+        assert!(!claim_of(1, u64::MAX, 0, u64::MAX).overlaps(&claim_of(2, u64::MAX, 0, u64::MAX)));
+    }
+
+    #[test]
+    fn test_claims_and_solutions() {
+        let path = std::env::temp_dir().join(format!("night-cloud-coord-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        claim(&path, claim_of(0, 2, 0, 1000), false).unwrap();
+        claim(&path, claim_of(1, 2, 0, 1000), false).unwrap();
+        let err = claim(&path, claim_of(2, 4, 0, 10), false).unwrap_err();
+        assert!(err.to_string().contains("worker 0 of 2"), "{}", err);
+        // A restarted checkpointed worker reclaims its own partition
+        claim(&path, claim_of(1, 2, 300, 1000), true).unwrap();
+        assert!(!solved(&path));
+
+        let solution = serde_json::json!({"nonce": "0000000000000001"});
+        assert!(publish(&path, &solution).unwrap());
+        assert!(!publish(&path, &serde_json::json!({})).unwrap());
+        assert!(solved(&path));
+        assert!(claim(&path, claim_of(0, 2, 1000, 2000), false).is_err());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"claim":{"index":0,"count":2,"start":0,"end":1000}}"#
+        );
+        assert_eq!(lines[3], r#"{"solution":{"nonce":"0000000000000001"}}"#);
+        assert_eq!(lines.len(), 4);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!solved(&path));
+    }
+}
@@ -0,0 +1,96 @@
+/*!
+# CPU count
+
+Mining threads default to the CPUs this process may use, which in a
+container is often far fewer than the host's cores it sees: a pod limited
+to 4 CPUs on a 64-core node that starts 64 threads only thrashes.
+
+The cgroup's CPU quota is read first, from cgroup v2 `cpu.max` or else
+v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us`, rounded up to whole CPUs
+and capped at [`std::thread::available_parallelism`], which honours CPU
+affinity. Without a quota, or where none can be read, that is the count.
+`--cpu-count-override` replaces the detection altogether.
+*/
+
+use std::fs;
+
+use tracing::debug;
+
+/// cgroup v2 quota file: `$MAX $PERIOD`, or `max $PERIOD` for no quota
+const CGROUP_V2: &str = "/sys/fs/cgroup/cpu.max";
+/// cgroup v1 quota and period files; a quota of -1 means none
+const CGROUP_V1: (&str, &str) = (
+    "/sys/fs/cgroup/cpu/cpu.cfs_quota_us",
+    "/sys/fs/cgroup/cpu/cpu.cfs_period_us",
+);
+
+/// CPUs this process may use, at least one
+pub fn available() -> usize {
+    let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+    match quota() {
+        Some(quota) => {
+            debug!("cgroup CPU quota: {} CPUs", quota);
+            quota.min(parallelism)
+        }
+        None => parallelism,
+    }
+}
+
+/// Whole CPUs the cgroup's quota allows, if it sets one
+pub fn quota() -> Option<usize> {
+    if let Ok(cpu_max) = fs::read_to_string(CGROUP_V2) {
+        return parse_cpu_max(&cpu_max);
+    }
+    let (quota, period) = CGROUP_V1;
+    parse_cfs_quota(
+        &fs::read_to_string(quota).ok()?,
+        &fs::read_to_string(period).ok()?,
+    )
+}
+
+/// CPUs a cgroup v2 `cpu.max` allows; `None` for `max` or a malformed file
+fn parse_cpu_max(cpu_max: &str) -> Option<usize> {
+    let mut fields = cpu_max.split_whitespace();
+    let quota = fields.next()?;
+    let period = fields.next().unwrap_or("100000");
+    whole_cpus(quota.parse().ok()?, period.parse().ok()?)
+}
+
+/// CPUs a cgroup v1 quota and period allow; `None` for a quota of -1
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<usize> {
+    let quota: i64 = quota.trim().parse().ok()?;
+    let period: u64 = period.trim().parse().ok()?;
+    whole_cpus(u64::try_from(quota).ok()?, period)
+}
+
+/// `quota / period` rounded up, so half a CPU still gets a thread
+fn whole_cpus(quota: u64, period: u64) -> Option<usize> {
+    if quota == 0 || period == 0 {
+        return None;
+    }
+    usize::try_from(quota.div_ceil(period)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_parsing() {
+        assert_eq!(parse_cpu_max("400000 100000\n"), Some(4));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("50000 100000\n"), Some(1));
+        assert_eq!(parse_cpu_max("200000\n"), Some(2));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max(""), None);
+        assert_eq!(parse_cpu_max("0 100000\n"), None);
+
+        assert_eq!(parse_cfs_quota("400000\n", "100000\n"), Some(4));
+        assert_eq!(parse_cfs_quota("250000\n", "100000\n"), Some(3));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+        assert_eq!(parse_cfs_quota("400000\n", "0\n"), None);
+        assert_eq!(parse_cfs_quota("lots\n", "100000\n"), None);
+
+        assert!(available() >= 1);
+    }
+}
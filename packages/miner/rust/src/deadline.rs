@@ -0,0 +1,227 @@
+/*!
+# Submission deadline
+
+Parsing of the challenge's `latest_submission` timestamp and of
+`--max-duration` budgets, and the `--auto-budget` estimator that stops
+hashing once no further attempt fits before the deadline.
+*/
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+/// Parse an RFC 3339 timestamp such as `2025-10-19T08:59:59.000Z`
+pub fn parse(latest_submission: &str) -> Result<DateTime<Utc>> {
+    let deadline = DateTime::parse_from_rfc3339(latest_submission).with_context(|| {
+        format!(
+            "latest_submission '{}' is not an RFC 3339 timestamp",
+            latest_submission
+        )
+    })?;
+
+    Ok(deadline.with_timezone(&Utc))
+}
+
+/// Milliseconds left between `now` and the deadline (negative once it has passed)
+pub fn margin_ms(deadline: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    deadline.signed_duration_since(now).num_milliseconds()
+}
+
+/// Milliseconds left before `latest_submission` right now, or `None` (with a
+/// warning) if the timestamp does not parse
+pub fn margin_from_now(latest_submission: &str) -> Option<i64> {
+    match parse(latest_submission) {
+        Ok(deadline) => Some(margin_ms(deadline, Utc::now())),
+        Err(e) => {
+            warn!("Cannot report deadline margin: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Parse a duration such as `90`, `30s`, `5m`, `2h` or `500ms`; a bare
+/// number is seconds
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("'{}' does not start with a number", value))?;
+    let millis = match unit {
+        "ms" => 1,
+        "" | "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => bail!("'{}' has unknown unit '{}' (use ms, s, m or h)", value, unit),
+    };
+    match number.checked_mul(millis) {
+        Some(ms) if ms > 0 => Ok(Duration::from_millis(ms)),
+        Some(_) => bail!("duration must be more than zero"),
+        None => bail!("'{}' is too long", value),
+    }
+}
+
+/// Deadlines further than this from the clock suggest the clock is wrong
+const PLAUSIBLE_SKEW_DAYS: i64 = 365;
+
+/// 2020-01-01T00:00:00Z; any earlier reading means the clock was never set
+const EARLIEST_PLAUSIBLE_CLOCK: i64 = 1_577_836_800;
+
+/// Explain why `now` looks wrong next to `deadline`, if it does
+///
+/// Real deadlines are hours to days away, so a clock more than a year off,
+/// or one still near the epoch, is almost certainly unset or skewed and will
+/// make every deadline look expired or endless.
+pub fn clock_problem(deadline: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+    if now.timestamp() < EARLIEST_PLAUSIBLE_CLOCK {
+        return Some(format!(
+            "System clock reads {}, which looks unset",
+            now.to_rfc3339()
+        ));
+    }
+
+    let days = deadline.signed_duration_since(now).num_days();
+    if days.abs() > PLAUSIBLE_SKEW_DAYS {
+        return Some(format!(
+            "System clock reads {} but latest_submission is {} ({} days {}); check the clock",
+            now.to_rfc3339(),
+            deadline.to_rfc3339(),
+            days.abs(),
+            if days > 0 { "ahead" } else { "behind" }
+        ));
+    }
+    None
+}
+
+/// Weight of the newest hash time in the running estimate
+const HASH_TIME_SMOOTHING: f64 = 0.05;
+
+/// Attempt budget that tracks the time left before a deadline
+///
+/// The effective budget is however many hashes fit in the remaining time at
+/// the current hashrate, re-estimated after every hash.
+#[derive(Debug)]
+pub struct AutoBudget {
+    deadline: Instant,
+    /// Smoothed seconds per hash, once at least one hash has been timed
+    hash_secs: Option<f64>,
+}
+
+impl AutoBudget {
+    /// Budget running until `deadline`, measured against the wall clock now
+    pub fn new(deadline: DateTime<Utc>) -> Self {
+        let left = deadline
+            .signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        Self::until(Instant::now() + left)
+    }
+
+    fn until(deadline: Instant) -> Self {
+        Self {
+            deadline,
+            hash_secs: None,
+        }
+    }
+
+    /// Fold the duration of one hash into the hashrate estimate
+    pub fn record(&mut self, hash_time: Duration) {
+        let secs = hash_time.as_secs_f64();
+        self.hash_secs = Some(match self.hash_secs {
+            Some(avg) => avg + HASH_TIME_SMOOTHING * (secs - avg),
+            None => secs,
+        });
+    }
+
+    /// Hashes that still fit before the deadline at `now`
+    pub fn remaining(&self, now: Instant) -> u64 {
+        let left = self.deadline.saturating_duration_since(now).as_secs_f64();
+        match self.hash_secs {
+            Some(secs) if secs > 0.0 => (left / secs) as u64,
+            // Nothing measured yet: one attempt fits if any time is left
+            _ => u64::from(left > 0.0),
+        }
+    }
+
+    /// Whether another hash started at `now` would finish after the deadline
+    pub fn exhausted(&self, now: Instant) -> bool {
+        self.remaining(now) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_margin() {
+        let deadline = parse("2025-10-19T08:59:59.000Z").unwrap();
+
+        let before = parse("2025-10-19T08:59:58.250Z").unwrap();
+        assert_eq!(margin_ms(deadline, before), 750);
+
+        let after = parse("2025-10-19T09:00:01+00:00").unwrap();
+        assert_eq!(margin_ms(deadline, after), -2000);
+    }
+
+    #[test]
+    fn test_invalid_timestamp() {
+        assert!(parse("509681483").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+
+        for bad in ["", "s", "5 m", "1.5s", "10d", "0s", "-5s"] {
+            assert!(parse_duration(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_auto_budget() {
+        let start = Instant::now();
+        let mut budget = AutoBudget::until(start + Duration::from_secs(10));
+        assert_eq!(budget.remaining(start), 1);
+
+        budget.record(Duration::from_millis(2));
+        assert_eq!(budget.remaining(start), 5000);
+
+        // A slower hashrate shrinks the budget as it is observed
+        for _ in 0..200 {
+            budget.record(Duration::from_millis(4));
+        }
+        let remaining = budget.remaining(start);
+        assert!((2490..=2510).contains(&remaining), "{}", remaining);
+
+        let near = start + Duration::from_millis(9_999);
+        assert!(budget.exhausted(near));
+        assert!(budget.exhausted(start + Duration::from_secs(11)));
+        assert!(!budget.exhausted(start + Duration::from_secs(9)));
+
+        let passed = AutoBudget::new(parse("2020-01-01T00:00:00Z").unwrap());
+        assert!(passed.exhausted(Instant::now()));
+    }
+
+    #[test]
+    fn test_clock_problem() {
+        let deadline = parse("2025-10-19T08:59:59.000Z").unwrap();
+
+        assert!(clock_problem(deadline, parse("2025-10-19T07:00:00Z").unwrap()).is_none());
+        assert!(clock_problem(deadline, parse("2025-11-19T07:00:00Z").unwrap()).is_none());
+
+        let epoch = clock_problem(deadline, parse("1970-01-01T00:00:00Z").unwrap()).unwrap();
+        assert!(epoch.contains("looks unset"), "{}", epoch);
+        let skewed = clock_problem(deadline, parse("2023-06-01T00:00:00Z").unwrap()).unwrap();
+        assert!(skewed.contains("871 days ahead"), "{}", skewed);
+        let behind = clock_problem(deadline, parse("2027-01-01T00:00:00Z").unwrap()).unwrap();
+        assert!(behind.contains("behind"), "{}", behind);
+    }
+}
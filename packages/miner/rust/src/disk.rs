@@ -0,0 +1,206 @@
+/*!
+# Disk budget
+
+`--max-disk-usage-mb` caps the combined size of the files the miner writes
+(SQLite database, event log, hash file) and of the ROM cache. The check
+runs before a run starts. The oldest cached ROMs are deleted first, since
+any of them can be regenerated; if the output files alone still use the
+whole budget the run is refused rather than left to fill the volume.
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+
+/// Files SQLite keeps next to a database
+const SQLITE_SIDECARS: [&str; 3] = ["-journal", "-wal", "-shm"];
+
+/// Bytes currently used by `paths` and their SQLite sidecar files;
+/// missing files count as empty
+pub fn usage_bytes(paths: &[&Path]) -> u64 {
+    let size = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len());
+
+    paths
+        .iter()
+        .map(|path| {
+            let sidecars: u64 = SQLITE_SIDECARS
+                .iter()
+                .map(|suffix| {
+                    let mut name = path.as_os_str().to_owned();
+                    name.push(suffix);
+                    size(Path::new(&name))
+                })
+                .sum();
+            size(path) + sidecars
+        })
+        .sum()
+}
+
+/// Cached ROM deleted to stay within the budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pruned {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// `.rom` files in `dir` with their sizes, least recently modified first;
+/// a missing directory has none
+fn cache_entries(dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list {}", dir.display())),
+    };
+
+    let mut roms = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("rom") {
+            continue;
+        }
+        let Ok(meta) = fs::metadata(&path) else {
+            continue;
+        };
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        roms.push((modified, path, meta.len()));
+    }
+    roms.sort();
+    Ok(roms
+        .into_iter()
+        .map(|(_, path, bytes)| (path, bytes))
+        .collect())
+}
+
+/// Bytes used by the cached ROMs in `dir`
+pub fn cache_bytes(dir: &Path) -> Result<u64> {
+    Ok(cache_entries(dir)?.iter().map(|(_, bytes)| bytes).sum())
+}
+
+/// Delete the oldest cached ROMs in `cache_dir` until they and `paths`
+/// together use less than `max_mb`, sparing `keep` (the ROM this run maps)
+pub fn prune_cache(
+    cache_dir: &Path,
+    keep: Option<&Path>,
+    paths: &[&Path],
+    max_mb: u64,
+) -> Result<Vec<Pruned>> {
+    let budget = max_mb.saturating_mul(1024 * 1024);
+    let entries = cache_entries(cache_dir)?;
+    let mut used = usage_bytes(paths) + entries.iter().map(|(_, bytes)| bytes).sum::<u64>();
+
+    let mut pruned = Vec::new();
+    for (path, bytes) in entries {
+        if used < budget {
+            break;
+        }
+        if Some(path.as_path()) == keep {
+            continue;
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        used -= bytes;
+        pruned.push(Pruned { path, bytes });
+    }
+    Ok(pruned)
+}
+
+/// Refuse to start if `paths` and the ROMs in `cache_dir` already use
+/// `max_mb` or more
+pub fn check_budget(paths: &[&Path], cache_dir: Option<&Path>, max_mb: u64) -> Result<()> {
+    let cached = cache_dir.map(cache_bytes).transpose()?.unwrap_or(0);
+    let used = usage_bytes(paths) + cached;
+    let budget = max_mb.saturating_mul(1024 * 1024);
+    if used >= budget {
+        bail!(
+            "Output files and cached ROMs already use {} MiB of the {} MiB disk budget; \
+             remove or archive them before starting another run",
+            used / (1024 * 1024),
+            max_mb
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let dir = std::env::temp_dir().join(format!("night-cloud-disk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("solutions.sqlite");
+        let events = dir.join("events.ndjson");
+        std::fs::write(&db, vec![0; 600 * 1024]).unwrap();
+        std::fs::write(dir.join("solutions.sqlite-wal"), vec![0; 200 * 1024]).unwrap();
+        std::fs::write(&events, vec![0; 300 * 1024]).unwrap();
+
+        let missing = dir.join("missing.bin");
+        let paths = [db.as_path(), events.as_path(), missing.as_path()];
+        assert_eq!(usage_bytes(&paths), 1100 * 1024);
+        assert!(check_budget(&paths, None, 2).is_ok());
+        assert!(check_budget(&paths, None, 1).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_oldest_cached_roms_are_pruned() {
+        let dir =
+            std::env::temp_dir().join(format!("night-cloud-disk-cache-{}", std::process::id()));
+        let cache = dir.join("cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        let events = dir.join("events.ndjson");
+        std::fs::write(&events, vec![0; 300 * 1024]).unwrap();
+
+        let rom = |name: &str, age_secs: u64| {
+            let path = cache.join(name);
+            std::fs::write(&path, vec![0; 400 * 1024]).unwrap();
+            let modified = SystemTime::now() - std::time::Duration::from_secs(age_secs);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+            path
+        };
+        let oldest = rom("a.rom", 300);
+        let current = rom("b.rom", 200);
+        let newest = rom("c.rom", 100);
+        // Not a ROM, so neither counted nor pruned
+        std::fs::write(cache.join("c.rom.tmp.1"), vec![0; 400 * 1024]).unwrap();
+
+        let paths = [events.as_path()];
+        assert_eq!(cache_bytes(&cache).unwrap(), 1200 * 1024);
+        assert!(check_budget(&paths, Some(&cache), 1).is_err());
+
+        // 1500 KiB in use: the oldest goes, and the one in use is spared
+        // even though it is older than the newest
+        let pruned = prune_cache(&cache, Some(&current), &paths, 1).unwrap();
+        assert_eq!(
+            pruned,
+            [
+                Pruned {
+                    path: oldest.clone(),
+                    bytes: 400 * 1024
+                },
+                Pruned {
+                    path: newest.clone(),
+                    bytes: 400 * 1024
+                },
+            ]
+        );
+        assert!(!oldest.exists() && !newest.exists() && current.exists());
+        assert!(check_budget(&paths, Some(&cache), 1).is_ok());
+
+        // Nothing to do once under the budget
+        assert!(prune_cache(&cache, None, &paths, 1).unwrap().is_empty());
+        assert!(prune_cache(&dir.join("missing"), None, &paths, 1)
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
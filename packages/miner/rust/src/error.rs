@@ -0,0 +1,39 @@
+/*!
+# Errors
+
+Failure modes of the mining library, so callers can match on the kind of
+error rather than on its message. The binary turns them into `anyhow`
+errors like any other.
+
+Running out of attempts, time or deadline, and being interrupted, are not
+errors: the search returns normally and says why it stopped in the
+outcome's `termination`.
+*/
+
+use std::io;
+
+use thiserror::Error;
+
+/// Error returned by the mining and hashing functions
+#[derive(Debug, Error)]
+pub enum MinerError {
+    /// A field that must be hex isn't
+    #[error("{field} '{value}' is not valid hex")]
+    InvalidHex { field: &'static str, value: String },
+    /// A difficulty that is hex but can't be mined against, or a bad
+    /// bit count or byte threshold list
+    #[error("{0}")]
+    InvalidDifficulty(String),
+    /// The challenge fields add up to more than `max_preimage_len` bytes
+    #[error("Preimage would be {len} bytes, over the {max}-byte limit; check the challenge fields")]
+    PreimageTooLong { len: usize, max: usize },
+    /// ROM or hash parameters ashmaize can't use, or a bad nonce partition
+    #[error("{0}")]
+    InvalidParams(String),
+    /// The ROM could not be produced, e.g. its bytes could not be read or,
+    /// with [`io::ErrorKind::OutOfMemory`], not allocated
+    #[error("Failed to load the ROM: {0}")]
+    RomGenerationFailed(#[source] io::Error),
+}
+
+pub type Result<T, E = MinerError> = std::result::Result<T, E>;
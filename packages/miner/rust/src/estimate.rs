@@ -0,0 +1,128 @@
+/*!
+# Time-to-solution estimates
+
+A random hash meets a hex mask digit with probability 2^(set bits)/16, so
+the expected number of attempts is the inverse of the product over the
+mask, and a hashrate turns that into a wall-clock estimate.
+*/
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// Counts above this are shown as a bound rather than a number
+const MAX_SHOWN_HASHES: f64 = 1e15;
+/// Durations above this many years are shown as a bound
+const MAX_SHOWN_YEARS: f64 = 1000.0;
+
+/// Expected work for a difficulty, and the time it takes at `hashrate`
+#[derive(Debug, Clone, Serialize)]
+pub struct Estimate {
+    /// `null` when no hash can meet the difficulty
+    #[serde(serialize_with = "finite_or_null")]
+    pub expected_hashes: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hashrate: Option<f64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "some_finite_or_null"
+    )]
+    pub expected_secs: Option<f64>,
+}
+
+impl Estimate {
+    /// Estimate for hashes that succeed with probability `probability`
+    pub fn new(probability: f64, hashrate: Option<f64>) -> Self {
+        let expected_hashes = 1.0 / probability;
+        Self {
+            expected_hashes,
+            hashrate,
+            expected_secs: hashrate.filter(|&h| h > 0.0).map(|h| expected_hashes / h),
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.expected_hashes.is_finite() {
+            return write!(f, "no hash can meet the difficulty");
+        }
+        write!(f, "expected {} hashes", format_count(self.expected_hashes))?;
+        if let (Some(secs), Some(hashrate)) = (self.expected_secs, self.hashrate) {
+            write!(f, ", {} at {:.1} H/s", format_duration(secs), hashrate)?;
+        }
+        Ok(())
+    }
+}
+
+/// `1234` as `1.2K` and so on, capped at [`MAX_SHOWN_HASHES`]
+fn format_count(count: f64) -> String {
+    if count > MAX_SHOWN_HASHES {
+        return format!("more than {:e}", MAX_SHOWN_HASHES);
+    }
+    let units = [(1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "K")];
+    match units.iter().find(|(scale, _)| count >= *scale) {
+        Some((scale, unit)) => format!("~{:.1}{}", count / scale, unit),
+        None => format!("~{:.0}", count),
+    }
+}
+
+/// Seconds in the largest sensible unit, capped at [`MAX_SHOWN_YEARS`]
+fn format_duration(secs: f64) -> String {
+    const YEAR: f64 = 365.25 * 86400.0;
+    if !secs.is_finite() || secs > MAX_SHOWN_YEARS * YEAR {
+        return format!("more than {} years", MAX_SHOWN_YEARS);
+    }
+    let units = [
+        (YEAR, "years"),
+        (86400.0, "days"),
+        (3600.0, "hours"),
+        (60.0, "minutes"),
+    ];
+    match units.iter().find(|(scale, _)| secs >= 2.0 * scale) {
+        Some((scale, unit)) => format!("~{:.1} {}", secs / scale, unit),
+        None => format!("~{:.1} seconds", secs),
+    }
+}
+
+fn finite_or_null<S: serde::Serializer>(value: &f64, s: S) -> Result<S::Ok, S::Error> {
+    match value.is_finite() {
+        true => s.serialize_f64(*value),
+        false => s.serialize_none(),
+    }
+}
+
+fn some_finite_or_null<S: serde::Serializer>(value: &Option<f64>, s: S) -> Result<S::Ok, S::Error> {
+    finite_or_null(&value.unwrap_or(f64::INFINITY), s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_is_capped() {
+        let estimate = Estimate::new(1.0 / 4096.0, Some(1000.0));
+        assert_eq!(
+            estimate.to_string(),
+            "expected ~4.1K hashes, ~4.1 seconds at 1000.0 H/s"
+        );
+
+        let estimate = Estimate::new(1.0 / 1048576.0, Some(750.0));
+        assert_eq!(
+            estimate.to_string(),
+            "expected ~1.0M hashes, ~23.3 minutes at 750.0 H/s"
+        );
+
+        let estimate = Estimate::new(2f64.powi(-512), Some(750.0));
+        assert_eq!(
+            estimate.to_string(),
+            "expected more than 1e15 hashes, more than 1000 years at 750.0 H/s"
+        );
+
+        let estimate = Estimate::new(0.0, None);
+        assert_eq!(estimate.to_string(), "no hash can meet the difficulty");
+        let json = serde_json::to_value(&estimate).unwrap();
+        assert!(json["expected_hashes"].is_null());
+    }
+}
@@ -0,0 +1,335 @@
+/*!
+# Event log
+
+Newline-delimited JSON events for long runs: a `start` event, a `prune`
+event for each cached ROM deleted to stay within the disk budget, a
+`progress` event every interval while hashing, and a `finish` event. Paths ending in
+`.gz` (or `.zst`, with the `zstd` feature) are compressed on the fly. The
+compressor is finalized when the log is finished or dropped, so an early
+error return still leaves a readable file.
+
+Events are flushed as they are written unless a flush interval is given:
+then they collect in the write buffer and reach the file once the interval
+has passed since the last flush or the buffer fills, saving a write (and,
+compressed, a sync flush of the encoder) per event. Finishing or dropping
+the log flushes whatever is left.
+*/
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::output::tagged;
+
+/// One line of the event log
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Start {
+        challenge_id: &'a str,
+        difficulty: &'a str,
+    },
+    Prune {
+        path: &'a Path,
+        bytes: u64,
+    },
+    Progress {
+        attempts: u64,
+        hashrate: f64,
+    },
+    Finish {
+        success: bool,
+        attempts: u64,
+    },
+}
+
+/// Event with its timestamp
+#[derive(Debug, Serialize)]
+struct Stamped<'a> {
+    ts: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Output file, compressed according to its extension
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn create(path: &Path) -> Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str());
+        if extension == Some("zst") && cfg!(not(feature = "zstd")) {
+            anyhow::bail!("{} needs a build with the zstd feature", path.display());
+        }
+
+        let file = BufWriter::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+        );
+        Ok(match extension {
+            Some("gz") => Sink::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            #[cfg(feature = "zstd")]
+            Some("zst") => Sink::Zstd(zstd::Encoder::new(file, 0)?),
+            _ => Sink::Plain(file),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(w) => w,
+            Sink::Gzip(w) => w,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w,
+        }
+    }
+
+    /// Write any compressed trailer and flush to disk
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Sink::Plain(w) => w,
+            Sink::Gzip(w) => w.finish()?,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w.finish()?,
+        };
+        file.flush()
+    }
+}
+
+/// Open sink and when it was last flushed
+struct Buffered {
+    sink: Sink,
+    flush_every: Duration,
+    flushed_at: Instant,
+}
+
+/// Open event log; `None` inside once finished
+type Shared = Arc<Mutex<Option<Buffered>>>;
+
+/// Event log file, with an optional background progress reporter
+pub struct EventLog {
+    sink: Shared,
+    job_id: Option<String>,
+    stop: Arc<AtomicBool>,
+    progress: Option<JoinHandle<()>>,
+}
+
+impl EventLog {
+    /// Create the log at `path`; `job_id` is echoed in every event, which
+    /// is flushed to the file at most every `flush_every` (zero: at once)
+    pub fn create(path: &Path, job_id: Option<String>, flush_every: Duration) -> Result<Self> {
+        let sink = Buffered {
+            sink: Sink::create(path)?,
+            flush_every,
+            flushed_at: Instant::now(),
+        };
+        Ok(Self {
+            sink: Arc::new(Mutex::new(Some(sink))),
+            job_id,
+            stop: Arc::new(AtomicBool::new(false)),
+            progress: None,
+        })
+    }
+
+    /// Append one event
+    pub fn emit(&self, event: &Event) -> Result<()> {
+        write_event(&self.sink, self.job_id.as_deref(), event).context("Failed to write event")
+    }
+
+    /// Emit a progress event with the value of `attempts` every `interval`
+    /// until the log is finished
+    pub fn watch(&mut self, attempts: Arc<AtomicU64>, interval: Duration) {
+        let sink = self.sink.clone();
+        let job_id = self.job_id.clone();
+        let stop = self.stop.clone();
+
+        self.progress = Some(thread::spawn(move || {
+            let started = Instant::now();
+            loop {
+                thread::park_timeout(interval);
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let attempts = attempts.load(Ordering::Relaxed);
+                let secs = started.elapsed().as_secs_f64();
+                let event = Event::Progress {
+                    attempts,
+                    hashrate: if secs > 0.0 {
+                        attempts as f64 / secs
+                    } else {
+                        0.0
+                    },
+                };
+                // Best-effort: a full disk must not stop mining
+                if let Err(e) = write_event(&sink, job_id.as_deref(), &event) {
+                    warn!("Failed to write progress event: {}", e);
+                }
+            }
+        }));
+    }
+
+    /// Stop progress reporting and finalize the file
+    pub fn finish(mut self) -> Result<()> {
+        self.close().context("Failed to finalize event log")
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.progress.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+        match lock(&self.sink).take() {
+            Some(buffered) => buffered.sink.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("Failed to finalize event log: {}", e);
+        }
+    }
+}
+
+fn lock(sink: &Shared) -> std::sync::MutexGuard<'_, Option<Buffered>> {
+    // A panicked writer leaves the sink usable; keep logging
+    sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write_event(sink: &Shared, job_id: Option<&str>, event: &Event) -> io::Result<()> {
+    let stamped = Stamped {
+        ts: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        event,
+    };
+    let line = serde_json::to_string(&tagged(job_id, &stamped))?;
+
+    let mut guard = lock(sink);
+    let Some(buffered) = guard.as_mut() else {
+        return Ok(());
+    };
+    let writer = buffered.sink.writer();
+    writeln!(writer, "{}", line)?;
+    if buffered.flushed_at.elapsed() >= buffered.flush_every {
+        writer.flush()?;
+        buffered.flushed_at = Instant::now();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn read_events(path: &Path) -> Vec<serde_json::Value> {
+        let raw = std::fs::read(path).unwrap();
+        let mut text = String::new();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => {
+                flate2::read::GzDecoder::new(&raw[..])
+                    .read_to_string(&mut text)
+                    .unwrap();
+            }
+            #[cfg(feature = "zstd")]
+            Some("zst") => text = String::from_utf8(zstd::decode_all(&raw[..]).unwrap()).unwrap(),
+            _ => text = String::from_utf8(raw).unwrap(),
+        }
+        text.lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    fn write_log(path: &Path) {
+        let mut log = EventLog::create(path, Some("job-7".to_string()), Duration::ZERO).unwrap();
+        log.emit(&Event::Start {
+            challenge_id: "**D07C10",
+            difficulty: "000FFFFF",
+        })
+        .unwrap();
+        log.watch(Arc::new(AtomicU64::new(10)), Duration::from_millis(5));
+        thread::sleep(Duration::from_millis(50));
+        log.emit(&Event::Finish {
+            success: false,
+            attempts: 10,
+        })
+        .unwrap();
+        log.finish().unwrap();
+    }
+
+    #[test]
+    fn test_compressed_logs_are_complete() {
+        let mut extensions = vec!["ndjson", "gz"];
+        if cfg!(feature = "zstd") {
+            extensions.push("zst");
+        }
+
+        for extension in extensions {
+            let path = std::env::temp_dir().join(format!(
+                "night-cloud-events-{}.{}",
+                std::process::id(),
+                extension
+            ));
+            write_log(&path);
+            let events = read_events(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(events[0]["event"], "start", "{}", extension);
+            assert_eq!(events[0]["job_id"], "job-7");
+            assert!(events[0]["ts"].as_str().unwrap().ends_with('Z'));
+            assert!(events.len() > 2, "{}: no progress events", extension);
+            assert!(events[1..events.len() - 1]
+                .iter()
+                .all(|e| e["event"] == "progress" && e["attempts"] == 10));
+            assert_eq!(events.last().unwrap()["event"], "finish", "{}", extension);
+        }
+    }
+
+    #[test]
+    fn test_buffered_events_are_flushed_on_finish_and_drop() {
+        let path = std::env::temp_dir().join(format!(
+            "night-cloud-events-buffered-{}.ndjson",
+            std::process::id()
+        ));
+        let start = Event::Start {
+            challenge_id: "**D07C10",
+            difficulty: "000FFFFF",
+        };
+
+        // Within the interval the event stays in the buffer
+        let log = EventLog::create(&path, None, Duration::from_secs(3600)).unwrap();
+        log.emit(&start).unwrap();
+        assert!(read_events(&path).is_empty());
+        log.finish().unwrap();
+        assert_eq!(read_events(&path).len(), 1);
+
+        let log = EventLog::create(&path, None, Duration::from_secs(3600)).unwrap();
+        log.emit(&start).unwrap();
+        drop(log);
+        assert_eq!(read_events(&path).len(), 1);
+
+        // Once it has passed the next event flushes everything
+        let log = EventLog::create(&path, None, Duration::from_millis(20)).unwrap();
+        log.emit(&start).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        log.emit(&start).unwrap();
+        assert_eq!(read_events(&path).len(), 2);
+        drop(log);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
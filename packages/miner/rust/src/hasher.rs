@@ -4,38 +4,361 @@
 This wraps the official ashmaize crate for use in our miner.
 */
 
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::time::Instant;
+
 use ashmaize::{hash as hash_internal, Rom, RomGenerationType};
+use cryptoxide::hashing::sha256;
+use tracing::info;
+
+use crate::error::{MinerError, Result};
+
+pub const NB_LOOPS: u32 = 8;
+pub const NB_INSTRS: u32 = 256;
+pub const PRE_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
+pub const ROM_SIZE: usize = 1_073_741_824; // 1 GiB
+pub const MIXING_NUMBERS: usize = 4;
+
+/// Pre-memory size of the small ROM used by tests and fixtures
+pub const SMALL_PRE_SIZE: usize = 16 * 1024;
+/// Size of the small ROM used by tests and fixtures
+pub const SMALL_ROM_SIZE: usize = 256 * 1024;
+
+/// Size of an AshMaize digest in bytes
+pub const DIGEST_SIZE: usize = 64;
+
+/// ROM generation and hash parameters; the default is the spec's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub pre_size: usize,
+    pub rom_size: usize,
+    pub mixing_numbers: usize,
+    pub nb_loops: u32,
+    pub nb_instrs: u32,
+    /// Threads generating the ROM; the bytes are the same for any count
+    pub rom_threads: usize,
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        Self {
+            pre_size: PRE_SIZE,
+            rom_size: ROM_SIZE,
+            mixing_numbers: MIXING_NUMBERS,
+            nb_loops: NB_LOOPS,
+            nb_instrs: NB_INSTRS,
+            rom_threads: 1,
+        }
+    }
+}
+
+impl HashParams {
+    /// The spec's parameters with a ROM of non-standard size
+    pub fn with_sizes(pre_size: usize, rom_size: usize) -> Self {
+        Self {
+            pre_size,
+            rom_size,
+            ..Self::default()
+        }
+    }
 
-const NB_LOOPS: u32 = 8;
-const NB_INSTRS: u32 = 256;
-const PRE_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
-const ROM_SIZE: usize = 1_073_741_824; // 1 GiB
-const MIXING_NUMBERS: usize = 4;
+    /// Whether hashes under these parameters are the network's; any other
+    /// ROM or hash parameters give digests no validator accepts
+    pub fn is_spec(&self) -> bool {
+        *self
+            == Self {
+                rom_threads: self.rom_threads,
+                ..Self::default()
+            }
+    }
+
+    /// Reject parameters the ashmaize crate would panic or misbehave on
+    pub fn validate(&self) -> Result<()> {
+        let problem = if self.pre_size < 64 || !self.pre_size.is_power_of_two() {
+            format!("--pre-size {} must be a power of two of at least 64", self.pre_size)
+        } else if self.rom_size < self.pre_size {
+            format!(
+                "--rom-size {} is smaller than --pre-size {}",
+                self.rom_size, self.pre_size
+            )
+        } else if !self.rom_size.is_multiple_of(64) {
+            format!("--rom-size {} must be a multiple of 64", self.rom_size)
+        } else if self.mixing_numbers == 0 {
+            "--mixing-numbers must be at least 1".to_string()
+        } else if self.nb_loops < 2 {
+            format!("--loops {} must be at least 2", self.nb_loops)
+        } else if self.nb_instrs < 256 {
+            format!("--instructions {} must be at least 256", self.nb_instrs)
+        } else {
+            return Ok(());
+        };
+        Err(MinerError::InvalidParams(problem))
+    }
+}
 
 /// AshMaize hasher - wrapper around official implementation
 pub struct AshMaizeHasher {
     rom: Rom,
+    nb_loops: u32,
+    nb_instrs: u32,
 }
 
 impl AshMaizeHasher {
     /// Create a new hasher with ROM initialized from no_pre_mine value
+    ///
+    /// Nothing else seeds the ROM: the other challenge fields, including
+    /// no_pre_mine_hour, only enter the preimage, so one hasher serves every
+    /// challenge with this no_pre_mine.
+    ///
+    /// # Panics
+    ///
+    /// If the ROM can't be allocated; see [`try_new`](Self::try_new).
     pub fn new(no_pre_mine_hex: &str) -> Self {
-        let seed = no_pre_mine_hex.as_bytes();
-        
-        let rom = Rom::new(
+        Self::with_params(no_pre_mine_hex, &HashParams::default())
+    }
+
+    /// Like [`new`](Self::new), but failing with
+    /// [`MinerError::RomGenerationFailed`] rather than panicking when the
+    /// ROM can't be allocated
+    pub fn try_new(no_pre_mine_hex: &str) -> Result<Self> {
+        Self::try_with_params(no_pre_mine_hex, &HashParams::default())
+    }
+
+    /// Hasher with non-spec parameters, which must have been validated
+    ///
+    /// # Panics
+    ///
+    /// If the ROM can't be allocated; see
+    /// [`try_with_params`](Self::try_with_params).
+    pub fn with_params(no_pre_mine_hex: &str, params: &HashParams) -> Self {
+        Self::from_seed_bytes(no_pre_mine_hex.as_bytes(), params)
+    }
+
+    /// Hasher over a 256 KiB ROM, so tests don't allocate 1 GiB
+    #[cfg(test)]
+    pub fn small(no_pre_mine_hex: &str) -> Self {
+        Self::with_sizes(no_pre_mine_hex, SMALL_PRE_SIZE, SMALL_ROM_SIZE)
+    }
+
+    /// Hasher over a ROM of non-standard size; `pre_size` must be a power of two
+    ///
+    /// Hashes differ from the real ones, so this is for tests and fixtures only.
+    pub fn with_sizes(no_pre_mine_hex: &str, pre_size: usize, rom_size: usize) -> Self {
+        Self::with_params(no_pre_mine_hex, &HashParams::with_sizes(pre_size, rom_size))
+    }
+
+    /// Hasher whose ROM is keyed by arbitrary seed bytes rather than the
+    /// no_pre_mine string, e.g. the hex-decoded value
+    ///
+    /// Generation is logged as it goes, since a full-size ROM takes a few
+    /// seconds: once before, every 10% and once when the ROM is ready.
+    ///
+    /// # Panics
+    ///
+    /// If the ROM can't be allocated; see
+    /// [`try_from_seed_bytes`](Self::try_from_seed_bytes).
+    pub fn from_seed_bytes(seed: &[u8], params: &HashParams) -> Self {
+        Self::try_from_seed_bytes(seed, params).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [`with_params`](Self::with_params), but failing with
+    /// [`MinerError::RomGenerationFailed`] rather than panicking when the
+    /// ROM can't be allocated
+    pub fn try_with_params(no_pre_mine_hex: &str, params: &HashParams) -> Result<Self> {
+        Self::try_from_seed_bytes(no_pre_mine_hex.as_bytes(), params)
+    }
+
+    /// Like [`from_seed_bytes`](Self::from_seed_bytes), but failing with
+    /// [`MinerError::RomGenerationFailed`] of kind
+    /// [`io::ErrorKind::OutOfMemory`] when the ROM can't be allocated
+    pub fn try_from_seed_bytes(seed: &[u8], params: &HashParams) -> Result<Self> {
+        info!("Generating {} ROM, this may take a few seconds...", format_size(params.rom_size));
+        let started = Instant::now();
+        let logged = Cell::new(0);
+        let progress = |done: usize, total: usize| {
+            let percent = done * 100 / total.max(1);
+            if percent / 10 > logged.get() && done < total {
+                logged.set(percent / 10);
+                info!("Generating ROM: {}%", percent);
+            }
+        };
+        let rom = Rom::try_new_with_progress(
             seed,
             RomGenerationType::TwoStep {
-                pre_size: PRE_SIZE,
-                mixing_numbers: MIXING_NUMBERS,
+                pre_size: params.pre_size,
+                mixing_numbers: params.mixing_numbers,
             },
-            ROM_SIZE,
-        );
-        
-        Self { rom }
+            params.rom_size,
+            params.rom_threads,
+            &progress,
+        )
+        .map_err(|e| {
+            MinerError::RomGenerationFailed(io::Error::new(io::ErrorKind::OutOfMemory, e))
+        })?;
+        info!("ROM ready in {:.1}s", started.elapsed().as_secs_f64());
+
+        Ok(Self::from_rom(rom, params))
+    }
+
+    /// Create a hasher over `params.rom_size` ROM bytes read from `reader`,
+    /// e.g. a ROM generated earlier and stored elsewhere
+    ///
+    /// No generation happens: the bytes are trusted to be a ROM. The ROM
+    /// size must be a non-zero multiple of 64.
+    pub fn from_reader(mut reader: impl Read, params: &HashParams) -> Result<Self> {
+        let rom_size = params.rom_size;
+        if rom_size == 0 || !rom_size.is_multiple_of(64) {
+            return Err(MinerError::InvalidParams(format!(
+                "ROM size {} is not a non-zero multiple of 64",
+                rom_size
+            )));
+        }
+
+        let mut data = vec![0; rom_size];
+        reader
+            .read_exact(&mut data)
+            .map_err(MinerError::RomGenerationFailed)?;
+
+        Ok(Self::from_rom(Rom::from_data(data), params))
+    }
+
+    /// Hasher over an already built ROM, e.g. one mapped from the cache;
+    /// only the hash parameters are taken from `params`
+    pub fn from_rom(rom: Rom, params: &HashParams) -> Self {
+        Self {
+            rom,
+            nb_loops: params.nb_loops,
+            nb_instrs: params.nb_instrs,
+        }
+    }
+
+    /// Digest of the ROM, which is all a hash needs besides the ROM bytes
+    pub fn rom_digest(&self) -> &[u8; 64] {
+        self.rom.digest()
+    }
+
+    /// Raw ROM bytes, for diagnostics
+    pub fn rom_bytes(&self) -> &[u8] {
+        self.rom.as_bytes()
+    }
+
+    /// Whether the ROM is mapped (see [`from_rom`](Self::from_rom)) rather
+    /// than held in this process's own memory
+    pub fn rom_is_mapped(&self) -> bool {
+        self.rom.is_external()
     }
 
     /// Hash data using AshMaize algorithm
     pub fn hash(&self, preimage: &[u8]) -> Vec<u8> {
-        hash_internal(preimage, &self.rom, NB_LOOPS, NB_INSTRS).to_vec()
+        let mut digest = [0; DIGEST_SIZE];
+        self.hash_into(preimage, &mut digest);
+        digest.to_vec()
+    }
+
+    /// Like [`hash`](Self::hash), into a buffer the caller reuses, so the
+    /// mining loop doesn't allocate per attempt
+    pub fn hash_into(&self, preimage: &[u8], digest: &mut [u8; DIGEST_SIZE]) {
+        *digest = hash_internal(preimage, &self.rom, self.nb_loops, self.nb_instrs);
+    }
+}
+
+/// Short, stable identifier for a ROM seed (first 8 bytes of its SHA-256)
+pub fn seed_fingerprint(no_pre_mine_hex: &str) -> String {
+    hex::encode(&sha256(no_pre_mine_hex.as_bytes())[..8])
+}
+
+/// `bytes` in the largest binary unit it is a whole number of
+fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while unit + 1 < UNITS.len() && size >= 1024 && size.is_multiple_of(1024) {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn test_from_reader_round_trip() {
+        let generated = AshMaizeHasher::small("fd651ac2");
+        let bytes = generated.rom_bytes().to_vec();
+
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE);
+        let loaded = AshMaizeHasher::from_reader(Cursor::new(&bytes), &params).unwrap();
+        assert_eq!(loaded.rom_bytes(), generated.rom_bytes());
+        let mut digest = [0; DIGEST_SIZE];
+        for preimage in [&b"preimage"[..], b"", &[0xff; 300]] {
+            assert_eq!(loaded.hash(preimage), generated.hash(preimage));
+            loaded.hash_into(preimage, &mut digest);
+            assert_eq!(&digest[..], generated.hash(preimage));
+        }
+
+        let short = AshMaizeHasher::from_reader(Cursor::new(&bytes[..1024]), &params);
+        match short {
+            Err(MinerError::RomGenerationFailed(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            _ => panic!("expected a read error"),
+        }
+        let odd = HashParams::with_sizes(SMALL_PRE_SIZE, 100);
+        assert!(AshMaizeHasher::from_reader(Cursor::new(&bytes), &odd).is_err());
+    }
+
+    #[test]
+    fn test_params_validation() {
+        assert!(HashParams::default().validate().is_ok());
+        assert!(HashParams { rom_threads: 8, ..HashParams::default() }.is_spec());
+        assert!(!HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE).is_spec());
+        assert!(HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE).validate().is_ok());
+
+        let small_rom = HashParams::with_sizes(SMALL_ROM_SIZE, SMALL_PRE_SIZE).validate();
+        assert!(small_rom.unwrap_err().to_string().contains("smaller than --pre-size"));
+        assert!(HashParams::with_sizes(3000, SMALL_ROM_SIZE).validate().is_err());
+        assert!(HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE + 1).validate().is_err());
+        for params in [
+            HashParams { mixing_numbers: 0, ..HashParams::default() },
+            HashParams { nb_loops: 1, ..HashParams::default() },
+            HashParams { nb_instrs: 255, ..HashParams::default() },
+        ] {
+            assert!(params.validate().is_err(), "{:?}", params);
+        }
+
+        // The hash parameters change every digest
+        let spec = AshMaizeHasher::small("fd651ac2");
+        let params = HashParams {
+            nb_loops: 3,
+            ..HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE)
+        };
+        let other = AshMaizeHasher::with_params("fd651ac2", &params);
+        assert_eq!(other.rom_bytes(), spec.rom_bytes());
+        assert_ne!(other.hash(b"preimage"), spec.hash(b"preimage"));
+    }
+
+    #[test]
+    fn test_rom_threads_generate_the_same_rom() {
+        // Larger than the ROM generator's 1 MiB blocks, and not a whole
+        // number of them
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, 3 * 1024 * 1024 + 64);
+        let single = AshMaizeHasher::with_params("fd651ac2", &params);
+        for rom_threads in [2, 3] {
+            let params = HashParams { rom_threads, ..params };
+            let parallel = AshMaizeHasher::with_params("fd651ac2", &params);
+            assert!(parallel.rom_bytes() == single.rom_bytes());
+            assert_eq!(parallel.rom_digest(), single.rom_digest());
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(ROM_SIZE), "1 GiB");
+        assert_eq!(format_size(SMALL_ROM_SIZE), "256 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 + 64), "3145792 bytes");
     }
 }
@@ -0,0 +1,150 @@
+/*!
+# TCP heartbeat
+
+Periodically sends a one-line JSON status to a TCP endpoint so an external
+monitor can tell the miner is alive. Best-effort: connection failures are
+logged and retried on the next tick, never returned to the miner.
+*/
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::warn;
+
+/// Status line sent on every tick
+#[derive(Debug, Serialize)]
+struct Beat<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<&'a str>,
+    attempts: u64,
+    hashrate: f64,
+    elapsed_ms: u64,
+}
+
+/// Background thread sending heartbeats until dropped
+pub struct Heartbeat {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    /// Start sending the value of `attempts` to `addr` every `interval`
+    ///
+    /// `job_id` is echoed in every status line.
+    pub fn spawn(
+        addr: String,
+        interval: Duration,
+        attempts: Arc<AtomicU64>,
+        job_id: Option<String>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(&addr, interval, &attempts, job_id.as_deref(), &stop))
+        };
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    addr: &str,
+    interval: Duration,
+    attempts: &AtomicU64,
+    job_id: Option<&str>,
+    stop: &AtomicBool,
+) {
+    let started = Instant::now();
+    let mut conn: Option<TcpStream> = None;
+
+    loop {
+        thread::park_timeout(interval);
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let elapsed = started.elapsed();
+        let attempts = attempts.load(Ordering::Relaxed);
+        let secs = elapsed.as_secs_f64();
+        let beat = Beat {
+            job_id,
+            attempts,
+            hashrate: if secs > 0.0 {
+                attempts as f64 / secs
+            } else {
+                0.0
+            },
+            elapsed_ms: elapsed.as_millis() as u64,
+        };
+
+        if conn.is_none() {
+            match TcpStream::connect(addr) {
+                Ok(stream) => {
+                    let _ = stream.set_write_timeout(Some(interval));
+                    conn = Some(stream);
+                }
+                Err(e) => {
+                    warn!("Heartbeat: cannot connect to {}: {}", addr, e);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(stream) = &mut conn {
+            let line = serde_json::to_string(&beat).expect("heartbeat serializes");
+            if let Err(e) = writeln!(stream, "{}", line) {
+                warn!("Heartbeat: send to {} failed, reconnecting: {}", addr, e);
+                conn = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_sends_status_lines() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let attempts = Arc::new(AtomicU64::new(1234));
+
+        let heartbeat = Heartbeat::spawn(
+            addr,
+            Duration::from_millis(20),
+            attempts,
+            Some("job-7".to_string()),
+        );
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        drop(heartbeat);
+
+        let beat: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(beat["job_id"], "job-7");
+        assert_eq!(beat["attempts"], 1234);
+        assert!(beat["hashrate"].as_f64().unwrap() > 0.0);
+        assert!(beat["elapsed_ms"].is_u64());
+    }
+}
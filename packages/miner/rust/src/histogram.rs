@@ -0,0 +1,109 @@
+/*!
+# Hash prefix histogram
+
+Hashes the challenge's preimages for successive nonces and counts the first
+byte of every digest, so a skewed output distribution, e.g. from a badly
+seeded ROM, shows up before anyone spends a day mining against it. A
+chi-square statistic summarises how far the counts are from uniform.
+Observational only: nothing found here is reported as a solution.
+*/
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::hasher::{AshMaizeHasher, DIGEST_SIZE};
+use crate::miner::{Challenge, NonceFormat, PreimageBuffer};
+
+/// Number of buckets: one per value of the leading byte
+const BUCKETS: usize = 256;
+
+/// How many standard deviations the chi-square statistic may stray from its
+/// mean before the counts stop looking uniform
+const TOLERANCE_SIGMAS: f64 = 4.0;
+
+/// Distribution of leading digest bytes over a sample
+#[derive(Debug, Serialize)]
+pub struct HistogramReport {
+    pub samples: u64,
+    /// Digests whose first byte is `i`, for each `i`
+    pub first_byte: Vec<u64>,
+    /// Count every bucket would have under a uniform hash
+    pub expected_per_bucket: f64,
+    pub chi_square: f64,
+    pub degrees_of_freedom: usize,
+    /// Whether `chi_square` is within a few standard deviations of its
+    /// mean; only meaningful with at least about 5 samples per bucket
+    pub looks_uniform: bool,
+    pub elapsed_ms: u128,
+}
+
+/// Hash `challenge` for the nonces `0..samples` in `format` and bucket the
+/// first byte of each digest
+pub fn sample(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    format: NonceFormat,
+    samples: u64,
+) -> HistogramReport {
+    let started = Instant::now();
+    let mut preimage = PreimageBuffer::new(challenge, format);
+    let mut digest = [0; DIGEST_SIZE];
+    let mut first_byte = vec![0u64; BUCKETS];
+    for nonce in 0..samples {
+        preimage.set_nonce(nonce);
+        hasher.hash_into(preimage.as_str().as_bytes(), &mut digest);
+        first_byte[digest[0] as usize] += 1;
+    }
+    report(first_byte, samples, started.elapsed().as_millis())
+}
+
+fn report(first_byte: Vec<u64>, samples: u64, elapsed_ms: u128) -> HistogramReport {
+    let expected = samples as f64 / BUCKETS as f64;
+    let chi_square = if expected > 0.0 {
+        first_byte
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    } else {
+        0.0
+    };
+    let degrees_of_freedom = BUCKETS - 1;
+    // Chi-square with k degrees of freedom has mean k and variance 2k
+    let sigma = (2.0 * degrees_of_freedom as f64).sqrt();
+    HistogramReport {
+        samples,
+        first_byte,
+        expected_per_bucket: expected,
+        chi_square,
+        degrees_of_freedom,
+        looks_uniform: (chi_square - degrees_of_freedom as f64).abs() <= TOLERANCE_SIGMAS * sigma,
+        elapsed_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_and_skewed_counts() {
+        let flat = report(vec![10; BUCKETS], 10 * BUCKETS as u64, 0);
+        assert_eq!(flat.expected_per_bucket, 10.0);
+        assert_eq!(flat.chi_square, 0.0);
+        // Too perfect is as suspicious as too skewed
+        assert!(!flat.looks_uniform);
+
+        let mut skewed = vec![10; BUCKETS];
+        skewed[0] += 200;
+        skewed[1] -= 10;
+        let skewed = report(skewed, 10 * BUCKETS as u64 + 190, 0);
+        assert!(!skewed.looks_uniform);
+
+        let challenge = crate::vectors::spec_challenge();
+        let hasher = AshMaizeHasher::small(&challenge.no_pre_mine);
+        let sampled = sample(&hasher, &challenge, NonceFormat::default(), 512);
+        assert_eq!(sampled.first_byte.iter().sum::<u64>(), 512);
+        assert_eq!(sampled.degrees_of_freedom, 255);
+    }
+}
@@ -0,0 +1,76 @@
+/*!
+# Night Cloud Miner
+
+The mining library behind the `night-cloud` binary, for services that
+want to mine in-process rather than run the binary and parse its JSON.
+
+```no_run
+use night_cloud::{mine_solution, AshMaizeHasher, Challenge, MineOptions};
+
+let challenge = Challenge {
+    address: "addr1...".to_string(),
+    challenge_id: "**D07C10".to_string(),
+    difficulty: "000FFFFF".to_string(),
+    no_pre_mine: "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011".to_string(),
+    latest_submission: "2025-10-19T08:59:59.000Z".to_string(),
+    no_pre_mine_hour: "509681483".to_string(),
+};
+let hasher = AshMaizeHasher::try_new(&challenge.no_pre_mine)?;
+let outcome = mine_solution(&hasher, &challenge, &MineOptions::default())?;
+if let Some(solution) = outcome.solution {
+    println!("{} -> {}", solution.nonce, solution.hash);
+}
+# Ok::<(), anyhow::Error>(())
+```
+
+Preimages built with [`construct_preimage`] (or [`Challenge::preimage`])
+are byte-identical to the ones the binary mines.
+*/
+
+pub mod affinity;
+pub mod bench;
+pub mod challenge_url;
+pub mod checkpoint;
+pub mod collisions;
+pub mod compare;
+pub mod config;
+pub mod coordination;
+pub mod cpus;
+pub mod deadline;
+pub mod disk;
+pub mod error;
+pub mod estimate;
+pub mod events;
+pub mod hasher;
+pub mod heartbeat;
+pub mod histogram;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod miner;
+pub mod output;
+pub mod progress;
+pub mod rlimit;
+pub mod rom_bench;
+pub mod rom_cache;
+pub mod rpc;
+pub mod session;
+pub mod shard;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats_signal;
+pub mod status_line;
+#[cfg(feature = "syslog")]
+pub mod syslog_output;
+pub mod thread_rates;
+pub mod validate;
+pub mod vectors;
+pub mod verify;
+
+pub use error::MinerError;
+pub use hasher::{AshMaizeHasher, HashParams};
+pub use miner::{
+    construct_preimage, mine_solution, mine_solutions, BestHash, Challenge, MineOptions,
+    MineOutcome, MultiOutcome, Solution, TerminationReason,
+};
+pub use output::{MineFailure, MineResult};
@@ -1,85 +1,1946 @@
-use anyhow::Result;
-use clap::Parser;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser, Subcommand};
 use tracing::info;
+use tracing_subscriber::EnvFilter;
 
-mod miner;
-mod hasher;
+use night_cloud::hasher::{self, AshMaizeHasher};
+use night_cloud::miner::{self, Challenge, MineOptions};
+use night_cloud::output::{self, tagged, HashCase, MineResult, PreimageEncoding, RunStats};
+use night_cloud::{
+    affinity, bench, challenge_url, checkpoint, collisions, compare, config, coordination, cpus,
+    deadline, disk, estimate, events, heartbeat, histogram, memory, progress, rlimit, rom_bench,
+    rom_cache, rpc, session, shard, stats_signal, status_line, thread_rates, validate, vectors,
+    verify,
+};
+#[cfg(feature = "metrics")]
+use night_cloud::metrics;
+#[cfg(feature = "sqlite")]
+use night_cloud::sqlite;
+#[cfg(feature = "syslog")]
+use night_cloud::syslog_output;
 
 #[derive(Parser, Debug)]
 #[command(name = "night-cloud")]
 #[command(about = "Night Cloud Miner - Single address mining worker", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(after_help = "Exit status of a single-challenge run: 0 when a solution is found, \
+2 when --max-attempts, --max-duration or --auto-budget runs out without one or another \
+worker publishes one to --coordination-file, 3 when the ROM can't be allocated, 130 when \
+interrupted, 1 on other errors such as bad input or an unreadable --rom-file. Stream, \
+seeds-file and addresses-file runs exit 0 unless they fail. `verify` exits 2 when the hash \
+misses the difficulty.")]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Read flags from this JSON object, keyed like challenge stream lines
+    /// (address, max_attempts, ...); flags given here take precedence
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Log errors only, without the banner and challenge details
+    /// [default: info, or RUST_LOG when set]
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log debug details as well, including each mining thread's hashrate
+    /// every 10s (or every --progress-interval)
+    #[arg(long, short, global = true)]
+    verbose: bool,
+
     /// Cardano address to mine for
-    #[arg(long)]
-    address: String,
+    #[arg(long, required_unless_present_any = ["params_fifo", "daemon", "addresses_file"])]
+    address: Option<String>,
 
     /// Challenge ID
+    #[arg(long, required_unless_present_any = ["params_fifo", "daemon", "challenge_url"])]
+    challenge_id: Option<String>,
+
+    /// Difficulty (hex string)
+    #[arg(
+        long,
+        required_unless_present_any = ["params_fifo", "daemon", "difficulty_bits", "challenge_url"]
+    )]
+    difficulty: Option<String>,
+
+    /// Difficulty as a number of leading zero bits, mined and sent in the
+    /// preimage as the equivalent mask (12 is 000FFFFF)
+    #[arg(long, conflicts_with = "difficulty")]
+    difficulty_bits: Option<u32>,
+
+    /// No pre-mine value (hex string)
+    #[arg(
+        long,
+        required_unless_present_any = [
+            "params_fifo", "daemon", "seeds_file", "challenge_url", "no_pre_mine_file"
+        ]
+    )]
+    no_pre_mine: Option<String>,
+
+    /// Read the no_pre_mine value from this file instead, keeping it out of
+    /// process listings and shell history; surrounding whitespace is ignored
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["no_pre_mine", "seeds_file"])]
+    no_pre_mine_file: Option<PathBuf>,
+
+    /// Latest submission timestamp
+    #[arg(long, required_unless_present_any = ["params_fifo", "daemon", "challenge_url"])]
+    latest_submission: Option<String>,
+
+    /// No pre-mine hour
+    #[arg(long, required_unless_present_any = ["params_fifo", "daemon", "hours", "challenge_url"])]
+    no_pre_mine_hour: Option<String>,
+
+    /// Fetch challenge_id, difficulty, no_pre_mine, latest_submission and
+    /// no_pre_mine_hour from this URL (e.g. the API's /challenge) before
+    /// mining, retrying on 429 and 5xx; needs curl
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = [
+            "challenge_id", "difficulty", "difficulty_bits", "no_pre_mine", "no_pre_mine_file",
+            "latest_submission", "no_pre_mine_hour", "hours", "params_fifo", "daemon", "seeds_file"
+        ]
+    )]
+    challenge_url: Option<String>,
+
+    /// Seconds --challenge-url may take to connect before the attempt
+    /// counts as timed out and is retried
+    #[arg(long, value_name = "SECS", default_value = "5")]
+    http_connect_timeout: u64,
+
+    /// Seconds a whole --challenge-url request may take, connecting included
+    #[arg(long, value_name = "SECS", default_value = "30")]
+    http_request_timeout: u64,
+
+    /// no_pre_mine_hour values to sweep, as numbers and inclusive first-last
+    /// ranges separated by commas: mine the challenge for each in turn with
+    /// one shared ROM and print one JSON line per hour
+    #[arg(
+        long,
+        value_name = "LIST",
+        conflicts_with_all = [
+            "no_pre_mine_hour", "params_fifo", "daemon", "seeds_file", "addresses_file"
+        ]
+    )]
+    hours: Option<session::Hours>,
+
+    /// Maximum attempts before giving up; 0 means no limit, as --forever
+    #[arg(long, default_value = "10000000")]
+    max_attempts: u64,
+
+    /// Hash until a solution is found, Ctrl-C or --max-duration, however
+    /// many attempts it takes
+    #[arg(long, conflicts_with = "max_attempts")]
+    forever: bool,
+
+    /// Also give up once this much time has been spent hashing, e.g. 30s,
+    /// 5m or 2h; whichever of this and --max-attempts comes first ends the run
+    #[arg(long, value_parser = deadline::parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Stop this long before latest_submission instead of at it, e.g. 10s,
+    /// to leave time for submitting a solution
+    #[arg(long, value_parser = deadline::parse_duration)]
+    deadline_margin: Option<Duration>,
+
+    /// Keep mining after latest_submission has passed, e.g. for benchmarks
+    /// on an old challenge
+    #[arg(long, conflicts_with = "deadline_margin")]
+    ignore_deadline: bool,
+
+    /// Hash each candidate's preimage a second time and recheck the
+    /// difficulty before reporting it, mining on if either check fails
+    #[arg(long)]
+    verify_after: bool,
+
+    /// Seed the random nonce generator so runs with the same seed,
+    /// challenge and parameters try the same nonces; reproducible with
+    /// --threads 1, as threads race each other otherwise [default: OS entropy]
+    #[arg(long = "seed", value_name = "U64")]
+    rng_seed: Option<u64>,
+
+    /// This worker's index for partitioned mining: try only the nonces
+    /// congruent to it modulo --worker-count, in order, instead of random ones
+    #[arg(long, requires = "worker_count")]
+    worker_index: Option<u64>,
+
+    /// Number of workers the nonce space is partitioned between
+    #[arg(long, requires = "worker_index")]
+    worker_count: Option<u64>,
+
+    /// Width of generated nonces in bytes, each written as two hex digits;
+    /// partitioned nonces wider than 8 bytes are zero-extended
+    #[arg(long, value_name = "N", default_value = "8")]
+    nonce_bytes: usize,
+
+    /// Letter case of the hex digits of generated nonces
+    #[arg(long, value_enum, default_value_t = miner::NonceEncoding::HexLower)]
+    nonce_encoding: miner::NonceEncoding,
+
+    /// Save this worker's progress to this file while mining and resume
+    /// from it on the next run: the attempt counter with --worker-index, or
+    /// with --seed how many random nonces each thread drew, replayed on
+    /// resume; random nonces without --seed can't be resumed
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "addresses_file"]
+    )]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoint saves
+    #[arg(long, default_value = "60", requires = "checkpoint")]
+    checkpoint_interval: u64,
+
+    /// Mining threads sharing one ROM and the attempt budget, also used to
+    /// generate the ROM [default: the CPUs the cgroup quota allows, or else
+    /// the logical CPUs]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Size the default --threads (and the --auto-threads candidates) for
+    /// this many CPUs instead of detecting them, e.g. when the container's
+    /// limit isn't visible to it
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with = "threads"
+    )]
+    cpu_count_override: Option<u16>,
+
+    /// Before mining, measure the hashrate with one thread, half the CPUs
+    /// and all of them for a second each, and mine with the fastest
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "threads", "params_fifo", "daemon", "seeds_file", "addresses_file", "hours"
+        ]
+    )]
+    auto_threads: bool,
+
+    /// Pin mining thread i to CPU i so the scheduler doesn't move threads
+    /// between cores (Linux only; ignored with a warning elsewhere)
+    #[arg(long)]
+    pin_threads: bool,
+
+    /// Instead of a fixed --max-attempts, keep hashing for as long as the
+    /// measured hashrate says another attempt fits before latest_submission
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "max_attempts", "forever", "params_fifo", "daemon", "seeds_file", "hours",
+            "addresses_file"
+        ]
+    )]
+    auto_budget: bool,
+
+    /// Insert each found solution into this SQLite database, in the stream,
+    /// seeds, hours and addresses modes too
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<PathBuf>,
+
+    /// Write the raw 64-byte hash of the found solution to this file
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "addresses_file", "hours"]
+    )]
+    hash_bin_out: Option<PathBuf>,
+
+    /// Refuse to mine if the constructed preimage is longer than this many
+    /// bytes, so a malformed field fails fast instead of slowing every hash
+    #[arg(long, default_value_t = miner::DEFAULT_MAX_PREIMAGE_LEN)]
+    max_preimage_len: usize,
+
+    /// JSON file holding an array of per-byte maxima; a hash only counts if
+    /// each of its leading bytes is at most the matching entry. Replaces the
+    /// hex mask check; --difficulty still goes into the preimage
+    #[arg(long, value_name = "PATH")]
+    difficulty_bytes_file: Option<PathBuf>,
+
+    /// File of no_pre_mine values, one per line: mine the challenge against
+    /// each seed in turn and print one JSON line per seed
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["no_pre_mine", "params_fifo", "daemon"])]
+    seeds_file: Option<PathBuf>,
+
+    /// File of addresses, one per line: mine the challenge for each in turn
+    /// with one shared ROM. Results stream as NDJSON, not one array: a line
+    /// per address as soon as it is mined, the usual result keyed by an
+    /// "address" field
+    #[arg(
+        long,
+        alias = "address-file",
+        value_name = "PATH",
+        conflicts_with_all = ["address", "params_fifo", "daemon", "seeds_file"]
+    )]
+    addresses_file: Option<PathBuf>,
+
+    /// Stop the addresses-file run at the first address that finds a
+    /// solution instead of mining for every address
+    #[arg(long, requires = "addresses_file")]
+    first_success: bool,
+
+    /// Only mine the addresses this worker owns: worker i of N takes those
+    /// whose SHA-256 is i modulo N
+    #[arg(long, value_name = "i/N", requires = "addresses_file")]
+    shard: Option<shard::Shard>,
+
+    /// Hash all max_attempts nonces and report the numerically lowest hash
+    /// instead of stopping at the first one that meets the difficulty; if
+    /// even that misses it, the run fails with it as best_hash
+    #[arg(
+        long,
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "hours", "addresses_file"]
+    )]
+    best_of: bool,
+
+    /// Mine on past the first solution until this many distinct nonces
+    /// meet the difficulty (or the budget runs out), printing them as a
+    /// JSON array; the usual failure object is printed if none does
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = [
+            "best_of", "params_fifo", "daemon", "seeds_file", "hours", "addresses_file"
+        ]
+    )]
+    solutions: u32,
+
+    /// Letter case of the hex hash in the JSON output. The nonce keeps the
+    /// case it was hashed with (--nonce-encoding), since the server
+    /// rebuilds the preimage from it
+    #[arg(long, value_enum, default_value_t = HashCase::Lower)]
+    hash_case: HashCase,
+
+    /// Refuse to start if the files this run writes to (--sqlite,
+    /// --events-file, --hash-bin-out, --output) and the ROM cache already
+    /// use this many MiB, deleting the oldest cached ROMs first
+    #[arg(long, value_name = "MB")]
+    max_disk_usage_mb: Option<u64>,
+
+    /// File partitioned workers share: each appends the attempts it claims
+    /// and refuses to start if another worker claimed any of its nonces,
+    /// and the first to find a solution appends its result, which stops the
+    /// others within a few hundred attempts; remove it before a new round
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "addresses_file"]
+    )]
+    coordination_file: Option<PathBuf>,
+
+    /// Write the final result JSON to this file instead of stdout, through
+    /// a temporary file that is renamed into place; the directory must exist
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Print only these top-level fields of the result, e.g. nonce,hash;
+    /// schema_version is always kept
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        value_parser = clap::builder::PossibleValuesParser::new(output::RESULT_FIELDS),
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "hours", "addresses_file"]
+    )]
+    output_fields: Option<Vec<String>>,
+
+    /// Encoding of the preimage in the JSON output
+    #[arg(long, value_enum, default_value_t = PreimageEncoding::Text)]
+    preimage_encoding: PreimageEncoding,
+
+    /// With --preimage-encoding hex, decode the preimage again before
+    /// printing and fail if it doesn't reproduce the hashed bytes
     #[arg(long)]
-    challenge_id: String,
+    verify_preimage_encoding: bool,
+
+    /// Write a one-line JSON summary of the run (attempts, wall-clock time,
+    /// hashrate, ROM build time and whether a solution was found) to stderr
+    /// once mining stops
+    #[arg(long)]
+    summary: bool,
+
+    /// Log the preimage of each solution found; with --dry-run, print the
+    /// preimage for --nonce as JSON instead of mining
+    #[arg(long)]
+    print_preimage: bool,
+
+    /// Assemble the challenge and print the --nonce preimage without
+    /// building the ROM or hashing anything
+    #[arg(
+        long,
+        requires_all = ["print_preimage", "nonce"],
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "addresses_file"]
+    )]
+    dry_run: bool,
+
+    /// Instead of mining, hash the challenge for nonces 0 to N-1 and print
+    /// how the first byte of the digests is distributed, to check the
+    /// hasher's output looks uniform
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["dry_run", "params_fifo", "daemon", "seeds_file", "addresses_file"]
+    )]
+    histogram: Option<u64>,
+
+    /// Nonce to build the --dry-run preimage for (16 hex digits)
+    #[arg(long, value_name = "HEX", requires = "dry_run")]
+    nonce: Option<String>,
+
+    /// Also send the result to the local syslog daemon
+    #[cfg(feature = "syslog")]
+    #[arg(long)]
+    syslog: bool,
+
+    /// Syslog facility, e.g. user, daemon, local0
+    #[cfg(feature = "syslog")]
+    #[arg(long, default_value = "user", requires = "syslog")]
+    syslog_facility: String,
+
+    /// Process name the syslog entries are tagged with
+    #[cfg(feature = "syslog")]
+    #[arg(long, default_value = "night-cloud", requires = "syslog")]
+    syslog_tag: String,
+
+    /// Send the result only to syslog, leaving stdout empty
+    #[cfg(feature = "syslog")]
+    #[arg(long, requires = "syslog")]
+    syslog_only: bool,
+
+    /// Serve Prometheus metrics (attempts, hashrate, solutions, ROM build
+    /// time) over HTTP on this address while mining, e.g. 127.0.0.1:9184
+    #[cfg(feature = "metrics")]
+    #[arg(long, value_name = "HOST:PORT")]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Send a JSON status line (attempts, hashrate, elapsed_ms) to this TCP
+    /// endpoint while mining; failures are logged and never stop mining
+    #[arg(long, value_name = "HOST:PORT")]
+    heartbeat_tcp: Option<String>,
+
+    /// Append NDJSON start/progress/finish events to this file; a .gz (or,
+    /// with the zstd feature, .zst) extension compresses it on the fly
+    #[arg(long, value_name = "PATH")]
+    events_file: Option<PathBuf>,
+
+    /// Milliseconds between progress events
+    #[arg(long, default_value = "1000", requires = "events_file")]
+    events_interval_ms: u64,
+
+    /// Milliseconds events may wait in the write buffer before being
+    /// flushed to --events-file (0: flush each one); whatever is left is
+    /// flushed when the run ends
+    #[arg(long, value_name = "MS", default_value = "0", requires = "events_file")]
+    progress_flush_ms: u64,
+
+    /// Seconds between heartbeats
+    #[arg(long, default_value = "10", requires = "heartbeat_tcp")]
+    heartbeat_interval: u64,
+
+    /// Cap the process address space at this many MiB before building the
+    /// ROM (Linux only; the ROM alone needs 1024)
+    #[arg(long, value_name = "MB", global = true)]
+    rlimit_as_mb: Option<u64>,
+
+    /// Cap the process CPU time at this many seconds (Linux only)
+    #[arg(long, value_name = "SECONDS", global = true)]
+    rlimit_cpu_seconds: Option<u64>,
+
+    /// Lock each ROM into RAM after building it so it can't be swapped out
+    /// (Linux only). Needs CAP_IPC_LOCK or `ulimit -l` of at least 1 GiB;
+    /// otherwise a warning is logged and mining continues unlocked
+    #[arg(long)]
+    mlock_rom: bool,
+
+    /// Read the 1 GiB ROM from this file instead of generating it from
+    /// no_pre_mine. The bytes are trusted as-is: a file from another seed
+    /// gives hashes the server will reject
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["params_fifo", "daemon", "seeds_file", "hours", "addresses_file"]
+    )]
+    rom_file: Option<PathBuf>,
+
+    /// Directory of cached ROMs, memory-mapped instead of regenerated
+    /// [default: $XDG_CACHE_HOME/night-cloud]
+    #[arg(long, value_name = "DIR")]
+    rom_cache_dir: Option<PathBuf>,
+
+    /// Always generate the ROM in memory, neither reading nor writing the cache
+    #[arg(long, conflicts_with = "rom_cache_dir")]
+    no_rom_cache: bool,
+
+    /// Regenerate every ROM and overwrite its cache file instead of mapping
+    /// the file already there, to replace one suspected of being wrong
+    #[arg(long, conflicts_with = "no_rom_cache")]
+    rom_cache_refresh: bool,
+
+    /// Try building the ROM again this many times when it can't be
+    /// allocated, before giving up with exit status 3
+    #[arg(long, default_value = "0")]
+    rom_retries: u32,
+
+    /// Wait between ROM allocation attempts, e.g. 30s or 2m
+    #[arg(long, value_parser = deadline::parse_duration, default_value = "10s")]
+    rom_retry_delay: Duration,
+
+    /// Mine over a 256 KiB ROM instead of 1 GiB, for testing and CI on small
+    /// machines. Hashes are NOT the network's, so no solution found this
+    /// way will be accepted
+    #[arg(long, conflicts_with_all = ["rom_size", "pre_size"])]
+    low_memory: bool,
+
+    /// Build the ROM even if the system seems to have too little free
+    /// memory for it
+    #[arg(long, global = true)]
+    ignore_memory_check: bool,
+
+    /// ROM size in bytes. This and the four flags below override the spec's
+    /// parameters, which changes every hash: for experiments only
+    #[arg(long, default_value_t = hasher::ROM_SIZE)]
+    rom_size: usize,
+
+    /// Pre-memory size in bytes; a power of two no larger than --rom-size
+    #[arg(long, default_value_t = hasher::PRE_SIZE)]
+    pre_size: usize,
+
+    /// Pre-memory chunks combined into each ROM chunk
+    #[arg(long, default_value_t = hasher::MIXING_NUMBERS)]
+    mixing_numbers: usize,
+
+    /// Program loops per hash
+    #[arg(long, default_value_t = hasher::NB_LOOPS)]
+    loops: u32,
+
+    /// Instructions per program loop
+    #[arg(long, default_value_t = hasher::NB_INSTRS)]
+    instructions: u32,
+
+    /// Show attempts, hashrate and elapsed time on one self-updating stderr
+    /// line while mining; ignored when stderr is not a terminal
+    #[arg(long)]
+    status_line: bool,
+
+    /// Write a JSON line with attempts, elapsed seconds, current hashrate and
+    /// the closest hash so far to stderr this often while mining, e.g. 5s or
+    /// 500ms; on Unix, SIGUSR1 writes one such line on demand
+    #[arg(long, value_parser = deadline::parse_duration, conflicts_with = "status_line")]
+    progress_interval: Option<Duration>,
+
+    /// Fail instead of warning when the system clock looks unset or more
+    /// than a year away from latest_submission
+    #[arg(long)]
+    strict_clock: bool,
+
+    /// Opaque ID echoed verbatim as "job_id" in every result, summary and
+    /// heartbeat record, for correlating output with the job that spawned it
+    #[arg(long, value_name = "STRING")]
+    job_id: Option<String>,
+
+    /// Before mining, check that the spec preimage still hashes to the
+    /// pinned vector (building the full ROM once) and exit non-zero if not;
+    /// see --expected-hash and --test-preimage for another vector
+    #[arg(long)]
+    self_test: bool,
+
+    /// Hash the self-test checks for instead of the spec vector's, for a
+    /// deployment whose vector differs
+    #[arg(long, value_name = "HEX", requires = "self_test")]
+    expected_hash: Option<String>,
+
+    /// Preimage the self-test hashes instead of the spec one
+    #[arg(long, value_name = "STRING", requires = "self_test")]
+    test_preimage: Option<String>,
+
+    /// Hash for a second (or --min-duration-seconds) after building the ROM
+    /// to estimate the time to solution at the measured hashrate
+    #[arg(long)]
+    estimate: bool,
+
+    /// Sample the --estimate hashrate for at least this many seconds,
+    /// discarding any solutions, so it is taken once the CPU has ramped up
+    #[arg(long, value_name = "SECS", requires = "estimate")]
+    min_duration_seconds: Option<u64>,
+
+    /// Print the supported difficulty modes as JSON and exit
+    #[arg(long, exclusive = true)]
+    list_difficulty_modes: bool,
+
+    /// Read newline-delimited JSON challenges from this named pipe and
+    /// mine each in turn, reusing the ROM while no_pre_mine is unchanged
+    #[arg(long, value_name = "PATH")]
+    params_fifo: Option<PathBuf>,
+
+    /// Stay alive reading newline-delimited JSON challenges from stdin, as
+    /// with --params-fifo, writing one result line per challenge to stdout
+    #[arg(long, conflicts_with = "params_fifo")]
+    daemon: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Hash random preimages and stream output-prefix collisions as JSON lines
+    Collisions(CollisionsArgs),
+    /// Measure random-read latency and bandwidth of the generated ROM
+    RomBench(RomBenchArgs),
+    /// Build a ROM and report the hashrate of the mining loop on one thread
+    Bench(BenchArgs),
+    /// Check a JSON params file without building a ROM; exits non-zero on any failure
+    Validate(ValidateArgs),
+    /// Recompute the hash of a nonce or preimage and check it against the
+    /// difficulty; exits 2 if it does not meet it, 1 on bad input
+    Verify(VerifyArgs),
+    /// Print the hex AshMaize hash of one preimage
+    Hash(HashArgs),
+    /// Keep a ROM resident and answer JSON-RPC requests over TCP
+    Serve(ServeArgs),
+    /// Print canonical (params, preimage, hash) test vectors as JSON
+    GenVectors(GenVectorsArgs),
+    /// Hash the same preimages with two hasher configurations and report
+    /// where they diverge
+    Compare(CompareArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CollisionsArgs {
+    /// No pre-mine value used to seed the ROM
+    #[arg(long)]
+    no_pre_mine: String,
+
+    /// Number of random preimages to hash
+    #[arg(long, default_value = "100000")]
+    samples: u64,
+
+    /// Length of the hash prefix compared, in bytes
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(u8).range(1..=8))]
+    prefix_bytes: u8,
+}
+
+#[derive(clap::Args, Debug)]
+struct RomBenchArgs {
+    /// No pre-mine value used to seed the ROM
+    #[arg(long)]
+    no_pre_mine: String,
+
+    /// Number of random reads for each of the latency and bandwidth passes
+    #[arg(long, default_value = "10000000")]
+    reads: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// No pre-mine value used to seed the ROM [default: the spec example's]
+    #[arg(long)]
+    no_pre_mine: Option<String>,
+
+    /// Seconds to hash for
+    #[arg(long, default_value = "10")]
+    duration: u64,
+
+    /// Keep hashing, discarding any solutions, until at least this many
+    /// seconds have passed even if --duration is shorter, so the rate is
+    /// the sustained one; the measured duration is reported
+    #[arg(long, value_name = "SECS")]
+    min_duration_seconds: Option<u64>,
+
+    /// Difficulty to estimate the time to solution for [default: the spec
+    /// example's]
+    #[arg(long)]
+    difficulty: Option<String>,
+
+    /// Print a JSON object instead of a human-readable line
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// JSON params file (same fields as the mining flags); "-" reads stdin
+    #[arg(default_value = "-")]
+    params: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// Nonce to verify; the preimage is built from it and the challenge fields
+    #[arg(long, required_unless_present = "preimage", conflicts_with = "preimage")]
+    nonce: Option<String>,
+
+    /// Full preimage to verify as-is, instead of --nonce and the challenge fields
+    #[arg(long)]
+    preimage: Option<String>,
+
+    /// Cardano address
+    #[arg(long, required_unless_present = "preimage")]
+    address: Option<String>,
+
+    /// Challenge ID
+    #[arg(long, required_unless_present = "preimage")]
+    challenge_id: Option<String>,
 
     /// Difficulty (hex string)
     #[arg(long)]
     difficulty: String,
 
-    /// No pre-mine value (hex string)
+    /// No pre-mine value (hex string), which seeds the ROM
     #[arg(long)]
     no_pre_mine: String,
 
     /// Latest submission timestamp
-    #[arg(long)]
-    latest_submission: String,
+    #[arg(long, required_unless_present = "preimage")]
+    latest_submission: Option<String>,
 
     /// No pre-mine hour
+    #[arg(long, required_unless_present = "preimage")]
+    no_pre_mine_hour: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct HashArgs {
+    /// No pre-mine value (hex string), which seeds the ROM
+    #[arg(long)]
+    no_pre_mine: String,
+
+    /// Preimage to hash
+    #[arg(long, required_unless_present = "preimage_file", conflicts_with = "preimage_file")]
+    preimage: Option<String>,
+
+    /// File whose bytes are hashed as-is, trailing newline included
+    #[arg(long, value_name = "PATH")]
+    preimage_file: Option<PathBuf>,
+
+    /// Use the 256 KiB test ROM instead of the 1 GiB one; the hash is not
+    /// the real one
     #[arg(long)]
-    no_pre_mine_hour: String,
+    small_rom: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    listen: String,
 
-    /// Maximum attempts before giving up
+    /// Build the ROM for this no_pre_mine before accepting requests
+    #[arg(long)]
+    no_pre_mine: Option<String>,
+
+    /// Default attempt budget for `mine` requests
     #[arg(long, default_value = "10000000")]
     max_attempts: u64,
+
+    /// Largest attempt budget a `mine` request may ask for; requests over
+    /// it are rejected rather than tying up the CPUs
+    #[arg(long, default_value = "100000000")]
+    max_request_attempts: u64,
+
+    /// Letter case of hex hashes in responses
+    #[arg(long, value_enum, default_value_t = HashCase::Lower)]
+    hash_case: HashCase,
+}
+
+#[derive(clap::Args, Debug)]
+struct GenVectorsArgs {
+    /// Skip the full-size spec vector, which needs the 1 GiB ROM
+    #[arg(long)]
+    small_only: bool,
+
+    /// Write the vectors to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareArgs {
+    /// Seed of hasher A
+    #[arg(long)]
+    seed_a: String,
+
+    /// Seed of hasher B [default: same as A]
+    #[arg(long)]
+    seed_b: Option<String>,
+
+    /// How hasher A turns its seed into ROM key bytes
+    #[arg(long, value_enum, default_value_t = compare::SeedEncoding::String)]
+    encoding_a: compare::SeedEncoding,
+
+    /// How hasher B turns its seed into ROM key bytes
+    #[arg(long, value_enum, default_value_t = compare::SeedEncoding::Hex)]
+    encoding_b: compare::SeedEncoding,
+
+    /// Preimages to hash, one per line [default: random nonces]
+    #[arg(long, value_name = "PATH")]
+    preimages_file: Option<PathBuf>,
+
+    /// Number of random preimages when no file is given
+    #[arg(long, default_value = "100")]
+    samples: u64,
+
+    /// Use 256 KiB ROMs instead of two full 1 GiB ones; encodings and seeds
+    /// still compare, real hashes don't
+    #[arg(long)]
+    small_rom: bool,
+}
+
+impl Command {
+    /// Bytes of ROM the subcommand holds at once, if it builds any
+    fn rom_size(&self) -> Option<usize> {
+        let size = |small: bool| match small {
+            true => hasher::SMALL_ROM_SIZE,
+            false => hasher::ROM_SIZE,
+        };
+        match self {
+            Command::Collisions(_)
+            | Command::RomBench(_)
+            | Command::Bench(_)
+            | Command::Verify(_)
+            | Command::Serve(_) => Some(hasher::ROM_SIZE),
+            Command::Hash(cmd) => Some(size(cmd.small_rom)),
+            Command::GenVectors(cmd) => Some(size(cmd.small_only)),
+            // Both hashers are alive at once
+            Command::Compare(cmd) => Some(2 * size(cmd.small_rom)),
+            Command::Validate(_) => None,
+        }
+    }
+}
+
+impl Args {
+    /// ROM cache to go through, unless disabled or no cache dir is known
+    fn rom_cache(&self) -> Option<rom_cache::RomCache> {
+        if self.no_rom_cache {
+            return None;
+        }
+        let dir = self.rom_cache_dir.clone().or_else(rom_cache::RomCache::default_dir)?;
+        Some(rom_cache::RomCache::new(dir).with_refresh(self.rom_cache_refresh))
+    }
+
+    /// `--max-attempts`, or no limit with `--forever` or `--max-attempts 0`
+    fn attempt_budget(&self) -> u64 {
+        match self.max_attempts {
+            _ if self.forever => u64::MAX,
+            0 => u64::MAX,
+            max_attempts => max_attempts,
+        }
+    }
+
+    /// Mining threads, which also generate the ROM
+    fn threads(&self) -> usize {
+        match self.threads {
+            Some(threads) => threads.into(),
+            None => self.cpu_count_override.map_or_else(cpus::available, usize::from),
+        }
+    }
+
+    /// Pre-memory and ROM sizes, after --low-memory
+    fn rom_sizes(&self) -> (usize, usize) {
+        if self.low_memory {
+            (hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE)
+        } else {
+            (self.pre_size, self.rom_size)
+        }
+    }
+
+    /// ROM and hash parameters from the flags, checked before any ROM is built
+    fn hash_params(&self) -> Result<hasher::HashParams> {
+        let (pre_size, rom_size) = self.rom_sizes();
+        let params = hasher::HashParams {
+            pre_size,
+            rom_size,
+            mixing_numbers: self.mixing_numbers,
+            nb_loops: self.loops,
+            nb_instrs: self.instructions,
+            rom_threads: self.threads(),
+        };
+        params.validate()?;
+        if !params.is_spec() {
+            tracing::warn!(
+                "⚠️  {}: hashes are NOT spec-compliant and solutions will NOT be accepted \
+                 by the network",
+                if self.low_memory {
+                    "--low-memory".to_string()
+                } else {
+                    format!("Non-spec hash parameters ({:?})", params)
+                }
+            );
+        }
+        Ok(params)
+    }
+
+    fn rom_retry(&self) -> rom_cache::RomRetry {
+        rom_cache::RomRetry {
+            retries: self.rom_retries,
+            delay: self.rom_retry_delay,
+        }
+    }
+
+    fn session_config(&self, interrupted: &Arc<AtomicBool>) -> Result<session::SessionConfig<'_>> {
+        Ok(session::SessionConfig {
+            options: self.mine_options(interrupted)?,
+            rom_cache: self.rom_cache(),
+            params: self.hash_params()?,
+            rom_retry: self.rom_retry(),
+            mlock_rom: self.mlock_rom,
+            hash_case: self.hash_case,
+            preimage_encoding: self.preimage_encoding,
+            verify_preimage_encoding: self.verify_preimage_encoding,
+            job_id: self.job_id.as_deref(),
+            #[cfg(feature = "sqlite")]
+            solution_db: None,
+        })
+    }
+
+    fn mine_options(&self, interrupted: &Arc<AtomicBool>) -> Result<MineOptions> {
+        let byte_thresholds = match &self.difficulty_bytes_file {
+            Some(path) => {
+                let json = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Some(miner::parse_byte_thresholds(&json)?)
+            }
+            None => None,
+        };
+
+        Ok(MineOptions {
+            max_attempts: self.attempt_budget(),
+            progress: None,
+            thread_attempts: None,
+            solutions: None,
+            best: None,
+            max_preimage_len: self.max_preimage_len,
+            byte_thresholds,
+            deadline: None,
+            deadline_margin: (!self.ignore_deadline)
+                .then(|| self.deadline_margin.unwrap_or_default()),
+            partition: match (self.worker_index, self.worker_count) {
+                (Some(index), Some(count)) => Some(miner::Partition::new(index, count)?),
+                _ => None,
+            },
+            nonce: self.nonce_format()?,
+            cursor: None,
+            threads: self.threads(),
+            cancel: Some(interrupted.clone()),
+            max_duration: self.max_duration,
+            verify_after: self.verify_after,
+            rng_seed: self.rng_seed,
+            rng_skip: Vec::new(),
+            pin_threads: self.pin_threads && affinity::SUPPORTED,
+            stop_file: self.coordination_file.clone(),
+        })
+    }
+
+    fn nonce_format(&self) -> Result<miner::NonceFormat> {
+        Ok(miner::NonceFormat::new(self.nonce_bytes, self.nonce_encoding)?)
+    }
+
+    /// Challenge assembled from the individual flags
+    fn challenge(&self) -> Result<Challenge> {
+        self.challenge_with_seed(required(&self.no_pre_mine, "no-pre-mine")?)
+    }
+
+    /// Challenge from the individual flags, with `no_pre_mine` supplied
+    /// separately
+    fn challenge_with_seed(&self, no_pre_mine: String) -> Result<Challenge> {
+        let address = required(&self.address, "address")?;
+        if address.trim().is_empty() {
+            anyhow::bail!("--address must not be empty");
+        }
+        Ok(Challenge {
+            no_pre_mine,
+            ..self.challenge_with_address(address)?
+        })
+    }
+
+    /// Challenge from the individual flags, with `address` supplied
+    /// separately
+    fn challenge_with_address(&self, address: String) -> Result<Challenge> {
+        Ok(Challenge {
+            address,
+            challenge_id: required(&self.challenge_id, "challenge-id")?,
+            difficulty: match self.difficulty_bits {
+                Some(bits) => miner::difficulty_mask_for_bits(bits)?,
+                None => required(&self.difficulty, "difficulty")?,
+            },
+            no_pre_mine: self.no_pre_mine.clone().unwrap_or_default(),
+            latest_submission: required(&self.latest_submission, "latest-submission")?,
+            no_pre_mine_hour: match &self.hours {
+                Some(_) => String::new(),
+                None => required(&self.no_pre_mine_hour, "no-pre-mine-hour")?,
+            },
+        })
+    }
+}
+
+/// Apply the resource limits and check there is memory for a `rom_size`
+/// ROM, before the first one is built
+fn prepare_rom(args: &Args, rom_size: usize) -> Result<()> {
+    rlimit::Limits {
+        address_space_mb: args.rlimit_as_mb,
+        cpu_seconds: args.rlimit_cpu_seconds,
+    }
+    .apply()?;
+    if !args.ignore_memory_check {
+        memory::check(rom_size)?;
+    }
+    Ok(())
+}
+
+/// Warn, or fail with --strict-clock, if the clock looks wrong for the deadline
+fn check_clock(args: &Args, latest_submission: &str) -> Result<()> {
+    // Unparseable deadlines are reported where they are used
+    let Ok(deadline) = deadline::parse(latest_submission) else {
+        return Ok(());
+    };
+    if let Some(problem) = deadline::clock_problem(deadline, chrono::Utc::now()) {
+        if args.strict_clock {
+            anyhow::bail!("{}", problem);
+        }
+        tracing::warn!("{}", problem);
+    }
+    Ok(())
+}
+
+/// Report a ROM that could not be built as a failure result, where a run
+/// that found nothing would put its own
+fn print_rom_failure(args: &Args, e: &night_cloud::MinerError, to_stdout: bool) -> Result<()> {
+    tracing::error!("{}", e);
+    let failure = output::MineFailure::new(e.to_string());
+    let json = serde_json::to_string_pretty(&tagged(args.job_id.as_deref(), &failure))?;
+    match &args.output {
+        Some(path) => output::write_atomic(path, format!("{}\n", json).as_bytes())?,
+        None if to_stdout => println!("{}", json),
+        None => {}
+    }
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Print the preimage of `--nonce` for `--dry-run`
+fn print_preimage(args: &Args, challenge: &Challenge) -> Result<()> {
+    let nonce = required(&args.nonce, "nonce")?;
+    validate::hex(&nonce).context("Invalid --nonce")?;
+    let len = args.nonce_format()?.hex_len();
+    if nonce.len() != len {
+        anyhow::bail!(
+            "--nonce must be {} hex digits like the miner's own, not {}",
+            len,
+            nonce.len()
+        );
+    }
+
+    let preimage = challenge.preimage(&nonce);
+    let preview = serde_json::json!({
+        "nonce": nonce,
+        "preimage": args
+            .preimage_encoding
+            .encode(preimage.as_bytes(), args.verify_preimage_encoding)?,
+        "preimage_len": preimage.len(),
+    });
+    println!("{}", serde_json::to_string_pretty(&tagged(args.job_id.as_deref(), preview))?);
+    Ok(())
+}
+
+/// `--quiet` or `--verbose` if given, else `RUST_LOG` if set, else info
+/// for the miner's own logs
+fn log_filter(args: &Args) -> EnvFilter {
+    let level = if args.quiet {
+        "error"
+    } else if args.verbose {
+        "debug"
+    } else {
+        match EnvFilter::try_from_default_env() {
+            Ok(filter) => return filter,
+            Err(_) => "info",
+        }
+    };
+    EnvFilter::new(format!("night_cloud={}", level))
+}
+
+fn required(value: &Option<String>, flag: &str) -> Result<String> {
+    value.clone().with_context(|| format!("--{} is required", flag))
+}
+
+/// Exit status of a single-challenge run that found no solution
+const EXIT_NO_SOLUTION: i32 = 2;
+/// Exit status when the ROM could not be allocated, even after --rom-retries
+const EXIT_ROM_FAILED: i32 = 3;
+/// Exit status after Ctrl-C, as a shell reports SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+/// How long --auto-threads measures each thread count
+const AUTO_THREADS_SAMPLE: Duration = Duration::from_secs(1);
+/// How long --estimate measures the hashrate, unless --min-duration-seconds
+/// asks for longer
+const ESTIMATE_SAMPLE: Duration = Duration::from_secs(1);
+
+/// Flag set by the first Ctrl-C, which stops mining so the partial result
+/// is still printed; a second Ctrl-C exits at once
+fn install_interrupt_handler() -> Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::Relaxed) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Interrupted, stopping after the current hash (Ctrl-C again to exit now)");
+    })
+    .context("Failed to install the Ctrl-C handler")?;
+    Ok(interrupted)
+}
+
+/// Non-empty lines of a list file, skipping `#` comments
+fn read_list(path: &std::path::Path) -> Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect())
 }
 
 fn main() -> Result<()> {
-    // Initialize tracing
+    let (args, config_warnings) =
+        config::expand_args(&Args::command(), std::env::args_os().collect())?;
+    let mut args = Args::parse_from(args);
+
+    // Initialize tracing; stdout is reserved for results
     tracing_subscriber::fmt()
-        .with_env_filter("night_miner=info")
+        .with_env_filter(log_filter(&args))
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
         .init();
+    for warning in config_warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    if let Some(command) = &args.command {
+        if let Some(rom_size) = command.rom_size() {
+            prepare_rom(&args, rom_size)?;
+        }
+        return match command {
+            Command::Collisions(cmd) => run_collisions(cmd),
+            Command::RomBench(cmd) => run_rom_bench(cmd),
+            Command::Bench(cmd) => run_bench(cmd),
+            Command::Validate(cmd) => run_validate(cmd),
+            Command::Verify(cmd) => run_verify(cmd),
+            Command::Hash(cmd) => run_hash(cmd),
+            Command::Serve(cmd) => run_serve(cmd),
+            Command::GenVectors(cmd) => run_gen_vectors(cmd),
+            Command::Compare(cmd) => run_compare(cmd),
+        };
+    }
+
+    if args.list_difficulty_modes {
+        println!("{}", serde_json::to_string_pretty(&miner::difficulty_modes())?);
+        return Ok(());
+    }
+
+    // The limits and the memory check must come before the first ROM is
+    // built, the self-test's 1 GiB one included; a dry run builds none
+    let largest_rom = [
+        args.self_test.then_some(hasher::ROM_SIZE),
+        (!args.dry_run).then(|| args.rom_sizes().1),
+    ]
+    .into_iter()
+    .flatten()
+    .max();
+    if let Some(rom_size) = largest_rom {
+        prepare_rom(&args, rom_size)?;
+    }
+
+    if args.self_test {
+        vectors::self_test(&vectors::SelfTestVector {
+            preimage: args.test_preimage.clone(),
+            expected_hash: args.expected_hash.clone(),
+        })?;
+        info!("Self-test passed");
+    }
+    if args.pin_threads && !affinity::SUPPORTED {
+        tracing::warn!("--pin-threads is only supported on Linux; threads stay unpinned");
+    }
+
+    let interrupted = install_interrupt_handler()?;
+
+    if let Some(path) = &args.no_pre_mine_file {
+        args.no_pre_mine = Some(validate::seed_file(path)?);
+    }
+    if let Some(url) = &args.challenge_url {
+        let timeouts = challenge_url::Timeouts {
+            connect: Duration::from_secs(args.http_connect_timeout),
+            request: Duration::from_secs(args.http_request_timeout),
+        };
+        let fetched = challenge_url::fetch(url, timeouts)?;
+        info!("Fetched challenge {} from {}", fetched.challenge_id, url);
+        args.challenge_id = Some(fetched.challenge_id);
+        args.difficulty = Some(fetched.difficulty);
+        args.no_pre_mine = Some(fetched.no_pre_mine);
+        args.latest_submission = Some(fetched.latest_submission);
+        args.no_pre_mine_hour = Some(fetched.no_pre_mine_hour);
+    }
+
+    // Open the database before mining so a bad path fails fast
+    #[cfg(feature = "sqlite")]
+    let solution_db = args.sqlite.as_deref().map(sqlite::SolutionDb::open).transpose()?;
+    // Settings of the multi-challenge modes, which record solutions as well
+    let session_config = || {
+        args.session_config(&interrupted).map(|config| session::SessionConfig {
+            #[cfg(feature = "sqlite")]
+            solution_db: solution_db.as_ref(),
+            ..config
+        })
+    };
+
+    if args.daemon || args.params_fifo.is_some() {
+        let input: Box<dyn io::BufRead> = match &args.params_fifo {
+            Some(path) => {
+                info!("☁️⛏️  Night Cloud Miner - Reading challenges from {}", path.display());
+                // Opening a FIFO blocks until the writer side is opened
+                let fifo = File::open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                Box::new(BufReader::new(fifo))
+            }
+            None => {
+                info!("☁️⛏️  Night Cloud Miner - Reading challenges from stdin");
+                Box::new(io::stdin().lock())
+            }
+        };
+        let summary = session::run(
+            input,
+            io::stdout().lock(),
+            &session_config()?,
+        )?;
+        info!(
+            "Session ended: {} challenges, {} solved",
+            summary.challenges, summary.solved
+        );
+        return Ok(());
+    }
+
+    if let Some(path) = &args.seeds_file {
+        let seeds = read_list(path)?;
+        info!("☁️⛏️  Night Cloud Miner - Mining against {} seeds", seeds.len());
+
+        let template = args.challenge_with_seed(String::new())?;
+        for seed in &seeds {
+            let challenge = Challenge {
+                no_pre_mine: seed.clone(),
+                ..template.clone()
+            };
+            validate::challenge(&challenge).context("Invalid challenge")?;
+        }
+        check_clock(&args, &template.latest_submission)?;
+        let summary = session::run_seeds(
+            &template,
+            &seeds,
+            io::stdout().lock(),
+            &session_config()?,
+        )?;
+        info!("{} of {} seeds solved", summary.solved, summary.challenges);
+        return Ok(());
+    }
+
+    if let Some(hours) = &args.hours {
+        info!("☁️⛏️  Night Cloud Miner - Sweeping {} no_pre_mine_hour values", hours.0.len());
+
+        let template = args.challenge()?;
+        for hour in &hours.0 {
+            let challenge = Challenge {
+                no_pre_mine_hour: hour.clone(),
+                ..template.clone()
+            };
+            validate::challenge(&challenge).context("Invalid challenge")?;
+        }
+        check_clock(&args, &template.latest_submission)?;
+        let summary = session::run_hours(
+            &template,
+            &hours.0,
+            io::stdout().lock(),
+            &session_config()?,
+        )?;
+        info!("{} of {} hours solved", summary.solved, summary.challenges);
+        return Ok(());
+    }
+
+    if let Some(path) = &args.addresses_file {
+        let addresses = read_list(path)?;
+        let owned = match &args.shard {
+            Some(shard) => shard.select(&addresses),
+            None => addresses.iter().collect(),
+        };
+        info!(
+            "☁️⛏️  Night Cloud Miner - Shard {} owns {} of {} addresses",
+            args.shard.map_or("0/1".to_string(), |s| s.to_string()),
+            owned.len(),
+            addresses.len()
+        );
+        for address in &owned {
+            info!("  {}", address);
+        }
+
+        let template = args.challenge_with_address(String::new())?;
+        validate::challenge(&template).context("Invalid challenge")?;
+        check_clock(&args, &template.latest_submission)?;
+        let summary = session::run_addresses(
+            &template,
+            &owned,
+            args.first_success,
+            io::stdout().lock(),
+            &session_config()?,
+        )?;
+        info!("{} of {} addresses solved", summary.solved, summary.challenges);
+        return Ok(());
+    }
 
-    let args = Args::parse();
+    let challenge = args.challenge()?;
+    validate::challenge(&challenge).context("Invalid challenge")?;
+
+    if args.dry_run {
+        return print_preimage(&args, &challenge);
+    }
 
     info!("☁️⛏️  Night Cloud Miner - Mining for single address");
-    info!("Address: {}...", &args.address[..20]);
-    info!("Challenge: {}", args.challenge_id);
-    info!("Difficulty: {}", args.difficulty);
-    info!("Max attempts: {}", args.max_attempts);
+    info!("Address: {}...", challenge.address.chars().take(20).collect::<String>());
+    info!("Challenge: {}", challenge.challenge_id);
+    info!("Difficulty: {}", challenge.difficulty);
+    if args.auto_budget {
+        info!("Max attempts: until {}", challenge.latest_submission);
+    } else if args.attempt_budget() == u64::MAX {
+        info!("Max attempts: unlimited");
+    } else {
+        info!("Max attempts: {}", args.max_attempts);
+    }
+    if let Some(duration) = args.max_duration {
+        info!("Max duration: {:?}", duration);
+    }
+
+    // Likewise bind the metrics port; scrapes during the ROM build see zeros
+    #[cfg(feature = "metrics")]
+    let metrics = args
+        .metrics_addr
+        .map(|addr| {
+            let metrics = metrics::Metrics::default();
+            metrics::MetricsServer::spawn(addr, metrics.clone()).map(|server| (server, metrics))
+        })
+        .transpose()?;
+
+    #[cfg(feature = "syslog")]
+    let mut syslog = args
+        .syslog
+        .then(|| syslog_output::SyslogSink::connect(&args.syslog_facility, &args.syslog_tag))
+        .transpose()?;
+    #[cfg(feature = "syslog")]
+    let to_stdout = !args.syslog_only;
+    #[cfg(not(feature = "syslog"))]
+    let to_stdout = true;
+
+    // Catch bad options and oversized fields before spending seconds on the ROM
+    let mut options = args.mine_options(&interrupted)?;
+    let params = args.hash_params()?;
+    miner::check_preimage_len(&challenge, options.nonce, options.max_preimage_len)?;
+    check_clock(&args, &challenge.latest_submission)?;
+    if args.auto_budget {
+        options.deadline = Some(deadline::parse(&challenge.latest_submission)?);
+        options.max_attempts = u64::MAX;
+    }
+
+    for path in args.output.iter().chain(&args.coordination_file).chain(&args.checkpoint) {
+        output::check_output_path(path)?;
+    }
+    let checkpoint = match &args.checkpoint {
+        Some(path) => {
+            let job = checkpoint::Job {
+                address: challenge.address.clone(),
+                challenge_id: challenge.challenge_id.clone(),
+                worker_index: args.worker_index,
+                worker_count: args.worker_count,
+            };
+            let source = match (options.partition, args.rng_seed) {
+                (Some(_), _) => {
+                    let first = checkpoint::resume(path, &job)?;
+                    if first > 0 {
+                        info!("Resuming from attempt {} saved in {}", first, path.display());
+                    }
+                    let cursor = Arc::new(miner::AttemptCursor::new(first));
+                    options.cursor = Some(cursor.clone());
+                    checkpoint::Source::Cursor(cursor)
+                }
+                (None, Some(seed)) => {
+                    let resumed = checkpoint::resume_draws(path, &job, seed)?;
+                    if !resumed.is_empty() {
+                        let path = path.display();
+                        info!("Skipping the {:?} nonces per thread saved in {}", resumed, path);
+                    }
+                    // Thread counts only ever drop below --threads (see
+                    // --auto-threads), so these cover every thread
+                    let hashed = thread_rates::counters(options.threads);
+                    options.thread_attempts = Some(hashed.clone());
+                    options.rng_skip = resumed.clone();
+                    checkpoint::Source::Draws {
+                        seed,
+                        resumed,
+                        hashed,
+                    }
+                }
+                (None, None) => anyhow::bail!(
+                    "--checkpoint needs --worker-index, or --seed so random nonces can be replayed"
+                ),
+            };
+            Some((path.clone(), job, source))
+        }
+        None => None,
+    };
+    if let (Some(path), Some(partition)) = (&args.coordination_file, options.partition) {
+        let start = options.cursor.as_ref().map_or(0, |cursor| cursor.resume_point());
+        let claim = coordination::Claim {
+            index: partition.index(),
+            count: partition.count(),
+            start,
+            end: start.saturating_add(options.max_attempts),
+        };
+        coordination::claim(path, claim, checkpoint.is_some())?;
+        info!("Claimed attempts {}..{} in {}", claim.start, claim.end, path.display());
+    }
+    let mut pruned = Vec::new();
+    if let Some(max_mb) = args.max_disk_usage_mb {
+        let outputs = [
+            args.events_file.as_deref(),
+            args.hash_bin_out.as_deref(),
+            args.output.as_deref(),
+            args.checkpoint.as_deref(),
+            #[cfg(feature = "sqlite")]
+            args.sqlite.as_deref(),
+        ];
+        let outputs: Vec<&std::path::Path> = outputs.into_iter().flatten().collect();
+        let cache = args.rom_cache();
+        if let Some(cache) = &cache {
+            let current = cache.path(&challenge.no_pre_mine, &params);
+            pruned = disk::prune_cache(cache.dir(), Some(&current), &outputs, max_mb)?;
+            for rom in &pruned {
+                info!(
+                    "Deleted cached ROM {} ({} MiB) to stay within the disk budget",
+                    rom.path.display(),
+                    rom.bytes / (1024 * 1024)
+                );
+            }
+        }
+        disk::check_budget(&outputs, cache.as_ref().map(|cache| cache.dir()), max_mb)?;
+    }
+
+    let mut events = args
+        .events_file
+        .as_deref()
+        .map(|path| {
+            let flush_every = Duration::from_millis(args.progress_flush_ms);
+            events::EventLog::create(path, args.job_id.clone(), flush_every)
+        })
+        .transpose()?;
+    if let Some(log) = &events {
+        log.emit(&events::Event::Start {
+            challenge_id: &challenge.challenge_id,
+            difficulty: &challenge.difficulty,
+        })?;
+        for rom in &pruned {
+            log.emit(&events::Event::Prune {
+                path: &rom.path,
+                bytes: rom.bytes,
+            })?;
+        }
+    }
+
+    // Initialize hasher with ROM
+    let rom_started = Instant::now();
+    let hasher = match &args.rom_file {
+        Some(path) => {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            AshMaizeHasher::from_reader(BufReader::new(file), &params)
+                .with_context(|| format!("Failed to read ROM from {}", path.display()))?
+        }
+        None => {
+            let cache = args.rom_cache();
+            let seed = &challenge.no_pre_mine;
+            match rom_cache::hasher(cache.as_ref(), seed, &params, args.rom_retry()) {
+                Ok(hasher) => hasher,
+                Err(e) => {
+                    print_rom_failure(&args, &e, to_stdout)?;
+                    std::process::exit(EXIT_ROM_FAILED);
+                }
+            }
+        }
+    };
+    let rom_build = rom_started.elapsed();
+    if args.mlock_rom {
+        rlimit::lock_memory(hasher.rom_bytes())?;
+    }
+
+    if let Some(samples) = args.histogram {
+        info!("Hashing {} samples for the histogram", samples);
+        let report = histogram::sample(&hasher, &challenge, options.nonce, samples);
+        println!("{}", serde_json::to_string_pretty(&tagged(args.job_id.as_deref(), &report))?);
+        return Ok(());
+    }
+
+    if args.auto_threads {
+        let candidates = bench::thread_candidates(options.threads);
+        let (best, samples) =
+            bench::calibrate_threads(&hasher, &challenge, &candidates, AUTO_THREADS_SAMPLE);
+        let measured: Vec<String> = samples
+            .iter()
+            .map(|(threads, hashrate)| format!("{} threads: {:.1} H/s", threads, hashrate))
+            .collect();
+        info!("--auto-threads: mining with {} threads ({})", best, measured.join(", "));
+        options.threads = best;
+    }
+
+    let probability = options.success_probability(&challenge.difficulty);
+    let sample = args.estimate.then(|| {
+        let min_duration = Duration::from_secs(args.min_duration_seconds.unwrap_or_default());
+        bench::run(&hasher, &challenge, ESTIMATE_SAMPLE.max(min_duration), rom_build)
+    });
+    let hashrate = sample.as_ref().map(|sample| sample.hashrate * options.threads as f64);
+    let estimate = estimate::Estimate::new(probability, hashrate);
+    match &sample {
+        Some(sample) => info!(
+            "Estimate: {} (hashrate measured over {:.1}s)",
+            estimate,
+            sample.hashing_ms as f64 / 1000.0
+        ),
+        None => info!("Estimate: {}", estimate),
+    }
+
+    #[cfg(feature = "metrics")]
+    let metrics_attempts = metrics.as_ref().map(|(_, metrics)| {
+        metrics.rom_build_ms.store(rom_build.as_millis() as u64, Ordering::Relaxed);
+        options.solutions = Some(metrics.solutions.clone());
+        metrics.attempts.clone()
+    });
+    #[cfg(not(feature = "metrics"))]
+    let metrics_attempts = None;
+
+    // Shared attempt counter for the observers that need it, which on Unix
+    // includes the SIGUSR1 stats dump
+    let progress = metrics_attempts.or_else(|| {
+        (cfg!(unix)
+            || args.heartbeat_tcp.is_some()
+            || args.status_line
+            || args.progress_interval.is_some()
+            || events.is_some())
+        .then(|| Arc::new(AtomicU64::new(0)))
+    });
+    options.progress = progress.clone();
+    let best = Arc::new(miner::BestHash::default());
+    options.best = Some(best.clone());
+    let stats_dump = match &progress {
+        Some(attempts) if cfg!(unix) => {
+            let stats = stats_signal::LiveStats {
+                attempts: attempts.clone(),
+                best: best.clone(),
+            };
+            Some(stats_signal::StatsDump::install(stats, args.job_id.clone())?)
+        }
+        _ => None,
+    };
+    if let (Some(log), Some(progress)) = (&mut events, &progress) {
+        log.watch(progress.clone(), Duration::from_millis(args.events_interval_ms));
+    }
+    let heartbeat = args.heartbeat_tcp.clone().zip(progress.clone()).map(|(addr, progress)| {
+        heartbeat::Heartbeat::spawn(
+            addr,
+            Duration::from_secs(args.heartbeat_interval),
+            progress,
+            args.job_id.clone(),
+        )
+    });
+    let status_line = progress
+        .clone()
+        .filter(|_| args.status_line)
+        .and_then(status_line::StatusLine::spawn);
+    let progress_lines = args.progress_interval.zip(progress).map(|(interval, progress)| {
+        progress::ProgressReporter::spawn(progress, best, interval, args.job_id.clone())
+    });
+    let thread_rates = args.verbose.then(|| {
+        let attempts = options
+            .thread_attempts
+            .get_or_insert_with(|| thread_rates::counters(options.threads))
+            .clone();
+        let interval = args.progress_interval.unwrap_or(thread_rates::INTERVAL);
+        thread_rates::ThreadRateReporter::spawn(attempts, interval)
+    });
+    let checkpoint = checkpoint.map(|(path, job, source)| {
+        let interval = Duration::from_secs(args.checkpoint_interval);
+        checkpoint::CheckpointWriter::spawn(path, job, source, interval)
+    });
 
     // Mine solution
-    match miner::mine_solution(
-        &args.address,
-        &args.challenge_id,
-        &args.difficulty,
-        &args.no_pre_mine,
-        &args.latest_submission,
-        &args.no_pre_mine_hour,
-        args.max_attempts,
-    )? {
-        Some((nonce, preimage, hash)) => {
-            // Output as JSON for easy parsing by TypeScript
-            println!("{{");
-            println!("  \"success\": true,");
-            println!("  \"nonce\": \"{}\",", nonce);
-            println!("  \"preimage\": \"{}\",", preimage);
-            println!("  \"hash\": \"{}\"", hash);
-            println!("}}");
-            Ok(())
+    let outcomes = if args.best_of {
+        let mut outcome = miner::mine_best(&hasher, &challenge, &options)?;
+        // The lowest hash is only a solution if it meets the difficulty;
+        // otherwise nothing may be recorded, published or submitted
+        let misses = |solution: &mut miner::Solution| {
+            hex::decode(&solution.hash)
+                .is_ok_and(|hash| !options.meets_difficulty(&hash, &challenge.difficulty))
+        };
+        if let Some(best) = outcome.solution.take_if(misses) {
+            outcome.best = Some(best);
         }
+        vec![outcome]
+    } else if args.solutions > 1 {
+        miner::mine_solutions(&hasher, &challenge, &options, args.solutions as usize)?.outcomes()
+    } else {
+        vec![miner::mine_solution(&hasher, &challenge, &options)?]
+    };
+    let outcome = &outcomes[0];
+    drop(heartbeat);
+    drop(status_line);
+    drop(progress_lines);
+    drop(thread_rates);
+    drop(stats_dump);
+    drop(checkpoint);
+    if let Some(log) = events {
+        log.emit(&events::Event::Finish {
+            success: outcome.solution.is_some(),
+            attempts: outcome.attempts,
+        })?;
+        log.finish()?;
+    }
+    let stats = RunStats::new(outcome, rom_build, options.threads);
+    info!(
+        "Hashed {} times in {:.2?} ({:.1} H/s) after building the ROM in {:.2?}",
+        outcome.attempts, outcome.elapsed, stats.hashrate, rom_build
+    );
+    if args.summary {
+        let summary = output::RunSummary::new(outcome.solution.is_some(), &stats);
+        let json = serde_json::to_string(&tagged(args.job_id.as_deref(), summary))?;
+        eprintln!("{}", json);
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(db) = &solution_db {
+        for solution in outcomes.iter().filter_map(|o| o.solution.as_ref()) {
+            db.insert(solution, &challenge.difficulty, &stats)?;
+        }
+    }
+
+    if let Some(solution) = &outcome.solution {
+        if let Some(path) = &args.hash_bin_out {
+            let digest = hex::decode(&solution.hash)?;
+            std::fs::write(path, digest)
+                .with_context(|| format!("Failed to write hash to {}", path.display()))?;
+        }
+    }
+
+    let mut results = Vec::new();
+    for outcome in &outcomes {
+        let mut result =
+            MineResult::from_outcome(outcome, &challenge, args.hash_case, stats.clone());
+        if let (MineResult::Success(success), Some(solution)) = (&mut result, &outcome.solution) {
+            if args.print_preimage {
+                info!("Preimage of nonce {}: {}", solution.nonce, solution.preimage);
+            }
+            success.preimage = args
+                .preimage_encoding
+                .encode(solution.preimage.as_bytes(), args.verify_preimage_encoding)?;
+            if args.best_of {
+                success.meets_difficulty = Some(true);
+            }
+        }
+
+        #[cfg(feature = "syslog")]
+        if let Some(sink) = &mut syslog {
+            sink.send_result(&result)?;
+        }
+        results.push(result);
+    }
+
+    if to_stdout || args.output.is_some() || args.coordination_file.is_some() {
+        // Output as JSON for easy parsing by TypeScript
+        let mut json = Vec::new();
+        for result in &results {
+            let mut value = serde_json::to_value(tagged(args.job_id.as_deref(), result))?;
+            if let Some(fields) = &args.output_fields {
+                value = output::project(value, fields);
+            }
+            json.push(value);
+        }
+        let json = if args.solutions > 1 && outcome.solution.is_some() {
+            serde_json::Value::Array(json)
+        } else {
+            json.swap_remove(0)
+        };
+        if let (Some(path), Some(_)) = (&args.coordination_file, &outcome.solution) {
+            if coordination::publish(path, &json)? {
+                info!("Published the solution to {}", path.display());
+            } else {
+                info!("Another worker published a solution to {} first", path.display());
+            }
+        }
+        let json = serde_json::to_string_pretty(&json)?;
+        match &args.output {
+            Some(path) => output::write_atomic(path, format!("{}\n", json).as_bytes())?,
+            None if to_stdout => println!("{}", json),
+            None => {}
+        }
+    }
+    if outcome.solution.is_some() {
+        return Ok(());
+    }
+    io::stdout().flush()?;
+    match outcome.termination {
+        Some(miner::TerminationReason::Interrupted) => std::process::exit(EXIT_INTERRUPTED),
+        _ => std::process::exit(EXIT_NO_SOLUTION),
+    }
+}
+
+fn run_collisions(cmd: &CollisionsArgs) -> Result<()> {
+    info!("Collision search: {} samples, {}-byte prefix", cmd.samples, cmd.prefix_bytes);
+    let hasher = AshMaizeHasher::try_new(&cmd.no_pre_mine)?;
+
+    // One JSON line per collision as it is found, then a summary line.
+    // Each sample costs a full AshMaize hash, so flushing per line is cheap
+    // next to the search while keeping streaming consumers up to date.
+    let mut out = io::stdout().lock();
+    let report = collisions::search(&hasher, cmd.samples, cmd.prefix_bytes as usize, |pair| {
+        serde_json::to_writer(&mut out, &pair)?;
+        writeln!(out)?;
+        out.flush()
+    })?;
+    info!(
+        "{} collisions (expected {:.2})",
+        report.collisions, report.expected_collisions
+    );
+    writeln!(out, "{}", serde_json::json!({ "summary": report }))?;
+    Ok(())
+}
+
+fn run_rom_bench(cmd: &RomBenchArgs) -> Result<()> {
+    let hasher = AshMaizeHasher::try_new(&cmd.no_pre_mine)?;
+
+    info!("Benchmarking {} random reads", cmd.reads);
+    let report = rom_bench::run(hasher.rom_bytes(), cmd.reads);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn run_bench(cmd: &BenchArgs) -> Result<()> {
+    let mut challenge = vectors::spec_challenge();
+    if let Some(seed) = &cmd.no_pre_mine {
+        challenge.no_pre_mine = seed.clone();
+    }
+    if let Some(difficulty) = &cmd.difficulty {
+        validate::difficulty(difficulty)?;
+        challenge.difficulty = difficulty.clone();
+    }
+
+    let rom_started = Instant::now();
+    let hasher = AshMaizeHasher::try_new(&challenge.no_pre_mine)?;
+    let rom_build = rom_started.elapsed();
+
+    let duration = cmd.duration.max(cmd.min_duration_seconds.unwrap_or_default());
+    info!("Hashing for {}s", duration);
+    let report = bench::run(&hasher, &challenge, Duration::from_secs(duration), rom_build);
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+fn run_serve(cmd: &ServeArgs) -> Result<()> {
+    let defaults = MineOptions {
+        max_attempts: cmd.max_attempts,
+        ..MineOptions::default()
+    };
+    let server = rpc::Server::new(defaults, cmd.max_request_attempts, cmd.hash_case);
+    if let Some(seed) = &cmd.no_pre_mine {
+        server.rebuild_rom(seed)?;
+    }
+
+    let listener = std::net::TcpListener::bind(&cmd.listen)
+        .with_context(|| format!("Failed to listen on {}", cmd.listen))?;
+    info!("☁️⛏️  Night Cloud Miner - JSON-RPC on {}", listener.local_addr()?);
+    rpc::serve(listener, std::sync::Arc::new(server))
+}
+
+fn run_gen_vectors(cmd: &GenVectorsArgs) -> Result<()> {
+    let vectors = vectors::generate(!cmd.small_only)?;
+    let json = serde_json::to_string_pretty(&vectors)?;
+    match &cmd.out {
+        Some(path) => std::fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+fn run_compare(cmd: &CompareArgs) -> Result<()> {
+    use rand::Rng;
+
+    let params = if cmd.small_rom {
+        hasher::HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE)
+    } else {
+        hasher::HashParams::default()
+    };
+    let seed_b = cmd.seed_b.as_deref().unwrap_or(&cmd.seed_a);
+    let key_a = cmd.encoding_a.key(&cmd.seed_a).context("Seed A")?;
+    let key_b = cmd.encoding_b.key(seed_b).context("Seed B")?;
+
+    let preimages = match &cmd.preimages_file {
+        Some(path) => read_list(path)?,
+        None => {
+            let mut rng = rand::thread_rng();
+            (0..cmd.samples)
+                .map(|_| format!("{:016x}", rng.gen::<u64>()))
+                .collect()
+        }
+    };
+
+    info!("Building hasher A");
+    let a = AshMaizeHasher::try_from_seed_bytes(&key_a, &params)?;
+    info!("Building hasher B");
+    let b = AshMaizeHasher::try_from_seed_bytes(&key_b, &params)?;
+
+    let report = compare::compare(&a, &b, preimages);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn run_verify(cmd: &VerifyArgs) -> Result<()> {
+    let preimage = match (&cmd.preimage, &cmd.nonce) {
+        (Some(preimage), _) => preimage.clone(),
+        (None, nonce) => Challenge {
+            address: required(&cmd.address, "address")?,
+            challenge_id: required(&cmd.challenge_id, "challenge-id")?,
+            difficulty: cmd.difficulty.clone(),
+            no_pre_mine: cmd.no_pre_mine.clone(),
+            latest_submission: required(&cmd.latest_submission, "latest-submission")?,
+            no_pre_mine_hour: required(&cmd.no_pre_mine_hour, "no-pre-mine-hour")?,
+        }
+        .preimage(&required(nonce, "nonce")?),
+    };
+
+    let hasher = AshMaizeHasher::try_new(&cmd.no_pre_mine)?;
+    let verification = verify::verify(&hasher, preimage, &cmd.difficulty);
+    println!("{}", serde_json::to_string_pretty(&verification)?);
+
+    if !verification.meets_difficulty {
+        std::process::exit(EXIT_NO_SOLUTION);
+    }
+    Ok(())
+}
+
+fn run_hash(cmd: &HashArgs) -> Result<()> {
+    let preimage = match &cmd.preimage {
+        Some(preimage) => preimage.clone().into_bytes(),
         None => {
-            // No solution found
-            println!("{{");
-            println!("  \"success\": false,");
-            println!("  \"message\": \"No solution found in {} attempts\"", args.max_attempts);
-            println!("}}");
-            Ok(())
+            let path = cmd.preimage_file.as_ref().context("--preimage-file is required")?;
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?
         }
+    };
+
+    let params = if cmd.small_rom {
+        hasher::HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE)
+    } else {
+        hasher::HashParams::default()
+    };
+    let hasher = AshMaizeHasher::try_with_params(&cmd.no_pre_mine, &params)?;
+    println!("{}", hex::encode(hasher.hash(&preimage)));
+    Ok(())
+}
+
+fn run_validate(cmd: &ValidateArgs) -> Result<()> {
+    let text = if cmd.params.as_os_str() == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(&cmd.params)
+            .with_context(|| format!("Failed to read {}", cmd.params.display()))?
+    };
+    let params: serde_json::Value =
+        serde_json::from_str(&text).context("Params are not valid JSON")?;
+
+    let report = validate::ValidationReport::new(&params);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    if !report.valid {
+        std::process::exit(1);
     }
+    Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    /// Multi-challenge runs, with the fields each needs
+    const SESSIONS: [&str; 5] = [
+        "--daemon",
+        "--params-fifo challenges.fifo",
+        "--seeds-file seeds.txt --address addr1 --no-pre-mine-hour 1",
+        "--hours 1-3 --address addr1 --no-pre-mine 00",
+        "--addresses-file addresses.txt --no-pre-mine 00 --no-pre-mine-hour 1",
+    ];
+
+    /// Parse `flags`, split on whitespace, after the fields every run needs
+    fn parse(flags: &str) -> Result<Args, clap::Error> {
+        let required = "--challenge-id **D07C10 --difficulty 000FFFFF \
+                        --latest-submission 2025-10-19T08:59:59.000Z";
+        let args = format!("night-cloud {} {}", required, flags);
+        Args::try_parse_from(args.split_whitespace())
+    }
+
+    fn conflicts(flags: &str) -> bool {
+        parse(flags).is_err_and(|e| e.kind() == ErrorKind::ArgumentConflict)
+    }
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_single_run_flags_conflict_with_sessions() {
+        for session in SESSIONS {
+            assert!(parse(session).is_ok(), "{}", session);
+            let flags = [
+                "--best-of",
+                "--solutions 3",
+                "--auto-budget",
+                "--rom-file rom.bin",
+                "--output-fields nonce,hash",
+            ];
+            for flag in flags {
+                let flags = format!("{} {}", session, flag);
+                assert!(conflicts(&flags), "{}", flags);
+            }
+        }
+    }
+
+    #[test]
+    fn test_challenge_url_conflicts_with_fetched_fields() {
+        let url = "night-cloud --address addr1 --challenge-url http://localhost/challenge";
+        assert!(Args::try_parse_from(url.split_whitespace()).is_ok());
+        let fields = [
+            "--challenge-id **D07C10",
+            "--difficulty 000FFFFF",
+            "--no-pre-mine 00",
+            "--latest-submission 2025-10-19T08:59:59.000Z",
+            "--no-pre-mine-hour 1",
+        ];
+        for field in fields {
+            let args = format!("{} {}", url, field);
+            let error = Args::try_parse_from(args.split_whitespace()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict, "{}", field);
+        }
+    }
+}
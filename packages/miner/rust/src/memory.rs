@@ -0,0 +1,135 @@
+/*!
+# Memory check
+
+Before a run builds its ROM, the memory the system can still hand out is
+compared with what the ROM needs, so a 1 GiB ROM on a 512 MiB container
+fails at once with a clear message instead of thrashing swap until the
+OOM killer steps in. `--ignore-memory-check` skips it.
+
+Available memory is `MemAvailable` from `/proc/meminfo`, lowered to the
+headroom left under the cgroup's memory limit when there is one, since a
+container sees the host's `/proc/meminfo`. Where neither can be read the
+check passes.
+*/
+
+use std::fs;
+
+use anyhow::{bail, Result};
+use tracing::debug;
+
+/// cgroup v2 limit and usage files
+const CGROUP_V2: (&str, &str) = ("/sys/fs/cgroup/memory.max", "/sys/fs/cgroup/memory.current");
+/// cgroup v1 limit and usage files
+const CGROUP_V1: (&str, &str) = (
+    "/sys/fs/cgroup/memory/memory.limit_in_bytes",
+    "/sys/fs/cgroup/memory/memory.usage_in_bytes",
+);
+
+/// cgroup v1 limits at least this large mean no limit
+const UNLIMITED: u64 = 1 << 62;
+
+/// Bytes a run building a `rom_size` ROM needs free: the ROM plus about
+/// 10% for the generation buffers and the rest of the process
+pub fn required_bytes(rom_size: usize) -> u64 {
+    let rom_size = rom_size as u64;
+    rom_size + rom_size / 10
+}
+
+/// Bytes the system can give this process without swapping, if known
+pub fn available_bytes() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|text| mem_available(&text));
+    let cgroup = [CGROUP_V2, CGROUP_V1]
+        .into_iter()
+        .find_map(|(limit, usage)| {
+            cgroup_headroom(
+                &fs::read_to_string(limit).ok()?,
+                &fs::read_to_string(usage).ok()?,
+            )
+        });
+    match (meminfo, cgroup) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Refuse to build a `rom_size` ROM when less than [`required_bytes`] is
+/// available
+pub fn check(rom_size: usize) -> Result<()> {
+    check_with(rom_size, available_bytes())
+}
+
+fn check_with(rom_size: usize, available: Option<u64>) -> Result<()> {
+    let Some(available) = available else {
+        debug!("Available memory unknown; skipping the memory check");
+        return Ok(());
+    };
+    let needed = required_bytes(rom_size);
+    if available < needed {
+        bail!(
+            "Need ~{} free for the ROM, only {} available; mine with --low-memory \
+             for testing, or pass --ignore-memory-check to try anyway",
+            format_bytes(needed),
+            format_bytes(available)
+        );
+    }
+    Ok(())
+}
+
+/// `MemAvailable` of a `/proc/meminfo`, in bytes
+fn mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Bytes left under a cgroup memory limit; `None` without a limit (v2
+/// writes "max", v1 a page-rounded `i64::MAX`)
+fn cgroup_headroom(limit: &str, usage: &str) -> Option<u64> {
+    let limit: u64 = limit.trim().parse().ok()?;
+    let usage: u64 = usage.trim().parse().ok()?;
+    (limit < UNLIMITED).then(|| limit.saturating_sub(usage))
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= 1024 * MIB {
+        format!("{:.1} GiB", bytes as f64 / (1024 * MIB) as f64)
+    } else {
+        format!("{} MiB", bytes / MIB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::ROM_SIZE;
+
+    #[test]
+    fn test_memory_check() {
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:         1012732 kB\n\
+                       MemAvailable:     409600 kB\nBuffers:          215452 kB\n";
+        assert_eq!(mem_available(meminfo), Some(400 * 1024 * 1024));
+        assert_eq!(mem_available("MemTotal: 1 kB\n"), None);
+
+        assert_eq!(
+            cgroup_headroom("536870912\n", "134217728\n"),
+            Some(384 * 1024 * 1024)
+        );
+        assert_eq!(cgroup_headroom("max\n", "134217728\n"), None);
+        assert_eq!(cgroup_headroom("9223372036854771712\n", "0\n"), None);
+        assert_eq!(cgroup_headroom("100\n", "200\n"), Some(0));
+
+        let err = check_with(ROM_SIZE, mem_available(meminfo)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Need ~1.1 GiB free for the ROM, only 400 MiB available; mine with --low-memory \
+             for testing, or pass --ignore-memory-check to try anyway"
+        );
+        assert!(check_with(ROM_SIZE, Some(2 * ROM_SIZE as u64)).is_ok());
+        assert!(check_with(ROM_SIZE, None).is_ok());
+    }
+}
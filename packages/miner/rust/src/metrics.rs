@@ -0,0 +1,202 @@
+/*!
+# Prometheus metrics
+
+Serves the miner's counters in the Prometheus text format over plain HTTP
+while mining (`--metrics-addr`), so a fleet can be scraped centrally instead
+of parsing every host's progress lines. Any `GET` gets the metrics; the
+server answers one connection at a time, which is plenty for a scraper.
+*/
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+/// How long a scraper may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters the mining threads keep up to date
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// As in [`MineOptions::progress`](crate::miner::MineOptions::progress)
+    pub attempts: Arc<AtomicU64>,
+    /// As in [`MineOptions::solutions`](crate::miner::MineOptions::solutions)
+    pub solutions: Arc<AtomicU64>,
+    /// Set once the ROM is ready; the server starts before it is built
+    pub rom_build_ms: Arc<AtomicU64>,
+}
+
+/// Background thread serving metrics until dropped
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Bind `addr` and start serving `metrics`; binding fails here rather
+    /// than in the background
+    pub fn spawn(addr: SocketAddr, metrics: Metrics) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind --metrics-addr {}", addr))?;
+        let addr = listener.local_addr()?;
+        info!("Serving Prometheus metrics on http://{}/metrics", addr);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(listener, &metrics, &stop))
+        };
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address actually bound, e.g. when port 0 was asked for
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // Wake the blocking accept
+            let _ = TcpStream::connect(self.addr);
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(listener: TcpListener, metrics: &Metrics, stop: &AtomicBool) {
+    let started = Instant::now();
+    let mut last = (0, Duration::ZERO);
+
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept a metrics connection: {}", e);
+                continue;
+            }
+        };
+
+        let now = (metrics.attempts.load(Ordering::Relaxed), started.elapsed());
+        let secs = now.1.saturating_sub(last.1).as_secs_f64();
+        let hashrate = if secs > 0.0 {
+            now.0.saturating_sub(last.0) as f64 / secs
+        } else {
+            0.0
+        };
+        last = now;
+
+        if let Err(e) = respond(stream, &render(metrics, hashrate)) {
+            warn!("Failed to answer a metrics request: {}", e);
+        }
+    }
+}
+
+/// Answer one HTTP request with `body`, or 405 if it isn't a `GET`
+fn respond(stream: TcpStream, body: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request has no body worth reading
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut stream = reader.into_inner();
+    let response = if request_line.starts_with("GET ") {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string()
+    };
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// The metrics in the Prometheus text exposition format
+fn render(metrics: &Metrics, hashrate: f64) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = write!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        );
+    };
+    metric(
+        "night_cloud_attempts_total",
+        "counter",
+        "Hashes computed",
+        metrics.attempts.load(Ordering::Relaxed).to_string(),
+    );
+    metric(
+        "night_cloud_hashrate",
+        "gauge",
+        "Hashes per second since the previous scrape",
+        hashrate.to_string(),
+    );
+    metric(
+        "night_cloud_solutions_total",
+        "counter",
+        "Solutions found",
+        metrics.solutions.load(Ordering::Relaxed).to_string(),
+    );
+    metric(
+        "night_cloud_rom_build_seconds",
+        "gauge",
+        "Time spent building or loading the ROM",
+        (metrics.rom_build_ms.load(Ordering::Relaxed) as f64 / 1000.0).to_string(),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_scrape() {
+        let metrics = Metrics {
+            attempts: Arc::new(AtomicU64::new(1234)),
+            solutions: Arc::new(AtomicU64::new(1)),
+            rom_build_ms: Arc::new(AtomicU64::new(2500)),
+        };
+        let server = MetricsServer::spawn("127.0.0.1:0".parse().unwrap(), metrics).unwrap();
+
+        let mut conn = TcpStream::connect(server.local_addr()).unwrap();
+        conn.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        conn.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE night_cloud_attempts_total counter\n"));
+        assert!(response.contains("\nnight_cloud_attempts_total 1234\n"));
+        assert!(response.contains("\nnight_cloud_solutions_total 1\n"));
+        assert!(response.contains("\nnight_cloud_rom_build_seconds 2.5\n"));
+        drop(server);
+    }
+}
@@ -1,64 +1,979 @@
-use anyhow::Result;
-use tracing::{debug, info};
-use rand::Rng;
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::hasher::AshMaizeHasher;
+use chrono::{DateTime, Utc};
+use tracing::{debug, info, warn};
+use clap::ValueEnum;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::affinity;
+use crate::coordination;
+use crate::deadline::{self, AutoBudget};
+use crate::error::{MinerError, Result};
+use crate::hasher::{AshMaizeHasher, DIGEST_SIZE};
+
+/// A solution found by the miner
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub nonce: String,
+    pub preimage: String,
+    pub hash: String,
+}
+
+/// Result of a mining run, with the work done either way
+#[derive(Debug)]
+pub struct MineOutcome {
+    pub solution: Option<Solution>,
+    /// Number of hashes computed
+    pub attempts: u64,
+    /// Time spent hashing (excludes ROM generation)
+    pub elapsed: Duration,
+    /// Why the search stopped early, if it did
+    pub termination: Option<TerminationReason>,
+    /// Hash closest to meeting the difficulty (see [`Target::distance`]),
+    /// whether or not it met it
+    pub best: Option<Solution>,
+}
+
+/// Result of [`mine_solutions`]
+#[derive(Debug)]
+pub struct MultiOutcome {
+    /// Distinct solutions in the order they were found
+    pub solutions: Vec<Solution>,
+    pub attempts: u64,
+    pub elapsed: Duration,
+    pub termination: Option<TerminationReason>,
+    pub best: Option<Solution>,
+}
+
+impl MultiOutcome {
+    /// One outcome per solution sharing this run's work, or a single
+    /// outcome without a solution if none was found
+    pub fn outcomes(&self) -> Vec<MineOutcome> {
+        let outcome = |solution| MineOutcome {
+            solution,
+            attempts: self.attempts,
+            elapsed: self.elapsed,
+            termination: self.termination,
+            best: self.best.clone(),
+        };
+        if self.solutions.is_empty() {
+            return vec![outcome(None)];
+        }
+        self.solutions.iter().map(|s| outcome(Some(s.clone()))).collect()
+    }
+}
+
+/// Reason a search ended without finding or exhausting its budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// No further attempt fit before `latest_submission` (`--auto-budget`)
+    DeadlinePassed,
+    /// The cancel flag was set, e.g. by Ctrl-C
+    Interrupted,
+    /// `max_duration` passed (`--max-duration`)
+    MaxDuration,
+    /// Another worker published a solution to the `stop_file`
+    /// (`--coordination-file`)
+    Superseded,
+}
+
+/// How a search ended
+struct SearchEnd {
+    attempts: u64,
+    elapsed: Duration,
+    termination: Option<TerminationReason>,
+    best: Option<Solution>,
+}
+
+/// Closest hash a search has seen so far, shared with observers on other
+/// threads
+#[derive(Debug, Default)]
+pub struct BestHash(Mutex<Option<(Vec<u8>, Solution)>>);
+
+impl BestHash {
+    /// The closest hash so far, if any hash was computed
+    pub fn get(&self) -> Option<Solution> {
+        lock(&self.0).as_ref().map(|(_, best)| best.clone())
+    }
+
+    /// Keep the hash built by `solution` if `distance` is below the best
+    /// so far
+    fn offer(&self, distance: &[u8], solution: impl FnOnce() -> Solution) {
+        let mut best = lock(&self.0);
+        if best.as_ref().is_none_or(|(best, _)| distance < best.as_slice()) {
+            *best = Some((distance.to_vec(), solution()));
+        }
+    }
+}
+
+/// Attempt numbers of a search: the next one to hand out, and the batches
+/// threads have claimed but not finished, so a checkpoint knows where a
+/// restart can pick up without skipping any
+#[derive(Debug)]
+pub struct AttemptCursor {
+    next: AtomicU64,
+    unfinished: Mutex<BTreeSet<u64>>,
+}
+
+impl AttemptCursor {
+    /// Cursor handing out attempt numbers from `first` on
+    pub fn new(first: u64) -> Self {
+        Self {
+            next: AtomicU64::new(first),
+            unfinished: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Lowest attempt number that may not have been tried yet
+    ///
+    /// A thread that stopped mid-batch leaves the whole batch unfinished, so
+    /// resuming here may repeat up to [`NONCE_BATCH`] attempts per thread.
+    pub fn resume_point(&self) -> u64 {
+        let unfinished = lock(&self.unfinished);
+        let next = self.next.load(Ordering::Relaxed);
+        unfinished.first().map_or(next, |&start| start.min(next))
+    }
+
+    fn claim(&self) -> Range<u64> {
+        // Claimed under the lock, so resume_point never sees a batch handed
+        // out but not yet recorded
+        let mut unfinished = lock(&self.unfinished);
+        let start = self.next.fetch_add(NONCE_BATCH, Ordering::Relaxed);
+        unfinished.insert(start);
+        start..start.saturating_add(NONCE_BATCH)
+    }
+
+    fn finish(&self, start: u64) {
+        lock(&self.unfinished).remove(&start);
+    }
+}
+
+impl Default for AttemptCursor {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Challenge fields that make up every preimage
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Challenge {
+    pub address: String,
+    pub challenge_id: String,
+    pub difficulty: String,
+    pub no_pre_mine: String,
+    pub latest_submission: String,
+    pub no_pre_mine_hour: String,
+}
+
+impl Challenge {
+    /// Construct the preimage for a nonce
+    pub fn preimage(&self, nonce: &str) -> String {
+        construct_preimage(
+            nonce,
+            &self.address,
+            &self.challenge_id,
+            &self.difficulty,
+            &self.no_pre_mine,
+            &self.latest_submission,
+            &self.no_pre_mine_hour,
+        )
+    }
+}
+
+/// Settings for a mining run
+#[derive(Debug, Clone)]
+pub struct MineOptions {
+    /// Maximum attempts before giving up
+    pub max_attempts: u64,
+    /// Incremented after every hash, for observers on other threads
+    pub progress: Option<Arc<AtomicU64>>,
+    /// Slot `i` is incremented for every hash thread `i` computes, for
+    /// per-thread hashrates; threads without a slot are only counted in
+    /// `progress`
+    pub thread_attempts: Option<Arc<[AtomicU64]>>,
+    /// Incremented for every solution accepted
+    pub solutions: Option<Arc<AtomicU64>>,
+    /// Where the search records its closest hash, for observers on other
+    /// threads; the search uses its own when this is `None`
+    pub best: Option<Arc<BestHash>>,
+    /// Refuse to mine if the preimage would be longer than this many bytes
+    pub max_preimage_len: usize,
+    /// Per-byte maxima replacing the hex mask check (see [`DifficultyMode::Bytes`])
+    pub byte_thresholds: Option<Vec<u8>>,
+    /// Also stop once no further attempt fits before this time
+    pub deadline: Option<DateTime<Utc>>,
+    /// Stop, with [`TerminationReason::DeadlinePassed`], once the
+    /// challenge's `latest_submission` is less than this far off, checked
+    /// every [`DURATION_CHECK_INTERVAL`] attempts; `None` mines past it
+    pub deadline_margin: Option<Duration>,
+    /// Worker threads sharing the hasher and the attempt budget
+    pub threads: usize,
+    /// Try this worker's share of the nonce space in order instead of
+    /// random nonces
+    pub partition: Option<Partition>,
+    /// Width and encoding of the nonces tried
+    pub nonce: NonceFormat,
+    /// Hands out attempt numbers, e.g. starting from a checkpoint, and
+    /// tracks how far they have been tried; the search uses its own, from
+    /// zero, when this is `None`. `max_attempts` counts from where it starts.
+    pub cursor: Option<Arc<AttemptCursor>>,
+    /// Stop every thread before its next attempt once this is set
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Wall-clock budget, checked every [`DURATION_CHECK_INTERVAL`] attempts
+    /// of each thread
+    pub max_duration: Option<Duration>,
+    /// Hash a candidate's preimage a second time and check the difficulty
+    /// again before accepting it
+    pub verify_after: bool,
+    /// Seed the nonce RNG of thread `i` with `seed + i` instead of OS
+    /// entropy; with one thread the nonce sequence is then reproducible
+    pub rng_seed: Option<u64>,
+    /// Nonces thread `i`'s seeded RNG drew in earlier runs, skipped before
+    /// it mines so a resumed run continues the stream; ignored without
+    /// `rng_seed`
+    pub rng_skip: Vec<u64>,
+    /// Pin worker thread `i` to the `i`-th CPU (see [`affinity`]); a thread
+    /// that can't be pinned logs a warning and mines unpinned
+    pub pin_threads: bool,
+    /// Stop once a solution is published to this [`coordination`] file,
+    /// checked every [`STOP_FILE_CHECK_INTERVAL`] attempts of each thread
+    ///
+    /// [`coordination`]: crate::coordination
+    pub stop_file: Option<PathBuf>,
+}
+
+/// One worker's slice of the 64-bit nonce space: the nonces congruent to
+/// `index` modulo `count`, tried in increasing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    index: u64,
+    count: u64,
+}
+
+impl Partition {
+    pub fn new(index: u64, count: u64) -> Result<Self> {
+        if count == 0 {
+            return Err(MinerError::InvalidParams(
+                "--worker-count must be at least 1".to_string(),
+            ));
+        }
+        if index >= count {
+            return Err(MinerError::InvalidParams(format!(
+                "--worker-index {} must be below --worker-count {}",
+                index, count
+            )));
+        }
+        Ok(Self { index, count })
+    }
+
+    pub fn index(self) -> u64 {
+        self.index
+    }
+
+    pub fn count(self) -> u64 {
+        self.count
+    }
+
+    /// Nonce value of this worker's `attempt`-th try
+    pub fn nonce(self, attempt: u64) -> u64 {
+        attempt.wrapping_mul(self.count).wrapping_add(self.index)
+    }
+}
+
+impl MineOptions {
+    /// The target these options mine `difficulty` against, decoded for
+    /// checking raw digests
+    pub fn target(&self, difficulty: &str) -> Result<Target> {
+        match &self.byte_thresholds {
+            Some(thresholds) => Ok(Target::Bytes(thresholds.clone())),
+            None => Target::mask(difficulty),
+        }
+    }
+
+    /// Whether a raw digest meets the target these options mine against
+    pub fn meets_difficulty(&self, hash: &[u8], difficulty: &str) -> bool {
+        self.target(difficulty).is_ok_and(|target| target.meets(hash))
+    }
+
+    /// Probability that a random hash meets `difficulty` under these options
+    pub fn success_probability(&self, difficulty: &str) -> f64 {
+        match &self.byte_thresholds {
+            Some(thresholds) => thresholds.iter().map(|&max| (max as f64 + 1.0) / 256.0).product(),
+            None => mask_probability(difficulty),
+        }
+    }
+
+    /// Whether `preimage` hashes to `hash` again and that hash still meets
+    /// the difficulty; logs a warning when it doesn't
+    pub fn recheck(
+        &self,
+        hasher: &AshMaizeHasher,
+        preimage: &str,
+        hash: &[u8],
+        difficulty: &str,
+    ) -> bool {
+        let rehash = hasher.hash(preimage.as_bytes());
+        if rehash != hash {
+            warn!(
+                "Discarding candidate: preimage rehashed to {}, not {}",
+                hex::encode(&rehash),
+                hex::encode(hash)
+            );
+            return false;
+        }
+        if !self.meets_difficulty(&rehash, difficulty) {
+            warn!(
+                "Discarding candidate: hash {} failed the difficulty recheck against {}",
+                hex::encode(&rehash),
+                difficulty
+            );
+            return false;
+        }
+        true
+    }
+}
+
+impl Default for MineOptions {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10_000_000,
+            progress: None,
+            thread_attempts: None,
+            solutions: None,
+            best: None,
+            max_preimage_len: DEFAULT_MAX_PREIMAGE_LEN,
+            byte_thresholds: None,
+            deadline: None,
+            deadline_margin: None,
+            threads: 1,
+            partition: None,
+            nonce: NonceFormat::default(),
+            cursor: None,
+            cancel: None,
+            max_duration: None,
+            verify_after: false,
+            rng_seed: None,
+            rng_skip: Vec::new(),
+            pin_threads: false,
+            stop_file: None,
+        }
+    }
+}
+
+/// Generous bound on the preimage size; real challenges are ~200 bytes
+pub const DEFAULT_MAX_PREIMAGE_LEN: usize = 4096;
+
+/// Attempts each thread makes between checks of `max_duration`
+pub const DURATION_CHECK_INTERVAL: u64 = 64;
+
+/// Attempts each thread makes between checks of `stop_file`, a fraction
+/// of a second of hashing per read
+pub const STOP_FILE_CHECK_INTERVAL: u64 = 256;
+
+/// Generator random nonces come from, as checkpoints record it: rand 0.8's
+/// `SmallRng`, which is Xoshiro256++ on 64-bit targets (Xoshiro128++ on
+/// others), thread `i`'s seeded through `seed_from_u64(seed + i)`, which
+/// expands the seed with SplitMix64. A checkpoint of draw counts replays
+/// only under the same generator, so a rand upgrade that changes it must
+/// change this name.
+#[cfg(target_pointer_width = "64")]
+pub const NONCE_RNG: &str = "rand-0.8-small-rng-xoshiro256plusplus";
+#[cfg(not(target_pointer_width = "64"))]
+pub const NONCE_RNG: &str = "rand-0.8-small-rng-xoshiro128plusplus";
+
+/// Length of the spec's nonce, in hex characters
+pub const NONCE_LEN: usize = 16;
+
+/// Letter case of the hex digits of generated nonces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NonceEncoding {
+    #[default]
+    HexLower,
+    HexUpper,
+}
+
+/// Width and encoding of generated nonces; the default is the spec's
+/// [`NONCE_LEN`] lowercase hex digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceFormat {
+    bytes: usize,
+    encoding: NonceEncoding,
+}
+
+impl Default for NonceFormat {
+    fn default() -> Self {
+        Self {
+            bytes: NONCE_LEN / 2,
+            encoding: NonceEncoding::HexLower,
+        }
+    }
+}
+
+impl NonceFormat {
+    /// Widest nonce supported, in bytes
+    pub const MAX_BYTES: usize = 32;
+
+    pub fn new(bytes: usize, encoding: NonceEncoding) -> Result<Self> {
+        if bytes == 0 || bytes > Self::MAX_BYTES {
+            return Err(MinerError::InvalidParams(format!(
+                "--nonce-bytes {} must be 1 to {}",
+                bytes,
+                Self::MAX_BYTES
+            )));
+        }
+        Ok(Self { bytes, encoding })
+    }
+
+    pub fn bytes(self) -> usize {
+        self.bytes
+    }
+
+    /// Length of every nonce in this format, in characters
+    pub fn hex_len(self) -> usize {
+        2 * self.bytes
+    }
+}
+
+/// Attempt numbers each thread claims from the shared counter at a time,
+/// so threads don't contend for it on every hash
+pub const NONCE_BATCH: u64 = 64;
+
+/// The preimage of one challenge with the nonce rewritten in place
+///
+/// The nonce is a fixed-width prefix, so only its bytes change between
+/// attempts and the mining loop neither formats nor allocates per hash.
+#[derive(Debug, Clone)]
+pub struct PreimageBuffer {
+    preimage: String,
+    format: NonceFormat,
+}
+
+impl PreimageBuffer {
+    pub fn new(challenge: &Challenge, format: NonceFormat) -> Self {
+        Self {
+            preimage: challenge.preimage(&"0".repeat(format.hex_len())),
+            format,
+        }
+    }
+
+    /// Make `value` the nonce: zero-extended to a wider format, or only its
+    /// low bytes in a narrower one
+    pub fn set_nonce(&mut self, value: u64) {
+        let mut bytes = [0; NonceFormat::MAX_BYTES];
+        let width = self.format.bytes;
+        let be = value.to_be_bytes();
+        let low = width.min(be.len());
+        bytes[width - low..width].copy_from_slice(&be[be.len() - low..]);
+        self.set_nonce_bytes(&bytes[..width]);
+    }
+
+    /// Make big-endian `bytes`, exactly the format's width, the nonce
+    pub fn set_nonce_bytes(&mut self, bytes: &[u8]) {
+        let digits = match self.format.encoding {
+            NonceEncoding::HexLower => b"0123456789abcdef",
+            NonceEncoding::HexUpper => b"0123456789ABCDEF",
+        };
+        let mut nonce = [0; 2 * NonceFormat::MAX_BYTES];
+        for (pair, byte) in nonce.chunks_exact_mut(2).zip(bytes) {
+            pair[0] = digits[(byte >> 4) as usize];
+            pair[1] = digits[(byte & 0xf) as usize];
+        }
+        let len = self.format.hex_len();
+        let nonce = std::str::from_utf8(&nonce[..len]).expect("hex digits are ASCII");
+        // Same length, so this never reallocates
+        self.preimage.replace_range(..len, nonce);
+    }
+
+    pub fn nonce(&self) -> &str {
+        &self.preimage[..self.format.hex_len()]
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.preimage
+    }
+}
 
 /// Mine a solution for a single address
-/// Returns (nonce, preimage, hash) if solution found
+/// The outcome holds the solution if one is found within `max_attempts`
+///
+/// The hasher must have been built from `challenge.no_pre_mine`.
 pub fn mine_solution(
-    address: &str,
-    challenge_id: &str,
-    difficulty: &str,
-    no_pre_mine: &str,
-    latest_submission: &str,
-    no_pre_mine_hour: &str,
-    max_attempts: u64,
-) -> Result<Option<(String, String, String)>> {
-    // Initialize hasher with ROM
-    let hasher = AshMaizeHasher::new(no_pre_mine);
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    options: &MineOptions,
+) -> Result<MineOutcome> {
+    mine_solution_filtered(hasher, challenge, options, |_, _| true)
+}
+
+/// Like [`mine_solution`], but a hash that meets the difficulty only counts
+/// if `accept(hash, nonce)` also returns true; otherwise the search goes on
+///
+/// With several threads `accept` is called from each of them in turn.
+pub fn mine_solution_filtered(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    options: &MineOptions,
+    accept: impl FnMut(&[u8], &str) -> bool + Send,
+) -> Result<MineOutcome> {
+    let accept = Mutex::new(accept);
+    let solution = Mutex::new(None);
+
+    let end = search(hasher, challenge, options, |nonce, preimage, hash, meets| {
+        if !meets {
+            return false;
+        }
+        if options.verify_after && !options.recheck(hasher, preimage, hash, &challenge.difficulty) {
+            return false;
+        }
+        if !(lock(&accept))(hash, nonce) {
+            return false;
+        }
 
-    let mut rng = rand::thread_rng();
+        let mut solution = lock(&solution);
+        // Another thread may have won the race; keep its solution
+        if solution.is_none() {
+            info!("Found solution: nonce={}", nonce);
+            if let Some(found) = &options.solutions {
+                found.fetch_add(1, Ordering::Relaxed);
+            }
+            *solution = Some(Solution {
+                nonce: nonce.to_string(),
+                preimage: preimage.to_string(),
+                hash: hex::encode(hash),
+            });
+        }
+        true
+    })?;
 
-    for attempt in 0..max_attempts {
-        // Generate random nonce (16 hex characters = 8 bytes)
-        let nonce = format!("{:016x}", rng.gen::<u64>());
+    Ok(MineOutcome {
+        solution: solution.into_inner().unwrap_or_else(|e| e.into_inner()),
+        attempts: end.attempts,
+        elapsed: end.elapsed,
+        termination: end.termination,
+        best: end.best,
+    })
+}
 
-        // Construct preimage following the spec
-        let preimage = construct_preimage(
-            &nonce,
-            address,
-            challenge_id,
-            difficulty,
-            no_pre_mine,
-            latest_submission,
-            no_pre_mine_hour,
-        );
+/// Like [`mine_solution`], but mine on past the first solution until
+/// `count` distinct nonces meet the difficulty or the budget runs out
+pub fn mine_solutions(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    options: &MineOptions,
+    count: usize,
+) -> Result<MultiOutcome> {
+    let solutions: Mutex<Vec<Solution>> = Mutex::new(Vec::new());
+
+    let end = search(hasher, challenge, options, |nonce, preimage, hash, meets| {
+        if !meets {
+            return false;
+        }
+        if options.verify_after && !options.recheck(hasher, preimage, hash, &challenge.difficulty) {
+            return false;
+        }
+
+        let mut solutions = lock(&solutions);
+        // Another thread may have found the last one meanwhile
+        if solutions.len() >= count {
+            return true;
+        }
+        if solutions.iter().any(|s| s.nonce == nonce) {
+            return false;
+        }
+        info!("Found solution {} of {}: nonce={}", solutions.len() + 1, count, nonce);
+        if let Some(found) = &options.solutions {
+            found.fetch_add(1, Ordering::Relaxed);
+        }
+        solutions.push(Solution {
+            nonce: nonce.to_string(),
+            preimage: preimage.to_string(),
+            hash: hex::encode(hash),
+        });
+        solutions.len() >= count
+    })?;
+
+    Ok(MultiOutcome {
+        solutions: solutions.into_inner().unwrap_or_else(|e| e.into_inner()),
+        attempts: end.attempts,
+        elapsed: end.elapsed,
+        termination: end.termination,
+        best: end.best,
+    })
+}
+
+/// Spend the whole `max_attempts` budget and keep the nonce whose hash is
+/// numerically smallest, whether or not it meets the difficulty
+pub fn mine_best(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    options: &MineOptions,
+) -> Result<MineOutcome> {
+    let best: Mutex<Option<(Vec<u8>, String, String)>> = Mutex::new(None);
+
+    let end = search(hasher, challenge, options, |nonce, preimage, hash, _| {
+        let mut best = lock(&best);
+        // Digests are fixed-length, so byte order is numeric order
+        if best.as_ref().is_none_or(|(best_hash, _, _)| hash < best_hash.as_slice()) {
+            *best = Some((hash.to_vec(), nonce.to_string(), preimage.to_string()));
+        }
+        false
+    })?;
+    let best = best.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    if let Some((hash, nonce, _)) = &best {
+        info!("Best hash {} (nonce={})", hex::encode(hash), nonce);
+    }
+
+    Ok(MineOutcome {
+        solution: best.map(|(hash, nonce, preimage)| Solution {
+            nonce,
+            preimage,
+            hash: hex::encode(hash),
+        }),
+        attempts: end.attempts,
+        elapsed: end.elapsed,
+        termination: end.termination,
+        best: end.best,
+    })
+}
+
+/// Lock a mutex shared between mining threads, ignoring poisoning: a
+/// panicking thread fails the whole search anyway
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hash random nonces until `on_hash` returns true or the budget runs out
+///
+/// `on_hash` receives the nonce, preimage and raw digest of every attempt,
+/// and whether the digest meets the difficulty, from `options.threads`
+/// worker threads at once. `max_attempts` is shared by all of them, and the
+/// first `true` stops every thread.
+fn search(
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    options: &MineOptions,
+    on_hash: impl Fn(&str, &str, &[u8], bool) -> bool + Sync,
+) -> Result<SearchEnd> {
+    check_preimage_len(challenge, options.nonce, options.max_preimage_len)?;
+    // Decoded once, so no attempt hex-encodes its digest
+    let target = options.target(&challenge.difficulty)?;
+
+    let started = Instant::now();
+    let expiry = match options.deadline_margin {
+        Some(margin) => {
+            let deadline = deadline::parse(&challenge.latest_submission)
+                .map_err(|e| MinerError::InvalidParams(format!("{:#}", e)))?;
+            let left = (deadline - Utc::now()).to_std().unwrap_or_default();
+            Some(started + left.saturating_sub(margin))
+        }
+        None => None,
+    };
+    let cursor = options.cursor.clone().unwrap_or_default();
+    let shared = Shared {
+        end: cursor.next.load(Ordering::Relaxed).saturating_add(options.max_attempts),
+        cursor,
+        hashed: AtomicU64::new(0),
+        stop: AtomicBool::new(false),
+        time_limit: options.max_duration.map(|d| started + d),
+        expiry,
+        best: options.best.clone().unwrap_or_default(),
+    };
+
+    let ends = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.threads.max(1))
+            .map(|index| {
+                let (target, on_hash, shared) = (&target, &on_hash, &shared);
+                scope.spawn(move || {
+                    worker(index, hasher, challenge, target, options, on_hash, shared)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|w| w.join().expect("mining thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let mut found = false;
+    let mut deadline_passed = false;
+    let mut interrupted = false;
+    let mut superseded = false;
+    let mut out_of_time = false;
+    for end in ends {
+        match end? {
+            WorkerEnd::Found => found = true,
+            WorkerEnd::DeadlinePassed => deadline_passed = true,
+            WorkerEnd::Interrupted => interrupted = true,
+            WorkerEnd::Superseded => superseded = true,
+            WorkerEnd::OutOfTime => out_of_time = true,
+            WorkerEnd::Finished => {}
+        }
+    }
+
+    let termination = if found {
+        None
+    } else if interrupted {
+        Some(TerminationReason::Interrupted)
+    } else if superseded {
+        Some(TerminationReason::Superseded)
+    } else if deadline_passed {
+        Some(TerminationReason::DeadlinePassed)
+    } else {
+        out_of_time.then_some(TerminationReason::MaxDuration)
+    };
+    Ok(SearchEnd {
+        attempts: shared.hashed.load(Ordering::Relaxed),
+        elapsed: started.elapsed(),
+        termination,
+        best: shared.best.get(),
+    })
+}
+
+/// Counters and limits shared by the threads of one search
+struct Shared {
+    /// Attempt numbers handed out, counting ones past the budget
+    cursor: Arc<AttemptCursor>,
+    /// First attempt number past the budget
+    end: u64,
+    /// Hashes actually computed
+    hashed: AtomicU64,
+    /// Set once any thread finds a solution, hits the deadline or fails
+    stop: AtomicBool,
+    /// When `max_duration` runs out
+    time_limit: Option<Instant>,
+    /// When `latest_submission`, less `deadline_margin`, passes
+    expiry: Option<Instant>,
+    best: Arc<BestHash>,
+}
+
+/// Why one worker thread stopped
+enum WorkerEnd {
+    Found,
+    DeadlinePassed,
+    Interrupted,
+    Superseded,
+    OutOfTime,
+    /// Budget used up, or another thread stopped the search
+    Finished,
+}
+
+/// Advance `rng` past `draws` random nonces of `nonce`'s width, drawing
+/// exactly what mining them would
+fn skip_nonces(rng: &mut SmallRng, nonce: NonceFormat, draws: u64) {
+    let mut bytes = [0u8; NonceFormat::MAX_BYTES];
+    for _ in 0..draws {
+        if nonce.bytes <= 8 {
+            rng.gen::<u64>();
+        } else {
+            rng.fill(&mut bytes[..nonce.bytes]);
+        }
+    }
+}
+
+fn worker(
+    index: usize,
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    target: &Target,
+    options: &MineOptions,
+    on_hash: &(impl Fn(&str, &str, &[u8], bool) -> bool + Sync),
+    shared: &Shared,
+) -> Result<WorkerEnd> {
+    if options.pin_threads {
+        match affinity::pin_current_thread(index) {
+            Ok(cpu) => debug!("Mining thread {} pinned to CPU {}", index, cpu),
+            Err(e) => warn!("Failed to pin mining thread {}: {:#}", index, e),
+        }
+    }
+    // Nonces only need to be spread out, not unpredictable
+    let mut rng = match options.rng_seed {
+        Some(seed) => {
+            let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(index as u64));
+            if let Some(&draws) = options.rng_skip.get(index) {
+                skip_nonces(&mut rng, options.nonce, draws);
+            }
+            rng
+        }
+        None => SmallRng::from_entropy(),
+    };
+    let thread_attempts = options.thread_attempts.as_deref().and_then(|a| a.get(index));
+    let mut auto_budget = options.deadline.map(AutoBudget::new);
+    let mut tries = 0u64;
+    // Closest distance this thread has seen, so it only locks `shared.best`
+    // when it might improve on it, and the buffer each attempt's goes into
+    let mut best_distance: Option<Vec<u8>> = None;
+    let mut distance = Vec::new();
+    // Attempt numbers claimed but not yet tried, and where they started
+    let mut batch = 0..0;
+    let mut batch_start = None;
+    let mut preimage = PreimageBuffer::new(challenge, options.nonce);
+    let mut nonce_bytes = [0; NonceFormat::MAX_BYTES];
+    let mut hash = [0; DIGEST_SIZE];
+
+    while !shared.stop.load(Ordering::Relaxed) {
+        if options.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+            shared.stop.store(true, Ordering::Relaxed);
+            return Ok(WorkerEnd::Interrupted);
+        }
+        if let Some(limit) = shared.time_limit {
+            if tries.is_multiple_of(DURATION_CHECK_INTERVAL) && Instant::now() >= limit {
+                info!("Stopping: --max-duration reached");
+                shared.stop.store(true, Ordering::Relaxed);
+                return Ok(WorkerEnd::OutOfTime);
+            }
+        }
+        if let Some(expiry) = shared.expiry {
+            if tries.is_multiple_of(DURATION_CHECK_INTERVAL) && Instant::now() >= expiry {
+                info!("Stopping: latest_submission has passed or is within --deadline-margin");
+                shared.stop.store(true, Ordering::Relaxed);
+                return Ok(WorkerEnd::DeadlinePassed);
+            }
+        }
+        if let Some(path) = &options.stop_file {
+            if tries.is_multiple_of(STOP_FILE_CHECK_INTERVAL) && coordination::solved(path) {
+                info!("Stopping: another worker published a solution to {}", path.display());
+                shared.stop.store(true, Ordering::Relaxed);
+                return Ok(WorkerEnd::Superseded);
+            }
+        }
+        tries += 1;
+        if batch.is_empty() {
+            if let Some(start) = batch_start {
+                shared.cursor.finish(start);
+            }
+            batch = shared.cursor.claim();
+            batch_start = Some(batch.start);
+        }
+        let attempt = batch.next().expect("batch was refilled");
+        if attempt >= shared.end {
+            break;
+        }
+
+        let hash_started = Instant::now();
+        if let Some(budget) = &auto_budget {
+            if budget.exhausted(hash_started) {
+                let hashed = shared.hashed.load(Ordering::Relaxed);
+                info!("Stopping after {} attempts: deadline reached", hashed);
+                shared.stop.store(true, Ordering::Relaxed);
+                return Ok(WorkerEnd::DeadlinePassed);
+            }
+        }
+
+        // Generate nonce (16 hex characters = 8 bytes by default); attempt
+        // numbers are unique across threads, so partitioned workers never
+        // repeat one. Random nonces wider than a u64 are random throughout.
+        // The preimage follows the spec
+        match options.partition {
+            Some(partition) => preimage.set_nonce(partition.nonce(attempt)),
+            None if options.nonce.bytes <= 8 => preimage.set_nonce(rng.gen::<u64>()),
+            None => {
+                let nonce_bytes = &mut nonce_bytes[..options.nonce.bytes];
+                rng.fill(nonce_bytes);
+                preimage.set_nonce_bytes(nonce_bytes);
+            }
+        }
+        let nonce = preimage.nonce();
 
         // Hash with AshMaize
-        let hash = hasher.hash(preimage.as_bytes());
-        let hash_hex = hex::encode(&hash);
-
-        // Check if hash meets difficulty
-        if check_difficulty(&hash_hex, difficulty) {
-            info!(
-                "Found solution after {} attempts: nonce={}",
-                attempt + 1, nonce
-            );
-            return Ok(Some((nonce, preimage, hash_hex)));
+        hasher.hash_into(preimage.as_str().as_bytes(), &mut hash);
+        if let Some(budget) = &mut auto_budget {
+            budget.record(hash_started.elapsed());
+        }
+
+        shared.hashed.fetch_add(1, Ordering::Relaxed);
+        if let Some(progress) = &options.progress {
+            progress.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(attempts) = thread_attempts {
+            attempts.fetch_add(1, Ordering::Relaxed);
+        }
+        target.distance_into(&hash, &mut distance);
+        if best_distance.as_ref().is_none_or(|best| distance < *best) {
+            shared.best.offer(&distance, || Solution {
+                nonce: nonce.to_string(),
+                preimage: preimage.as_str().to_string(),
+                hash: hex::encode(hash),
+            });
+            best_distance = Some(distance.clone());
+        }
+
+        if on_hash(nonce, preimage.as_str(), &hash, target.meets(&hash)) {
+            shared.stop.store(true, Ordering::Relaxed);
+            return Ok(WorkerEnd::Found);
         }
 
         // Log progress every 100k attempts
-        if attempt > 0 && attempt % 100_000 == 0 {
-            debug!("{} attempts...", attempt);
+        if attempt > 0 && attempt.is_multiple_of(100_000) {
+            match &auto_budget {
+                Some(budget) => debug!(
+                    "{} attempts, about {} more before the deadline...",
+                    attempt,
+                    budget.remaining(Instant::now())
+                ),
+                None => debug!("{} attempts...", attempt),
+            }
         }
     }
 
-    Ok(None)
+    Ok(WorkerEnd::Finished)
+}
+
+/// Reject challenges whose preimage would exceed `max_len` bytes
+///
+/// Nonces have a fixed length, so every preimage of a challenge is the same
+/// size and one check covers the whole run.
+pub fn check_preimage_len(challenge: &Challenge, nonce: NonceFormat, max_len: usize) -> Result<()> {
+    let len = nonce.hex_len()
+        + challenge.address.len()
+        + challenge.challenge_id.len()
+        + challenge.difficulty.len()
+        + challenge.no_pre_mine.len()
+        + challenge.latest_submission.len()
+        + challenge.no_pre_mine_hour.len();
+    if len > max_len {
+        return Err(MinerError::PreimageTooLong { len, max: max_len });
+    }
+    Ok(())
+}
+
+/// Reject a mask no hash could be checked against, rather than mining
+/// the whole budget for nothing
+fn check_difficulty_mask(difficulty: &str) -> Result<()> {
+    if difficulty.is_empty() || difficulty.len() > 2 * DIGEST_SIZE {
+        return Err(MinerError::InvalidDifficulty(format!(
+            "difficulty '{}' must be 1 to {} hex digits",
+            difficulty,
+            2 * DIGEST_SIZE
+        )));
+    }
+    if !difficulty.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(MinerError::InvalidHex {
+            field: "difficulty",
+            value: difficulty.to_string(),
+        });
+    }
+    Ok(())
 }
 
 /// Construct preimage following the Scavenger Mine spec
-fn construct_preimage(
+///
+/// Every field is concatenated as the text it arrives as. In particular
+/// `no_pre_mine` goes in as its 64 hex digits, not the 32 bytes they
+/// decode to, matching the spec's example preimage; only the ROM may be
+/// seeded from decoded bytes (see [`AshMaizeHasher::from_seed_bytes`]).
+///
+/// [`AshMaizeHasher::from_seed_bytes`]: crate::hasher::AshMaizeHasher::from_seed_bytes
+pub fn construct_preimage(
     nonce: &str,
     address: &str,
     challenge_id: &str,
@@ -73,6 +988,141 @@ fn construct_preimage(
     )
 }
 
+/// How a hash is compared against the difficulty string
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DifficultyMode {
+    /// Every set bit of the hash prefix must also be set in the mask
+    #[default]
+    Mask,
+    /// Each hash byte must be at most the threshold at the same position
+    Bytes,
+}
+
+impl DifficultyMode {
+    /// One-line summary for listings
+    pub fn description(self) -> &'static str {
+        match self {
+            DifficultyMode::Mask => {
+                "Hex mask; the hash prefix of the same length must satisfy (hash | mask) == mask"
+            }
+            DifficultyMode::Bytes => {
+                "JSON array of per-byte maxima; hash byte i must be <= entry i"
+            }
+        }
+    }
+
+    /// Example difficulty value accepted by the mode
+    pub fn example(self) -> &'static str {
+        match self {
+            DifficultyMode::Mask => "000FFFFF",
+            DifficultyMode::Bytes => "[0, 15, 255]",
+        }
+    }
+
+    /// Check if a hex hash meets the difficulty under this mode
+    pub fn check(self, hash_hex: &str, difficulty: &str) -> bool {
+        match self {
+            DifficultyMode::Mask => check_difficulty(hash_hex, difficulty),
+            DifficultyMode::Bytes => match (hex::decode(hash_hex), parse_byte_thresholds(difficulty)) {
+                (Ok(hash), Ok(thresholds)) => check_byte_thresholds(&hash, &thresholds),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Probability that a random hash meets a hex mask: each digit passes
+/// with 2^(set bits) in 16, and a digit that isn't hex never does
+pub fn mask_probability(difficulty: &str) -> f64 {
+    difficulty
+        .chars()
+        .map(|d| match d.to_digit(16) {
+            Some(d) => (1u32 << d.count_ones()) as f64 / 16.0,
+            None => 0.0,
+        })
+        .product()
+}
+
+/// Hex digits in a challenge difficulty, e.g. `000FFFFF`
+pub const DIFFICULTY_HEX_LEN: usize = 8;
+
+/// Hex mask equivalent to requiring `bits` leading zero bits, e.g.
+/// `000FFFFF` for 12
+///
+/// The mask is [`DIFFICULTY_HEX_LEN`] digits, or as many as `bits` needs
+/// beyond that. It goes into the preimage like any other difficulty.
+pub fn difficulty_mask_for_bits(bits: u32) -> Result<String> {
+    let max_bits = 8 * DIGEST_SIZE as u32;
+    if bits > max_bits {
+        return Err(MinerError::InvalidDifficulty(format!(
+            "{} leading zero bits requested, but digests are only {} bits",
+            bits, max_bits
+        )));
+    }
+    let len = DIFFICULTY_HEX_LEN.max(bits.div_ceil(4) as usize);
+    let mut mask = "0".repeat(bits as usize / 4);
+    if !bits.is_multiple_of(4) {
+        mask.push_str(&format!("{:X}", 0xF >> (bits % 4)));
+    }
+    while mask.len() < len {
+        mask.push('F');
+    }
+    Ok(mask)
+}
+
+/// Parse a per-byte threshold array such as `[0, 15, 255]`
+pub fn parse_byte_thresholds(json: &str) -> Result<Vec<u8>> {
+    let thresholds: Vec<u8> = serde_json::from_str(json).map_err(|e| {
+        MinerError::InvalidDifficulty(format!(
+            "Byte thresholds must be a JSON array of 0-255: {}",
+            e
+        ))
+    })?;
+    if thresholds.is_empty() {
+        return Err(MinerError::InvalidDifficulty(
+            "Byte thresholds must not be empty".to_string(),
+        ));
+    }
+    if thresholds.len() > DIGEST_SIZE {
+        return Err(MinerError::InvalidDifficulty(format!(
+            "{} byte thresholds given, but digests are only {} bytes",
+            thresholds.len(),
+            DIGEST_SIZE
+        )));
+    }
+    Ok(thresholds)
+}
+
+/// Check every leading hash byte against its threshold
+///
+/// A hash shorter than the thresholds fails rather than passing on the
+/// bytes it has.
+fn check_byte_thresholds(hash: &[u8], thresholds: &[u8]) -> bool {
+    hash.len() >= thresholds.len() && hash.iter().zip(thresholds).all(|(byte, max)| byte <= max)
+}
+
+/// Listing entry describing a difficulty mode
+#[derive(Debug, Serialize)]
+pub struct DifficultyModeInfo {
+    pub mode: String,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+/// Describe every supported difficulty mode
+pub fn difficulty_modes() -> Vec<DifficultyModeInfo> {
+    DifficultyMode::value_variants()
+        .iter()
+        .filter_map(|&mode| {
+            Some(DifficultyModeInfo {
+                mode: mode.to_possible_value()?.get_name().to_string(),
+                description: mode.description(),
+                example: mode.example(),
+            })
+        })
+        .collect()
+}
+
 /// Check if hash meets difficulty using bitwise OR check
 /// 
 /// This matches the browser implementation:
@@ -80,27 +1130,92 @@ fn construct_preimage(
 /// 
 /// This checks if all bits in hash_value are also set in diff_value
 fn check_difficulty(hash_hex: &str, difficulty: &str) -> bool {
-    // Take prefix of hash matching difficulty length
-    let hash_prefix = &hash_hex[..difficulty.len().min(hash_hex.len())];
-    
-    // Parse as hex integers
-    let hash_value = match u128::from_str_radix(hash_prefix, 16) {
-        Ok(v) => v,
-        Err(_) => return false,
-    };
-    
-    let diff_value = match u128::from_str_radix(difficulty, 16) {
-        Ok(v) => v,
-        Err(_) => return false,
-    };
-    
-    // Bitwise OR check (hash is subset of difficulty's bits)
-    (hash_value | diff_value) == diff_value
+    // Compare digit by digit, so any length works, odd ones included
+    if hash_hex.len() < difficulty.len() {
+        return false;
+    }
+    hash_hex.chars().zip(difficulty.chars()).all(|(h, d)| {
+        match (h.to_digit(16), d.to_digit(16)) {
+            // Bitwise OR check (hash is subset of difficulty's bits)
+            (Some(h), Some(d)) => (h | d) == d,
+            _ => false,
+        }
+    })
+}
+
+/// A difficulty decoded for checking raw digests, so the mining loop never
+/// hex-encodes a hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Hex mask as bytes; an odd trailing digit becomes the high nibble of
+    /// the last byte, with every low bit allowed
+    Mask(Vec<u8>),
+    /// Per-byte maxima (see [`DifficultyMode::Bytes`])
+    Bytes(Vec<u8>),
+}
+
+impl Target {
+    /// Decode a hex mask, rejecting one no hash could be checked against
+    pub fn mask(difficulty: &str) -> Result<Self> {
+        check_difficulty_mask(difficulty)?;
+        let digits = if difficulty.len() % 2 == 1 {
+            format!("{}F", difficulty)
+        } else {
+            difficulty.to_string()
+        };
+        let mask = hex::decode(&digits).map_err(|_| MinerError::InvalidHex {
+            field: "difficulty",
+            value: difficulty.to_string(),
+        })?;
+        Ok(Target::Mask(mask))
+    }
+
+    /// Whether `hash` meets the target, as [`check_difficulty`] decides for
+    /// its hex form; a hash shorter than the target fails
+    pub fn meets(&self, hash: &[u8]) -> bool {
+        match self {
+            Target::Mask(mask) => {
+                hash.len() >= mask.len() && hash.iter().zip(mask).all(|(h, m)| (h | m) == *m)
+            }
+            Target::Bytes(thresholds) => check_byte_thresholds(hash, thresholds),
+        }
+    }
+
+    /// How far `hash` is from meeting the target, comparable between the
+    /// digests of one challenge: all zeros when it meets it, smaller when
+    /// closer
+    ///
+    /// For a mask, each byte of the hash prefix with only the bits the mask
+    /// doesn't allow; for byte thresholds, how far each byte is over its
+    /// maximum. Either way the leading bytes weigh most.
+    pub fn distance(&self, hash: &[u8]) -> Vec<u8> {
+        let mut distance = Vec::new();
+        self.distance_into(hash, &mut distance);
+        distance
+    }
+
+    /// [`distance`](Self::distance) into a buffer the caller reuses
+    pub fn distance_into(&self, hash: &[u8], distance: &mut Vec<u8>) {
+        distance.clear();
+        let len = match self {
+            Target::Mask(mask) => {
+                distance.extend(hash.iter().zip(mask).map(|(h, m)| h & !m));
+                mask.len()
+            }
+            Target::Bytes(thresholds) => {
+                distance.extend(hash.iter().zip(thresholds).map(|(h, max)| h.saturating_sub(*max)));
+                thresholds.len()
+            }
+        };
+        // A hash too short to check is as far off as it gets
+        distance.resize(len, 0xFF);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vectors::test_challenge;
 
     #[test]
     fn test_preimage_construction() {
@@ -119,6 +1234,32 @@ mod tests {
         assert!(preimage.contains("**D07C10"));
     }
 
+    #[test]
+    fn test_preimage_buffer() {
+        let challenge = test_challenge("000FFFFF");
+        // The spec's 16 lowercase hex digits by default
+        let mut buffer = PreimageBuffer::new(&challenge, NonceFormat::default());
+        for value in [0x0019c96b6a30ee38, 0, 7, u64::MAX] {
+            buffer.set_nonce(value);
+            let nonce = format!("{:016x}", value);
+            assert_eq!(buffer.nonce(), nonce);
+            assert_eq!(buffer.as_str(), challenge.preimage(&nonce));
+        }
+
+        let wide = NonceFormat::new(12, NonceEncoding::HexUpper).unwrap();
+        let mut buffer = PreimageBuffer::new(&challenge, wide);
+        buffer.set_nonce(0x0019c96b6a30ee38);
+        assert_eq!(buffer.nonce(), "000000000019C96B6A30EE38");
+        assert!(buffer.as_str().ends_with("509681483"));
+        let narrow = NonceFormat::new(2, NonceEncoding::HexLower).unwrap();
+        let mut buffer = PreimageBuffer::new(&challenge, narrow);
+        buffer.set_nonce(0x0019c96b6a30ee38);
+        assert_eq!(buffer.as_str(), challenge.preimage("ee38"));
+
+        assert!(NonceFormat::new(0, NonceEncoding::HexLower).is_err());
+        assert!(NonceFormat::new(NonceFormat::MAX_BYTES + 1, NonceEncoding::HexLower).is_err());
+    }
+
     #[test]
     fn test_difficulty_check() {
         // Hash that meets difficulty
@@ -130,5 +1271,562 @@ mod tests {
         // Edge cases
         assert!(check_difficulty("00000000", "FFFFFFFF"));
         assert!(check_difficulty("000FFFFF", "000FFFFF"));
+        assert!(!check_difficulty("000", "000FFFFF"));
+        assert!(!check_difficulty("00z694200fb04137", "000FFFFF"));
+
+        // 64 digits, twice what a u128 holds; only the last ones differ
+        let difficulty = format!("{}0ff", "0".repeat(61));
+        let hash = format!("{}0a5{}", "0".repeat(61), "f".repeat(64));
+        assert!(check_difficulty(&hash, &difficulty));
+        let hash = format!("{}1a5{}", "0".repeat(61), "f".repeat(64));
+        assert!(!check_difficulty(&hash, &difficulty));
+        assert!(check_difficulty(&"0".repeat(128), &"f".repeat(128)));
+
+        // Odd lengths compare the half byte too
+        assert!(check_difficulty("0006", "00F"));
+        assert!(!check_difficulty("0106", "00F"));
+
+        // Letter case matters on neither side, so --hash-case and a
+        // lower-case difficulty from the server can't change the outcome
+        for (hash, difficulty) in [
+            ("000a9b2c", "000FFFFF"),
+            ("000A9B2C", "000FFFFF"),
+            ("000a9b2c", "000fffff"),
+            ("000A9B2C", "000fffff"),
+            ("000a9B2c", "000fFfFf"),
+        ] {
+            assert!(check_difficulty(hash, difficulty), "{} {}", hash, difficulty);
+            assert!(DifficultyMode::Mask.check(hash, difficulty));
+        }
+        assert!(check_difficulty("00BEEF", "00fEEF"));
+        assert!(!check_difficulty("00BEEF", "00bEEE"));
+        assert!(!check_difficulty("00beef", "00BEEE"));
+        assert!(DifficultyMode::Bytes.check("0A0F", "[10, 15]"));
+
+        // The decoded target the mining loop uses agrees with the hex check
+        for (hash, difficulty) in [
+            ("000694200fb04137", "000FFFFF"),
+            ("ffffffff", "000FFFFF"),
+            ("0006", "00F"),
+            ("0106", "00F"),
+            ("000f", "000FFFFF"),
+            ("00beef", "00bEEE"),
+        ] {
+            let target = Target::mask(difficulty).unwrap();
+            let meets = target.meets(&hex::decode(hash).unwrap());
+            assert_eq!(meets, check_difficulty(hash, difficulty), "{} {}", hash, difficulty);
+        }
+        assert!(Target::mask("00G").is_err());
+        assert!(Target::mask("").is_err());
+    }
+
+    #[test]
+    fn test_difficulty_mask_for_bits() {
+        assert_eq!(difficulty_mask_for_bits(12).unwrap(), "000FFFFF");
+        assert_eq!(difficulty_mask_for_bits(0).unwrap(), "FFFFFFFF");
+        assert_eq!(difficulty_mask_for_bits(6).unwrap(), "03FFFFFF");
+        assert_eq!(difficulty_mask_for_bits(37).unwrap(), "0000000007");
+        assert_eq!(difficulty_mask_for_bits(512).unwrap(), "0".repeat(128));
+        assert!(difficulty_mask_for_bits(513).is_err());
+
+        // The mask accepts exactly the hashes with that many leading zero bits
+        let leading_zeros = |hash: &str| {
+            let value = u32::from_str_radix(&hash[..8], 16).unwrap();
+            value.leading_zeros()
+        };
+        let hashes = ["000694200fb04137", "000FFFFF", "00100000", "0007ffff", "FFFFFFFF"];
+        for bits in [3, 11, 12, 13] {
+            let mask = difficulty_mask_for_bits(bits).unwrap();
+            for hash in hashes {
+                assert_eq!(check_difficulty(hash, &mask), leading_zeros(hash) >= bits, "{}", hash);
+            }
+        }
+        let mask = difficulty_mask_for_bits(12).unwrap();
+        for hash in hashes {
+            assert_eq!(check_difficulty(hash, &mask), check_difficulty(hash, "000FFFFF"));
+        }
+    }
+
+    #[test]
+    fn test_mask_probability() {
+        assert_eq!(mask_probability("FFFFFFFF"), 1.0);
+        assert_eq!(mask_probability("000FFFFF"), 1.0 / 4096.0);
+        assert_eq!(mask_probability("7FFFFFFF"), 0.5);
+        assert_eq!(mask_probability("0G"), 0.0);
+
+        let options = MineOptions {
+            byte_thresholds: Some(vec![0, 127]),
+            ..MineOptions::default()
+        };
+        assert_eq!(options.success_probability("FFFFFFFF"), 1.0 / 512.0);
+    }
+
+    #[test]
+    fn test_difficulty_mode_examples() {
+        // Every listed example must accept an all-zero hash
+        let zero_hash = "0".repeat(128);
+        for info in difficulty_modes() {
+            let mode = DifficultyMode::from_str(&info.mode, false).unwrap();
+            assert!(mode.check(&zero_hash, info.example), "{}", info.mode);
+        }
+    }
+
+    #[test]
+    fn test_byte_thresholds() {
+        let thresholds = parse_byte_thresholds("[0, 15, 255, 128]").unwrap();
+        assert_eq!(thresholds, [0x00, 0x0f, 0xff, 0x80]);
+
+        assert!(check_byte_thresholds(&[0x00, 0x0f, 0xff, 0x80, 0xff], &thresholds));
+        assert!(check_byte_thresholds(&[0x00, 0x00, 0x00, 0x00], &thresholds));
+        assert!(!check_byte_thresholds(&[0x01, 0x00, 0x00, 0x00], &thresholds));
+        assert!(!check_byte_thresholds(&[0x00, 0x10, 0x00, 0x00], &thresholds));
+        assert!(!check_byte_thresholds(&[0x00, 0x00, 0x00, 0x81], &thresholds));
+        assert!(!check_byte_thresholds(&[0x00, 0x00], &thresholds));
+
+        // Unlike the mask, thresholds are orderings, not bit subsets
+        assert!(DifficultyMode::Bytes.check("0e", "[15]"));
+        assert!(!DifficultyMode::Mask.check("0e", "01"));
+
+        let options = MineOptions {
+            byte_thresholds: Some(thresholds),
+            ..MineOptions::default()
+        };
+        assert!(options.meets_difficulty(&[0x00, 0x0e, 0xff, 0x7f], "ignored"));
+
+        assert!(parse_byte_thresholds("[]").is_err());
+        assert!(parse_byte_thresholds("[256]").is_err());
+        assert!(parse_byte_thresholds("\"000FFFFF\"").is_err());
+        assert!(parse_byte_thresholds(&format!("{:?}", [0u8; 65])).is_err());
+    }
+
+    #[test]
+    fn test_post_filter() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash meets this, so only the filter decides
+        let challenge = test_challenge("FFFFFFFF");
+        let options = MineOptions {
+            max_attempts: 50,
+            ..MineOptions::default()
+        };
+
+        let mut calls = 0;
+        let outcome = mine_solution_filtered(&hasher, &challenge, &options, |hash, _| {
+            calls += 1;
+            calls > 5 && hash[DIGEST_SIZE - 1] % 2 == 0
+        })
+        .unwrap();
+        let solution = outcome.solution.unwrap();
+        assert!(outcome.attempts > 5);
+        assert_eq!(outcome.attempts, calls);
+        assert_eq!(hex::decode(&solution.hash).unwrap()[DIGEST_SIZE - 1] % 2, 0);
+
+        let outcome = mine_solution_filtered(&hasher, &challenge, &options, |_, nonce| {
+            nonce.starts_with("zz")
+        })
+        .unwrap();
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.attempts, 50);
+    }
+
+    #[test]
+    fn test_recheck() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let hash = hasher.hash(b"preimage");
+        let options = MineOptions::default();
+
+        assert!(options.recheck(&hasher, "preimage", &hash, "FFFFFFFF"));
+        // A digest the preimage doesn't produce is caught
+        assert!(!options.recheck(&hasher, "preimage", &[0; DIGEST_SIZE], "FFFFFFFF"));
+        // As is a hash that never met the difficulty
+        let strict = "0".repeat(2 * DIGEST_SIZE);
+        assert!(!options.recheck(&hasher, "preimage", &hash, &strict));
+
+        let challenge = test_challenge("FFFFFFFF");
+        let options = MineOptions {
+            max_attempts: 1,
+            verify_after: true,
+            ..MineOptions::default()
+        };
+        let solution = mine_solution(&hasher, &challenge, &options).unwrap().solution.unwrap();
+        assert_eq!(hex::encode(hasher.hash(solution.preimage.as_bytes())), solution.hash);
+    }
+
+    #[test]
+    fn test_mine_solutions() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash is a solution
+        let challenge = test_challenge("FFFFFFFF");
+        let options = MineOptions {
+            max_attempts: 4,
+            ..MineOptions::default()
+        };
+
+        // Mines on past the first solution, then stops at the count
+        let outcome = mine_solutions(&hasher, &challenge, &options, 3).unwrap();
+        assert_eq!(outcome.solutions.len(), 3);
+        assert_eq!(outcome.attempts, 3);
+        assert_ne!(outcome.solutions[0].nonce, outcome.solutions[1].nonce);
+        assert_ne!(outcome.solutions[1].nonce, outcome.solutions[2].nonce);
+        let outcomes = outcome.outcomes();
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[2].solution.as_ref().unwrap().hash, outcome.solutions[2].hash);
+
+        // The budget still ends the search
+        let options = MineOptions {
+            max_attempts: 2,
+            ..options
+        };
+        let outcome = mine_solutions(&hasher, &challenge, &options, 5).unwrap();
+        assert_eq!(outcome.solutions.len(), 2);
+        assert_eq!(outcome.attempts, 2);
+    }
+
+    #[test]
+    fn test_rng_seed() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash reaches the filter, which records the nonces
+        let challenge = test_challenge("FFFFFFFF");
+        let nonces = |rng_seed| {
+            let options = MineOptions {
+                max_attempts: 3,
+                rng_seed,
+                ..MineOptions::default()
+            };
+            let mut nonces = Vec::new();
+            mine_solution_filtered(&hasher, &challenge, &options, |_, nonce| {
+                nonces.push(nonce.to_string());
+                false
+            })
+            .unwrap();
+            nonces
+        };
+
+        assert_eq!(nonces(Some(42)), nonces(Some(42)));
+        assert_ne!(nonces(Some(42)), nonces(Some(43)));
+    }
+
+    #[test]
+    fn test_rng_skip_continues_the_stream() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash reaches the filter, which records the nonces
+        let challenge = test_challenge("FFFFFFFF");
+        let nonces = |bytes, max_attempts, rng_skip| {
+            let options = MineOptions {
+                max_attempts,
+                nonce: NonceFormat {
+                    bytes,
+                    ..NonceFormat::default()
+                },
+                rng_seed: Some(42),
+                rng_skip,
+                ..MineOptions::default()
+            };
+            let mut nonces = Vec::new();
+            mine_solution_filtered(&hasher, &challenge, &options, |_, nonce| {
+                nonces.push(nonce.to_string());
+                false
+            })
+            .unwrap();
+            nonces
+        };
+
+        // Wide nonces draw several words at a time, and are skipped alike
+        for bytes in [8, 12] {
+            let whole = nonces(bytes, 6, Vec::new());
+            assert_eq!(nonces(bytes, 2, vec![4]), whole[4..], "{} bytes", bytes);
+            assert_eq!(nonces(bytes, 6, vec![0]), whole);
+        }
+    }
+
+    #[test]
+    fn test_best_hash_is_the_closest() {
+        let target = Target::mask("0F0F").unwrap();
+        assert_eq!(target.distance(&[0x1f, 0x3a]), [0x10, 0x30]);
+        assert_eq!(target.distance(&[0x0f, 0x0a]), [0, 0]);
+        assert_eq!(target.distance(&[0x0f]), [0, 0xFF]);
+        assert_eq!(Target::mask("0F0").unwrap().distance(&[0x1f, 0x3a]), [0x10, 0x30]);
+        assert_eq!(Target::Bytes(vec![0x10, 0]).distance(&[0x1f, 0x3a]), [0x0f, 0x3a]);
+
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let challenge = test_challenge("00000000");
+        let options = MineOptions {
+            max_attempts: 20,
+            rng_seed: Some(7),
+            ..MineOptions::default()
+        };
+
+        // Against an all-zero mask the closest hash is the smallest one
+        let miss = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert!(miss.solution.is_none());
+        let best = mine_best(&hasher, &challenge, &options).unwrap().solution.unwrap();
+        assert_eq!(miss.best.unwrap().nonce, best.nonce);
+    }
+
+    #[test]
+    fn test_cancel_stops_the_search() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash reaches the filter, which never accepts
+        let challenge = test_challenge("FFFFFFFF");
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = MineOptions {
+            max_attempts: 1000,
+            threads: 2,
+            cancel: Some(cancel.clone()),
+            ..MineOptions::default()
+        };
+
+        // Cancelled from inside the loop, as a signal handler would
+        let mut calls = 0;
+        let outcome = mine_solution_filtered(&hasher, &challenge, &options, |_, _| {
+            calls += 1;
+            if calls == 3 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+            false
+        })
+        .unwrap();
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.termination, Some(TerminationReason::Interrupted));
+        assert!(outcome.attempts >= 3 && outcome.attempts <= 4);
+
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 0);
+        assert_eq!(outcome.termination, Some(TerminationReason::Interrupted));
+    }
+
+    #[test]
+    fn test_stop_file_supersedes_the_search() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Every hash reaches the filter, which never accepts
+        let challenge = test_challenge("FFFFFFFF");
+        let path = std::env::temp_dir().join(format!("night-cloud-found-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = MineOptions {
+            max_attempts: 1000,
+            stop_file: Some(path.clone()),
+            ..MineOptions::default()
+        };
+
+        // A sibling finishes while this search is running; the check comes
+        // round again after STOP_FILE_CHECK_INTERVAL attempts
+        let mut calls = 0;
+        let outcome = mine_solution_filtered(&hasher, &challenge, &options, |_, _| {
+            calls += 1;
+            if calls == 3 {
+                coordination::publish(&path, &serde_json::json!({})).unwrap();
+            }
+            false
+        })
+        .unwrap();
+        assert_eq!(outcome.termination, Some(TerminationReason::Superseded));
+        assert_eq!(outcome.attempts, STOP_FILE_CHECK_INTERVAL);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let challenge = test_challenge("00000000");
+
+        // Whichever limit comes first ends the run
+        let options = MineOptions {
+            max_attempts: 1000,
+            threads: 2,
+            max_duration: Some(Duration::ZERO),
+            ..MineOptions::default()
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 0);
+        assert_eq!(outcome.termination, Some(TerminationReason::MaxDuration));
+
+        let options = MineOptions {
+            max_attempts: 3,
+            max_duration: Some(Duration::from_secs(3600)),
+            ..MineOptions::default()
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(outcome.termination, None);
+    }
+
+    #[test]
+    fn test_deadline_margin() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let mut challenge = test_challenge("00000000");
+
+        // Already expired: nothing is hashed
+        let options = MineOptions {
+            max_attempts: 1000,
+            threads: 2,
+            deadline_margin: Some(Duration::ZERO),
+            ..MineOptions::default()
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 0);
+        assert_eq!(outcome.termination, Some(TerminationReason::DeadlinePassed));
+
+        // Within the margin counts as expired too
+        let soon = Utc::now() + chrono::Duration::seconds(60);
+        challenge.latest_submission = soon.to_rfc3339();
+        let options = MineOptions {
+            deadline_margin: Some(Duration::from_secs(120)),
+            ..options
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.termination, Some(TerminationReason::DeadlinePassed));
+
+        let options = MineOptions {
+            max_attempts: 3,
+            deadline_margin: Some(Duration::from_secs(10)),
+            ..MineOptions::default()
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(outcome.termination, None);
+
+        challenge.latest_submission = "tomorrow".to_string();
+        assert!(matches!(
+            mine_solution(&hasher, &challenge, &options),
+            Err(MinerError::InvalidParams(_))
+        ));
+    }
+
+    #[test]
+    fn test_threads_share_the_budget() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        // Nothing but an all-zero prefix meets this
+        let mut challenge = test_challenge("00000000");
+        let options = MineOptions {
+            max_attempts: 24,
+            threads: 4,
+            ..MineOptions::default()
+        };
+
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert!(outcome.solution.is_none());
+        assert_eq!(outcome.attempts, 24);
+
+        // The first solution stops every thread well before the budget
+        challenge.difficulty = "FFFFFFFF".to_string();
+        let options = MineOptions {
+            max_attempts: 1000,
+            ..options
+        };
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        let solution = outcome.solution.unwrap();
+        assert!((1..=4).contains(&outcome.attempts), "{}", outcome.attempts);
+        assert_eq!(solution.preimage, challenge.preimage(&solution.nonce));
+        assert_eq!(
+            hex::encode(hasher.hash(solution.preimage.as_bytes())),
+            solution.hash
+        );
+
+        let options = MineOptions {
+            max_attempts: 12,
+            ..options
+        };
+        let outcome = mine_best(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 12);
+        assert!(outcome.solution.is_some());
+    }
+
+    #[test]
+    fn test_partition() {
+        let partition = Partition::new(1, 3).unwrap();
+        let nonces: Vec<u64> = (0..4).map(|a| partition.nonce(a)).collect();
+        assert_eq!(nonces, [1, 4, 7, 10]);
+        assert_eq!(Partition::new(0, 1).unwrap().nonce(42), 42);
+
+        assert!(Partition::new(3, 3).is_err());
+        assert!(Partition::new(0, 0).is_err());
+
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let challenge = test_challenge("FFFFFFFF");
+        let options = MineOptions {
+            max_attempts: 10,
+            partition: Some(Partition::new(2, 5).unwrap()),
+            ..MineOptions::default()
+        };
+        let mut tried = Vec::new();
+        mine_solution_filtered(&hasher, &challenge, &options, |_, nonce| {
+            tried.push(nonce.to_string());
+            tried.len() == 3
+        })
+        .unwrap();
+        assert_eq!(tried, ["0000000000000002", "0000000000000007", "000000000000000c"]);
+    }
+
+    #[test]
+    fn test_cursor_resumes_where_the_search_stopped() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let challenge = test_challenge("FFFFFFFF");
+        let cursor = Arc::new(AttemptCursor::new(100));
+        let options = MineOptions {
+            max_attempts: 100,
+            partition: Some(Partition::new(0, 1).unwrap()),
+            cursor: Some(cursor.clone()),
+            ..MineOptions::default()
+        };
+        let mut tried = Vec::new();
+        let outcome = mine_solution_filtered(&hasher, &challenge, &options, |_, nonce| {
+            tried.push(u64::from_str_radix(nonce, 16).unwrap());
+            false
+        })
+        .unwrap();
+        assert_eq!(outcome.attempts, 100);
+        assert_eq!(tried, (100..200).collect::<Vec<_>>());
+        // The batch the budget ran out in starts again from the top
+        assert_eq!(cursor.resume_point(), 100 + NONCE_BATCH);
+    }
+
+    #[test]
+    fn test_preimage_len_check() {
+        let mut challenge = test_challenge("000FFFFF");
+        let len = challenge.preimage("0019c96b6a30ee38").len();
+        let nonce = NonceFormat::default();
+        assert!(check_preimage_len(&challenge, nonce, len).is_ok());
+        assert!(check_preimage_len(&challenge, nonce, len - 1).is_err());
+        let wide = NonceFormat::new(16, NonceEncoding::HexLower).unwrap();
+        assert!(check_preimage_len(&challenge, wide, len + 16).is_ok());
+        assert!(check_preimage_len(&challenge, wide, len + 15).is_err());
+
+        challenge.challenge_id = "X".repeat(2 * 1024 * 1024);
+        let err = check_preimage_len(&challenge, nonce, DEFAULT_MAX_PREIMAGE_LEN).unwrap_err();
+        assert!(err.to_string().contains("4096-byte limit"));
+        assert!(matches!(err, MinerError::PreimageTooLong { max: 4096, .. }));
+    }
+
+    #[test]
+    fn test_bad_difficulty_is_an_error() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let mut challenge = test_challenge("GGFF");
+        let options = MineOptions::default();
+
+        let err = mine_solution(&hasher, &challenge, &options).unwrap_err();
+        assert!(matches!(err, MinerError::InvalidHex { field: "difficulty", .. }));
+        assert_eq!(err.to_string(), "difficulty 'GGFF' is not valid hex");
+
+        // A stray character fails up front instead of never matching
+        for stray in ["000FFFF ", "000FFFFF\n", "0x0FFFFF", "000FFFF-"] {
+            challenge.difficulty = stray.to_string();
+            let err = mine_solution(&hasher, &challenge, &options).unwrap_err();
+            assert!(
+                matches!(err, MinerError::InvalidHex { field: "difficulty", .. }),
+                "{:?}",
+                stray
+            );
+        }
+
+        challenge.difficulty = String::new();
+        let err = mine_solution(&hasher, &challenge, &options).unwrap_err();
+        assert!(matches!(err, MinerError::InvalidDifficulty(_)));
+
+        // Odd lengths are valid masks, compared down to the half byte, and
+        // can be met
+        challenge.difficulty = "FFF".to_string();
+        let outcome = mine_solution(&hasher, &challenge, &options).unwrap();
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.solution.is_some());
     }
 }
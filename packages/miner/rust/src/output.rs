@@ -0,0 +1,538 @@
+/*!
+# Result output
+
+JSON shapes written to stdout for the TypeScript side to parse.
+*/
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::deadline;
+use crate::miner::{Challenge, MineOutcome, Solution, TerminationReason};
+
+/// Result object printed for a mining run
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum MineResult {
+    Success(MineSuccess),
+    Failure(MineFailure),
+}
+
+impl MineResult {
+    /// Result for a finished run over `challenge`
+    pub fn from_outcome(
+        outcome: &MineOutcome,
+        challenge: &Challenge,
+        hash_case: HashCase,
+        stats: RunStats,
+    ) -> Self {
+        match &outcome.solution {
+            Some(solution) => {
+                let mut success = MineSuccess::new(solution);
+                success.hash = hash_case.apply(&success.hash);
+                success.deadline_margin_ms =
+                    deadline::margin_from_now(&challenge.latest_submission);
+                success.stats = Some(stats);
+                MineResult::Success(success)
+            }
+            None => {
+                let mut failure = MineFailure::new(match outcome.termination {
+                    Some(TerminationReason::Interrupted) => {
+                        format!("Interrupted after {} attempts", outcome.attempts)
+                    }
+                    Some(TerminationReason::MaxDuration) => format!(
+                        "No solution found in {} attempts before --max-duration ran out",
+                        outcome.attempts
+                    ),
+                    Some(TerminationReason::DeadlinePassed) => format!(
+                        "No solution found in {} attempts before latest_submission",
+                        outcome.attempts
+                    ),
+                    Some(TerminationReason::Superseded) => format!(
+                        "Superseded after {} attempts: another worker found a solution",
+                        outcome.attempts
+                    ),
+                    _ => format!("No solution found in {} attempts", outcome.attempts),
+                });
+                failure.termination_reason = outcome.termination;
+                failure.interrupted = outcome.termination == Some(TerminationReason::Interrupted);
+                failure.expired = outcome.termination == Some(TerminationReason::DeadlinePassed);
+                if let Some(best) = &outcome.best {
+                    failure.best_hash = Some(hash_case.apply(&best.hash));
+                    failure.best_nonce = Some(best.nonce.clone());
+                }
+                failure.stats = Some(stats);
+                MineResult::Failure(failure)
+            }
+        }
+    }
+}
+
+/// Version of the result object's shape, in every result as
+/// `schema_version`; bump it whenever a field is added, removed or changes
+/// meaning
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Every top-level field a result object can have, for `--output-fields`
+pub const RESULT_FIELDS: [&str; 15] = [
+    "job_id",
+    "schema_version",
+    "success",
+    "nonce",
+    "preimage",
+    "hash",
+    "deadline_margin_ms",
+    "meets_difficulty",
+    "message",
+    "termination_reason",
+    "interrupted",
+    "expired",
+    "best_hash",
+    "best_nonce",
+    "stats",
+];
+
+/// Keep only the named top-level fields of a serialized result, in the
+/// result's own order, and always `schema_version`
+pub fn project(value: serde_json::Value, fields: &[String]) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .filter(|(key, _)| key == "schema_version" || fields.iter().any(|f| f == key))
+            .collect(),
+        other => other,
+    }
+}
+
+/// Any output record, prefixed with the caller's `--job-id` when one is set
+#[derive(Debug, Serialize)]
+pub struct Tagged<'a, T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<&'a str>,
+    #[serde(flatten)]
+    pub record: T,
+}
+
+/// Attach `job_id` to `record`
+pub fn tagged<T: Serialize>(job_id: Option<&str>, record: T) -> Tagged<'_, T> {
+    Tagged { job_id, record }
+}
+
+/// Output for a found solution
+#[derive(Debug, Serialize)]
+pub struct MineSuccess {
+    /// [`SCHEMA_VERSION`]
+    pub schema_version: u32,
+    pub success: bool,
+    pub nonce: String,
+    pub preimage: String,
+    pub hash: String,
+    /// Time left before `latest_submission` when the solution was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline_margin_ms: Option<i64>,
+    /// Set in best-of mode, where only a lowest hash that meets the target
+    /// is reported as a success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meets_difficulty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<RunStats>,
+}
+
+impl MineSuccess {
+    pub fn new(solution: &Solution) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            success: true,
+            nonce: solution.nonce.clone(),
+            preimage: solution.preimage.clone(),
+            hash: solution.hash.clone(),
+            deadline_margin_ms: None,
+            meets_difficulty: None,
+            stats: None,
+        }
+    }
+}
+
+/// Output when no solution was found or the input was rejected
+#[derive(Debug, Serialize)]
+pub struct MineFailure {
+    /// [`SCHEMA_VERSION`]
+    pub schema_version: u32,
+    pub success: bool,
+    pub message: String,
+    /// Set when the search stopped before using its whole budget
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub termination_reason: Option<TerminationReason>,
+    /// Stopped by Ctrl-C; `stats.attempts` says how far it got
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    /// Stopped because `latest_submission` passed, or came within
+    /// `--deadline-margin`; a solution found now could not be submitted
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub expired: bool,
+    /// Hash that came closest to the difficulty, to judge whether a bigger
+    /// budget is worth it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<RunStats>,
+}
+
+impl MineFailure {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            success: false,
+            message: message.into(),
+            termination_reason: None,
+            interrupted: false,
+            expired: false,
+            best_hash: None,
+            best_nonce: None,
+            stats: None,
+        }
+    }
+}
+
+/// Telemetry attached to every mining result, whatever the outcome
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStats {
+    pub attempts: u64,
+    pub elapsed_ms: u64,
+    /// Hashes per second over the hashing phase
+    pub hashrate: f64,
+    /// Zero when the ROM was reused from a previous challenge
+    pub rom_build_ms: u64,
+    /// Peak resident memory of the process, where the OS reports it
+    pub peak_memory_mb: Option<u64>,
+    pub threads: usize,
+}
+
+impl RunStats {
+    pub fn new(outcome: &MineOutcome, rom_build: Duration, threads: usize) -> Self {
+        let secs = outcome.elapsed.as_secs_f64();
+        Self {
+            attempts: outcome.attempts,
+            elapsed_ms: outcome.elapsed.as_millis() as u64,
+            hashrate: if secs > 0.0 {
+                outcome.attempts as f64 / secs
+            } else {
+                0.0
+            },
+            rom_build_ms: rom_build.as_millis() as u64,
+            peak_memory_mb: peak_memory_mb(),
+            threads,
+        }
+    }
+}
+
+/// One-line account of a run for `--summary`, written to stderr
+#[derive(Debug, Serialize)]
+pub struct RunSummary<'a> {
+    pub event: &'static str,
+    pub success: bool,
+    #[serde(flatten)]
+    pub stats: &'a RunStats,
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn new(success: bool, stats: &'a RunStats) -> Self {
+        Self {
+            event: "summary",
+            success,
+            stats,
+        }
+    }
+}
+
+/// Peak resident set size (VmHWM) from /proc, Linux only
+fn peak_memory_mb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+/// Letter case used for hex digests in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HashCase {
+    #[default]
+    Lower,
+    Upper,
+}
+
+impl HashCase {
+    /// Re-case a hex string
+    pub fn apply(self, hex: &str) -> String {
+        match self {
+            HashCase::Lower => hex.to_ascii_lowercase(),
+            HashCase::Upper => hex.to_ascii_uppercase(),
+        }
+    }
+}
+
+/// How the preimage is written in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PreimageEncoding {
+    /// The preimage string as hashed
+    #[default]
+    Text,
+    /// Lowercase hex of the preimage bytes
+    Hex,
+}
+
+impl PreimageEncoding {
+    /// Encode preimage bytes for output
+    ///
+    /// With `verify`, hex output is decoded again and must reproduce
+    /// `preimage` exactly.
+    pub fn encode(self, preimage: &[u8], verify: bool) -> Result<String> {
+        match self {
+            PreimageEncoding::Text => Ok(String::from_utf8_lossy(preimage).into_owned()),
+            PreimageEncoding::Hex => {
+                let encoded = hex::encode(preimage);
+                if verify {
+                    check_hex_round_trip(&encoded, preimage)?;
+                }
+                Ok(encoded)
+            }
+        }
+    }
+}
+
+/// Fail before mining if the result file couldn't be written: its
+/// directory must already exist
+pub fn check_output_path(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        bail!("Directory {} for --output {} does not exist", dir.display(), path.display());
+    }
+    Ok(())
+}
+
+/// Write `contents` to a temporary file next to `path` and rename it into
+/// place, so a reader polling for `path` never sees a partial file
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    check_output_path(path)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp.{}", std::process::id()));
+    let tmp = Path::new(&tmp);
+
+    let written = fs::write(tmp, contents).and_then(|()| fs::rename(tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(tmp);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Fail unless `encoded` decodes back to exactly `source`
+fn check_hex_round_trip(encoded: &str, source: &[u8]) -> Result<()> {
+    let decoded = hex::decode(encoded)?;
+    if decoded != source {
+        let at = decoded
+            .iter()
+            .zip(source)
+            .position(|(a, b)| a != b)
+            .unwrap_or(decoded.len().min(source.len()));
+        bail!(
+            "Hex preimage does not round-trip: {} bytes decoded, {} expected, first difference at byte {}",
+            decoded.len(),
+            source.len(),
+            at
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors::test_challenge;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("night-cloud-output-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("result.json");
+
+        write_atomic(&path, b"{\"success\": true}\n").unwrap();
+        write_atomic(&path, b"{\"success\": false}\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"success\": false}\n");
+        // Only the result is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let missing = dir.join("missing").join("result.json");
+        let err = write_atomic(&missing, b"{}").unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        assert!(check_output_path(Path::new("result.json")).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_case() {
+        let hash = hex::encode([0x00, 0x06, 0x94, 0x20, 0x0f, 0xb0, 0x41, 0x37]);
+        assert_eq!(HashCase::Lower.apply(&hash), "000694200fb04137");
+        assert_eq!(HashCase::Upper.apply(&hash), "000694200FB04137");
+        assert_eq!(
+            HashCase::Lower.apply("000694200FB04137"),
+            "000694200fb04137"
+        );
+    }
+
+    #[test]
+    fn test_preimage_hex_round_trip() {
+        let every_byte: Vec<u8> = (0..=255).collect();
+        let encoded = PreimageEncoding::Hex.encode(&every_byte, true).unwrap();
+        assert_eq!(encoded.len(), 512);
+        assert!(encoded.starts_with("000102"));
+        assert!(encoded.ends_with("fdfeff"));
+        assert_eq!(hex::decode(&encoded).unwrap(), every_byte);
+
+        assert!(check_hex_round_trip(&encoded[..510], &every_byte).is_err());
+        assert!(check_hex_round_trip("00", &[0x01]).is_err());
+        assert_eq!(PreimageEncoding::Text.encode(b"**D07C10", true).unwrap(), "**D07C10");
+    }
+
+    #[test]
+    fn test_pretty_output_escapes_preimage() {
+        let success = MineSuccess::new(&Solution {
+            nonce: "0019c96b6a30ee38".to_string(),
+            preimage: "addr\"quoted\\slash\n".to_string(),
+            hash: "000694200fb04137".to_string(),
+        });
+        let json = serde_json::to_string_pretty(&MineResult::Success(success)).unwrap();
+        assert_eq!(
+            json,
+            r#"{
+  "schema_version": 2,
+  "success": true,
+  "nonce": "0019c96b6a30ee38",
+  "preimage": "addr\"quoted\\slash\n",
+  "hash": "000694200fb04137"
+}"#
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["preimage"], "addr\"quoted\\slash\n");
+
+        let json = serde_json::to_string_pretty(&MineFailure::new("bad \"input\"")).unwrap();
+        assert_eq!(
+            json,
+            "{\n  \"schema_version\": 2,\n  \"success\": false,\n  \"message\": \"bad \\\"input\\\"\"\n}"
+        );
+    }
+
+    #[test]
+    fn test_project() {
+        let mut success = MineSuccess::new(&Solution {
+            nonce: "0019c96b6a30ee38".to_string(),
+            preimage: "preimage".to_string(),
+            hash: "000694200fb04137".to_string(),
+        });
+        success.deadline_margin_ms = Some(5);
+        success.meets_difficulty = Some(true);
+        let mut failure = MineFailure::new("nope");
+        failure.termination_reason = Some(TerminationReason::DeadlinePassed);
+        failure.interrupted = true;
+        failure.expired = true;
+        failure.best_hash = Some("00f694200fb04137".to_string());
+        failure.best_nonce = Some("0019c96b6a30ee38".to_string());
+
+        // RESULT_FIELDS must cover everything a result can contain
+        for record in [
+            serde_json::to_value(tagged(Some("job-7"), MineResult::Success(success))).unwrap(),
+            serde_json::to_value(tagged(Some("job-7"), MineResult::Failure(failure))).unwrap(),
+        ] {
+            for key in record.as_object().unwrap().keys() {
+                assert!(RESULT_FIELDS.contains(&key.as_str()), "{}", key);
+            }
+
+            let fields = vec!["hash".to_string(), "success".to_string()];
+            let projected = project(record.clone(), &fields);
+            let keys: Vec<&String> = projected.as_object().unwrap().keys().collect();
+            if record["success"] == true {
+                assert_eq!(keys, ["schema_version", "success", "hash"]);
+            } else {
+                assert_eq!(keys, ["schema_version", "success"]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_job_id_tag() {
+        let failure = MineFailure::new("nope");
+        assert_eq!(
+            serde_json::to_string(&tagged(Some("job-7"), &failure)).unwrap(),
+            r#"{"job_id":"job-7","schema_version":2,"success":false,"message":"nope"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&tagged(None, &failure)).unwrap(),
+            r#"{"schema_version":2,"success":false,"message":"nope"}"#
+        );
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let stats = RunStats {
+            attempts: 500,
+            elapsed_ms: 250,
+            hashrate: 2000.0,
+            rom_build_ms: 1500,
+            peak_memory_mb: None,
+            threads: 2,
+        };
+        assert_eq!(
+            serde_json::to_string(&RunSummary::new(true, &stats)).unwrap(),
+            r#"{"event":"summary","success":true,"attempts":500,"elapsed_ms":250,"hashrate":2000.0,"rom_build_ms":1500,"peak_memory_mb":null,"threads":2}"#
+        );
+    }
+
+    #[test]
+    fn test_stats_on_every_outcome() {
+        let challenge = test_challenge("000FFFFF");
+        let miss = MineOutcome {
+            solution: None,
+            attempts: 500,
+            elapsed: Duration::from_millis(250),
+            termination: None,
+            best: None,
+        };
+        let stats = RunStats::new(&miss, Duration::from_secs(3), 1);
+        assert_eq!(stats.hashrate, 2000.0);
+
+        let result = MineResult::from_outcome(&miss, &challenge, HashCase::Lower, stats);
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.starts_with(
+            r#"{"schema_version":2,"success":false,"message":"No solution found in 500 attempts","stats":{"attempts":500,"elapsed_ms":250,"#
+        ));
+        assert!(json.contains(r#""rom_build_ms":3000"#));
+
+        let late = MineOutcome {
+            termination: Some(TerminationReason::DeadlinePassed),
+            ..miss
+        };
+        let stats = RunStats::new(&late, Duration::ZERO, 1);
+        let json = serde_json::to_string(&MineResult::from_outcome(
+            &late,
+            &challenge,
+            HashCase::Lower,
+            stats,
+        ))
+        .unwrap();
+        assert!(json.contains(
+            r#""message":"No solution found in 500 attempts before latest_submission","#
+        ));
+        assert!(json.contains(r#""termination_reason":"deadline_passed","expired":true,"stats""#));
+    }
+}
@@ -0,0 +1,171 @@
+/*!
+# Progress lines
+
+Writes a JSON line with attempts, elapsed time, the current hashrate and
+the closest hash so far to stderr every interval while mining, for
+dashboards that scrape worker output. Stdout stays reserved for the result
+object.
+*/
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::miner::BestHash;
+use crate::output::tagged;
+
+/// One progress line
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Progress {
+    attempts: u64,
+    elapsed_secs: f64,
+    /// Hashes per second since the previous line
+    hashrate: f64,
+}
+
+/// The closest hash so far, as progress lines show it
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct BestSoFar {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best_nonce: Option<String>,
+}
+
+impl BestSoFar {
+    pub(crate) fn of(best: &BestHash) -> Self {
+        match best.get() {
+            Some(best) => Self {
+                best_hash: Some(best.hash),
+                best_nonce: Some(best.nonce),
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// A progress line with the closest hash so far
+#[derive(Debug, Serialize)]
+struct Line {
+    #[serde(flatten)]
+    progress: Progress,
+    #[serde(flatten)]
+    best: BestSoFar,
+}
+
+/// Background thread writing progress lines until dropped
+pub struct ProgressReporter {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    /// Report the value of `attempts` and the closest hash in `best` every
+    /// `interval`; `job_id` is echoed in every line
+    pub fn spawn(
+        attempts: Arc<AtomicU64>,
+        best: Arc<BestHash>,
+        interval: Duration,
+        job_id: Option<String>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(&attempts, &best, interval, job_id.as_deref(), &stop))
+        };
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    attempts: &AtomicU64,
+    best: &BestHash,
+    interval: Duration,
+    job_id: Option<&str>,
+    stop: &AtomicBool,
+) {
+    let started = Instant::now();
+    let mut last = (0, Duration::ZERO);
+
+    loop {
+        thread::park_timeout(interval);
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let now = (attempts.load(Ordering::Relaxed), started.elapsed());
+        let line = Line {
+            progress: progress(last, now),
+            best: BestSoFar::of(best),
+        };
+        last = now;
+
+        // Whole lines only, so concurrent stderr writers can't split one
+        if let Ok(json) = serde_json::to_string(&tagged(job_id, &line)) {
+            let _ = io::stderr()
+                .lock()
+                .write_all(format!("{}\n", json).as_bytes());
+        }
+    }
+}
+
+/// Progress line for `now`, with the hashrate measured since `last`
+pub(crate) fn progress(last: (u64, Duration), now: (u64, Duration)) -> Progress {
+    let secs = now.1.saturating_sub(last.1).as_secs_f64();
+    Progress {
+        attempts: now.0,
+        elapsed_secs: now.1.as_secs_f64(),
+        hashrate: if secs > 0.0 {
+            now.0.saturating_sub(last.0) as f64 / secs
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_uses_the_latest_interval() {
+        let line = progress(
+            (1000, Duration::from_secs(10)),
+            (1600, Duration::from_secs(12)),
+        );
+        assert_eq!(
+            line,
+            Progress {
+                attempts: 1600,
+                elapsed_secs: 12.0,
+                hashrate: 300.0,
+            }
+        );
+        assert_eq!(
+            progress((0, Duration::ZERO), (0, Duration::ZERO)).hashrate,
+            0.0
+        );
+
+        let json = serde_json::to_string(&tagged(Some("job-7"), &line)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"job_id":"job-7","attempts":1600,"elapsed_secs":12.0,"hashrate":300.0}"#
+        );
+    }
+}
@@ -0,0 +1,168 @@
+/*!
+# Resource limits
+
+Caps the process's own address space and CPU time with `setrlimit(2)` so a
+runaway run is stopped by the kernel, and locks the ROM into RAM with
+`mlock(2)`. Linux only; elsewhere asking for either is an error rather than
+being silently ignored.
+
+The limits apply to the whole process and must be set before the ROM is
+built. An address-space limit below the ROM size makes the ROM allocation
+fail, which aborts the process; exceeding the CPU limit delivers SIGXCPU.
+*/
+
+use anyhow::Result;
+
+/// Limits requested on the command line
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub address_space_mb: Option<u64>,
+    pub cpu_seconds: Option<u64>,
+}
+
+impl Limits {
+    /// Apply every requested limit to the current process
+    pub fn apply(self) -> Result<()> {
+        if let Some(mb) = self.address_space_mb {
+            imp::set(imp::Resource::AddressSpace, mb.saturating_mul(1024 * 1024))?;
+        }
+        if let Some(secs) = self.cpu_seconds {
+            imp::set(imp::Resource::CpuTime, secs)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lock `bytes` into RAM so the kernel never swaps them out
+///
+/// Needs CAP_IPC_LOCK or an RLIMIT_MEMLOCK (`ulimit -l`) at least as large as
+/// `bytes`. When the lock is refused the miner carries on unlocked after a
+/// warning. The lock lasts until the memory is freed.
+pub fn lock_memory(bytes: &[u8]) -> Result<()> {
+    imp::lock(bytes)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::{bail, Result};
+    use tracing::{info, warn};
+
+    pub enum Resource {
+        AddressSpace,
+        CpuTime,
+    }
+
+    /// Lower the soft and hard limit of `resource` to `value`
+    pub fn set(resource: Resource, value: u64) -> Result<()> {
+        let (name, resource) = match resource {
+            Resource::AddressSpace => ("RLIMIT_AS", libc::RLIMIT_AS),
+            Resource::CpuTime => ("RLIMIT_CPU", libc::RLIMIT_CPU),
+        };
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+
+        // SAFETY: `limit` is a valid rlimit for the duration of the call
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            bail!(
+                "setrlimit({}, {}) failed: {}",
+                name,
+                value,
+                std::io::Error::last_os_error()
+            );
+        }
+        info!("Set {} to {}", name, value);
+        Ok(())
+    }
+
+    pub fn lock(bytes: &[u8]) -> Result<()> {
+        // SAFETY: the range is a live allocation borrowed for the call
+        if unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } == 0 {
+            info!("Locked {} MiB into RAM", bytes.len() / (1024 * 1024));
+            return Ok(());
+        }
+
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ENOMEM) | Some(libc::EPERM) => {
+                warn!(
+                    "Could not lock {} MiB into RAM ({}); RLIMIT_MEMLOCK is {}. \
+                     Raise `ulimit -l` or grant CAP_IPC_LOCK. Continuing unlocked",
+                    bytes.len() / (1024 * 1024),
+                    err,
+                    memlock_limit()
+                );
+                Ok(())
+            }
+            _ => bail!("mlock failed: {}", err),
+        }
+    }
+
+    fn memlock_limit() -> String {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: `limit` is a valid out-pointer for the duration of the call
+        if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+            return "unknown".to_string();
+        }
+        format_memlock(limit.rlim_cur)
+    }
+
+    /// `ulimit -l` style rendering of a soft RLIMIT_MEMLOCK in bytes
+    pub(super) fn format_memlock(limit: libc::rlim_t) -> String {
+        if limit == libc::RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            format!("{} KiB", limit / 1024)
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use anyhow::{bail, Result};
+
+    pub enum Resource {
+        AddressSpace,
+        CpuTime,
+    }
+
+    pub fn set(_resource: Resource, _value: u64) -> Result<()> {
+        bail!("--rlimit-as-mb and --rlimit-cpu-seconds are only supported on Linux")
+    }
+
+    pub fn lock(_bytes: &[u8]) -> Result<()> {
+        bail!("--mlock-rom is only supported on Linux")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_limits_is_a_no_op() {
+        // Would fail off Linux, or lower this test process's limits, if it
+        // touched anything
+        Limits::default().apply().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memlock_limit_format() {
+        assert_eq!(imp::format_memlock(libc::RLIM_INFINITY), "unlimited");
+        assert_eq!(imp::format_memlock(64 * 1024), "64 KiB");
+        assert_eq!(imp::format_memlock(8 * 1024 * 1024 + 512), "8192 KiB");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_lock_small_buffer() {
+        // Either locked, or refused for lack of RLIMIT_MEMLOCK and let
+        // through with a warning
+        lock_memory(&[0u8; 4096]).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+/*!
+# ROM access benchmark
+
+Measures random-read latency and bandwidth over the ROM bytes, isolated
+from hashing. AshMaize is bound by these reads, so the numbers show
+whether memory tuning (huge pages, NUMA placement) is worth trying.
+*/
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use serde::Serialize;
+
+/// Size of one ROM read, matching ashmaize's dataset access size
+const LINE: usize = 64;
+
+/// Dependent reads timed together; one latency sample per batch
+const BATCH: u64 = 1024;
+
+/// Latency and bandwidth figures for one run
+#[derive(Debug, Serialize)]
+pub struct RomBenchReport {
+    pub rom_bytes: usize,
+    pub reads: u64,
+    pub avg_latency_ns: f64,
+    pub p50_latency_ns: f64,
+    pub p90_latency_ns: f64,
+    pub p99_latency_ns: f64,
+    /// Throughput of independent random 64-byte reads
+    pub bandwidth_mb_s: f64,
+}
+
+/// Run `reads` dependent reads (pointer chasing, so each one waits on
+/// memory) for latency, then `reads` independent reads for bandwidth
+pub fn run(rom: &[u8], reads: u64) -> RomBenchReport {
+    let lines = (rom.len() / LINE) as u64;
+    assert!(lines > 0, "ROM is smaller than one read");
+
+    // Latency: the next index depends on the bytes just read
+    let mut samples = Vec::with_capacity((reads / BATCH + 1) as usize);
+    let mut index = 0u64;
+    let started = Instant::now();
+    let mut done = 0;
+    while done < reads {
+        let batch = BATCH.min(reads - done);
+        let batch_started = Instant::now();
+        for _ in 0..batch {
+            let line = read_line(rom, index);
+            index = mix(line ^ index) % lines;
+        }
+        samples.push(batch_started.elapsed().as_nanos() as f64 / batch as f64);
+        done += batch;
+    }
+    let avg_latency_ns = started.elapsed().as_nanos() as f64 / reads.max(1) as f64;
+    black_box(index);
+
+    // Bandwidth: indices come from a counter, so reads can overlap
+    let started = Instant::now();
+    let mut acc = 0u64;
+    for i in 0..reads {
+        acc ^= read_line(rom, mix(i) % lines);
+    }
+    let secs = started.elapsed().as_secs_f64();
+    black_box(acc);
+
+    samples.sort_by(f64::total_cmp);
+    RomBenchReport {
+        rom_bytes: rom.len(),
+        reads,
+        avg_latency_ns,
+        p50_latency_ns: percentile(&samples, 50.0),
+        p90_latency_ns: percentile(&samples, 90.0),
+        p99_latency_ns: percentile(&samples, 99.0),
+        bandwidth_mb_s: if secs > 0.0 {
+            (reads * LINE as u64) as f64 / secs / (1024.0 * 1024.0)
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Read a full 64-byte line, folded into a u64 so none of it is skipped
+fn read_line(rom: &[u8], line: u64) -> u64 {
+    let start = line as usize * LINE;
+    rom[start..start + LINE]
+        .chunks_exact(8)
+        .fold(0, |acc, c| acc ^ u64::from_le_bytes(c.try_into().unwrap()))
+}
+
+/// SplitMix64 finaliser, to spread indices over the whole ROM
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&samples, 50.0), 50.0);
+        assert_eq!(percentile(&samples, 99.0), 99.0);
+        assert_eq!(percentile(&[7.0], 90.0), 7.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+
+    #[test]
+    fn test_run_small_rom() {
+        let rom: Vec<u8> = (0..64 * 1024).map(|i| i as u8).collect();
+        let report = run(&rom, 5000);
+
+        assert_eq!(report.reads, 5000);
+        assert_eq!(report.rom_bytes, 64 * 1024);
+        assert!(report.p50_latency_ns <= report.p99_latency_ns);
+        assert!(report.bandwidth_mb_s > 0.0);
+    }
+}
@@ -0,0 +1,320 @@
+/*!
+# ROM cache
+
+The ROM is a pure function of the seed and the generation parameters, so
+it is written to a cache directory once and memory-mapped read-only on
+later runs instead of being regenerated.
+
+A cache file is an 80 byte header (magic, ROM size, ROM digest) followed by
+the ROM bytes. Files are written to a temporary name and renamed into
+place, so a crashed writer never leaves a partial file under the real name.
+A file whose header or length doesn't match is treated as stale, removed
+and regenerated: mining against a bad ROM would only produce hashes the
+server rejects. With [`RomCache::with_refresh`] existing files are never
+read: every ROM is generated again and overwrites its file, to get rid of
+one that is valid but was built wrong.
+
+The mapping is read-only and the hasher only ever reads the ROM, so any
+number of miners mapping the same file share one copy in the page cache.
+The process that builds a ROM maps it too once it is written, dropping its
+private copy.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use ashmaize::Rom;
+use cryptoxide::hashing::sha256;
+use memmap2::MmapOptions;
+use tracing::{info, warn};
+
+use crate::error::MinerError;
+use crate::hasher::{AshMaizeHasher, HashParams};
+
+/// Identifies the cache file format; bump it when the layout changes
+const MAGIC: &[u8; 8] = b"NCROM001";
+/// Magic, little-endian u64 ROM size, then the 64 byte ROM digest
+const HEADER_LEN: usize = 8 + 8 + 64;
+
+/// Directory of cached ROMs
+#[derive(Debug, Clone)]
+pub struct RomCache {
+    dir: PathBuf,
+    refresh: bool,
+}
+
+impl RomCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            refresh: false,
+        }
+    }
+
+    /// Ignore existing files when `refresh`, regenerating and overwriting them
+    pub fn with_refresh(self, refresh: bool) -> Self {
+        Self { refresh, ..self }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// `$XDG_CACHE_HOME/night-cloud`, falling back to `~/.cache/night-cloud`
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(base.join("night-cloud"))
+    }
+
+    /// Cache file for `no_pre_mine`, named after the SHA-256 of the seed and
+    /// every parameter the ROM depends on
+    pub fn path(&self, no_pre_mine_hex: &str, params: &HashParams) -> PathBuf {
+        let mut key = Vec::new();
+        key.extend_from_slice(MAGIC);
+        key.extend_from_slice(&(params.pre_size as u64).to_le_bytes());
+        key.extend_from_slice(&(params.rom_size as u64).to_le_bytes());
+        key.extend_from_slice(&(params.mixing_numbers as u64).to_le_bytes());
+        key.extend_from_slice(no_pre_mine_hex.as_bytes());
+        self.dir.join(format!("{}.rom", hex::encode(sha256(&key))))
+    }
+
+    /// Hasher for `no_pre_mine`, mapped from the cache if a valid file is
+    /// there and generated (and written out) otherwise, or always when
+    /// refreshing
+    ///
+    /// Failing to write the cache only costs the next run a rebuild, so it
+    /// is logged rather than returned; failing to allocate the ROM is
+    /// returned.
+    pub fn hasher(
+        &self,
+        no_pre_mine_hex: &str,
+        params: &HashParams,
+    ) -> Result<AshMaizeHasher, MinerError> {
+        let path = self.path(no_pre_mine_hex, params);
+        let cached = if self.refresh {
+            Ok(None)
+        } else {
+            load(&path, params.rom_size)
+        };
+        match cached {
+            Ok(Some(rom)) => {
+                info!("Mapped cached ROM {}", path.display());
+                return Ok(AshMaizeHasher::from_rom(rom, params));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Discarding cached ROM {}: {:#}", path.display(), e);
+                let _ = fs::remove_file(&path);
+            }
+        }
+
+        let hasher = AshMaizeHasher::try_with_params(no_pre_mine_hex, params)?;
+        if let Err(e) = store(&path, &hasher) {
+            warn!("Failed to cache ROM at {}: {:#}", path.display(), e);
+            return Ok(hasher);
+        }
+        // Share the file's pages with other miners rather than keep our own
+        match load(&path, params.rom_size) {
+            Ok(Some(rom)) => Ok(AshMaizeHasher::from_rom(rom, params)),
+            _ => Ok(hasher),
+        }
+    }
+}
+
+/// How often to build a ROM again when it could not be allocated, to ride
+/// out a moment of memory pressure; the default gives up at once
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RomRetry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Hasher for `no_pre_mine`, going through `cache` when there is one and
+/// retrying allocation failures as `retry` says
+pub fn hasher(
+    cache: Option<&RomCache>,
+    no_pre_mine_hex: &str,
+    params: &HashParams,
+    retry: RomRetry,
+) -> Result<AshMaizeHasher, MinerError> {
+    let mut failures = 0;
+    loop {
+        let built = match cache {
+            Some(cache) => cache.hasher(no_pre_mine_hex, params),
+            None => AshMaizeHasher::try_with_params(no_pre_mine_hex, params),
+        };
+        match built {
+            Err(MinerError::RomGenerationFailed(e))
+                if e.kind() == io::ErrorKind::OutOfMemory && failures < retry.retries =>
+            {
+                failures += 1;
+                warn!(
+                    "{}; retrying in {:.1}s ({} of {})",
+                    e,
+                    retry.delay.as_secs_f64(),
+                    failures,
+                    retry.retries
+                );
+                thread::sleep(retry.delay);
+            }
+            built => return built,
+        }
+    }
+}
+
+/// Map the cache file at `path`; `None` when there is no file yet
+fn load(path: &Path, rom_size: usize) -> Result<Option<Rom>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let expected = (HEADER_LEN + rom_size) as u64;
+    let len = file.metadata()?.len();
+    if len != expected {
+        bail!("file is {} bytes, expected {}", len, expected);
+    }
+
+    let mut header = [0; HEADER_LEN];
+    file.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+        bail!("unrecognised header");
+    }
+    let size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    if size != rom_size as u64 {
+        bail!("header says {} ROM bytes, expected {}", size, rom_size);
+    }
+    let digest: [u8; 64] = header[16..].try_into().unwrap();
+
+    // Safety: the file is only ever replaced by rename, never modified in
+    // place, so the mapped pages don't change under us
+    let map = unsafe {
+        MmapOptions::new()
+            .offset(HEADER_LEN as u64)
+            .len(rom_size)
+            .map(&file)?
+    };
+    Ok(Some(Rom::from_parts(digest, map)))
+}
+
+/// Write `hasher`'s ROM to `path` through a temporary file
+fn store(path: &Path, hasher: &AshMaizeHasher) -> Result<()> {
+    let dir = path.parent().context("cache path has no directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let tmp = path.with_extension(format!("rom.tmp.{}", std::process::id()));
+    let written = (|| -> Result<()> {
+        let bytes = hasher.rom_bytes();
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        out.write_all(hasher.rom_digest())?;
+        out.write_all(bytes)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::{SMALL_PRE_SIZE, SMALL_ROM_SIZE};
+
+    #[test]
+    fn test_cached_rom_hashes_identically_and_bad_files_are_rebuilt() {
+        let dir =
+            std::env::temp_dir().join(format!("night-cloud-rom-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = RomCache::new(dir.clone());
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE);
+        let generated = AshMaizeHasher::small("fd651ac2");
+        let path = cache.path("fd651ac2", &params);
+
+        // First use generates and writes, then maps the file like later ones
+        assert!(!generated.rom_is_mapped());
+        assert!(cache.hasher("fd651ac2", &params).unwrap().rom_is_mapped());
+        let expected = (HEADER_LEN + SMALL_ROM_SIZE) as u64;
+        assert_eq!(fs::metadata(&path).unwrap().len(), expected);
+        assert!(load(&path, SMALL_ROM_SIZE).unwrap().is_some());
+        let mapped = cache.hasher("fd651ac2", &params).unwrap();
+        assert_eq!(mapped.rom_bytes(), generated.rom_bytes());
+        assert_eq!(mapped.hash(b"preimage"), generated.hash(b"preimage"));
+
+        // Other seeds and sizes land in other files
+        assert_ne!(cache.path("fd651ac3", &params), path);
+        assert_ne!(cache.path("fd651ac2", &HashParams::default()), path);
+
+        // A truncated file is rejected and replaced
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(expected / 2).unwrap();
+        drop(file);
+        assert!(load(&path, SMALL_ROM_SIZE).is_err());
+        let rebuilt = cache.hasher("fd651ac2", &params).unwrap();
+        assert_eq!(rebuilt.hash(b"preimage"), generated.hash(b"preimage"));
+        assert_eq!(fs::metadata(&path).unwrap().len(), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_refresh_overwrites_a_valid_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "night-cloud-rom-cache-refresh-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, SMALL_ROM_SIZE);
+        let cache = RomCache::new(dir.clone());
+        let path = cache.path("fd651ac2", &params);
+
+        // A well-formed file holding another seed's ROM, as a buggy build
+        // could have left behind, is mapped as is without refreshing
+        let other = AshMaizeHasher::small("fd651ac3");
+        store(&path, &other).unwrap();
+        let stale = cache.hasher("fd651ac2", &params).unwrap();
+        assert_eq!(stale.rom_bytes(), other.rom_bytes());
+        drop(stale);
+
+        let generated = AshMaizeHasher::small("fd651ac2");
+        let refreshed = cache
+            .with_refresh(true)
+            .hasher("fd651ac2", &params)
+            .unwrap();
+        assert!(refreshed.rom_is_mapped());
+        assert_eq!(refreshed.rom_bytes(), generated.rom_bytes());
+        let reloaded = RomCache::new(dir.clone())
+            .hasher("fd651ac2", &params)
+            .unwrap();
+        assert_eq!(reloaded.hash(b"preimage"), generated.hash(b"preimage"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_allocation_failure_is_retried_then_returned() {
+        let params = HashParams::with_sizes(SMALL_PRE_SIZE, isize::MAX as usize & !63);
+        let retry = RomRetry {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        };
+        match hasher(None, "fd651ac2", &params, retry) {
+            Err(MinerError::RomGenerationFailed(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::OutOfMemory)
+            }
+            other => panic!("expected an allocation failure, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,409 @@
+/*!
+# JSON-RPC server
+
+`serve` keeps one ROM resident and answers newline-delimited JSON-RPC 2.0
+requests over TCP. Each connection is answered on its own thread, so a
+long `mine` doesn't hold up the others; requests on one connection are
+answered in order. Concurrent `mine` calls each start their own mining
+threads, and one that needs a different ROM waits until the requests using
+the resident one have finished before replacing it.
+
+Methods:
+- `hash {preimage}`: AshMaize digest of the preimage string
+- `mine {<challenge fields>, max_attempts?}`: mine like a single run,
+  rebuilding the ROM first if `no_pre_mine` differs from the resident one;
+  `max_attempts` may not exceed the server's `--max-request-attempts`
+- `rebuild_rom {seed}`: build the ROM for a new `no_pre_mine`
+- `capabilities`: supported methods and difficulty modes
+*/
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::error::MinerError;
+use crate::hasher::{self, AshMaizeHasher};
+use crate::miner::{self, MineOptions};
+use crate::output::{HashCase, MineResult, RunStats};
+use crate::session::ChallengeRequest;
+use crate::validate;
+
+const METHODS: [&str; 4] = ["hash", "mine", "rebuild_rom", "capabilities"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Server-defined: the method needs a ROM and none has been built yet
+const NO_ROM: i64 = -32000;
+/// Server-defined: the ROM could not be built, e.g. for lack of memory
+const ROM_FAILED: i64 = -32001;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: Option<String>,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// The resident ROM and the seed it was built for
+type Rom = Option<(String, AshMaizeHasher)>;
+
+/// RPC state: the resident ROM and the defaults for `mine`, shared by
+/// every connection
+pub struct Server {
+    rom: RwLock<Rom>,
+    defaults: MineOptions,
+    /// Largest `max_attempts` a `mine` request may ask for
+    attempt_cap: u64,
+    hash_case: HashCase,
+}
+
+impl Server {
+    pub fn new(defaults: MineOptions, attempt_cap: u64, hash_case: HashCase) -> Self {
+        Self {
+            rom: RwLock::new(None),
+            defaults,
+            attempt_cap,
+            hash_case,
+        }
+    }
+
+    /// Build the ROM for `seed`, replacing the resident one
+    ///
+    /// Returns the ROM build time in milliseconds. Waits for requests using
+    /// the resident ROM to finish. If the ROM can't be allocated the server
+    /// is left without one.
+    pub fn rebuild_rom(&self, seed: &str) -> Result<u64, MinerError> {
+        let mut rom = self.rom.write().unwrap_or_else(|e| e.into_inner());
+        build_rom(&mut rom, seed)
+    }
+
+    /// Answer one request line
+    pub fn handle_line(&self, line: &str) -> Response {
+        let request: Request = match serde_json::from_str::<Value>(line) {
+            Err(e) => {
+                return Response::new(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string())))
+            }
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => {
+                    return Response::new(
+                        Value::Null,
+                        Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                    )
+                }
+            },
+        };
+
+        if request.jsonrpc.as_deref() != Some("2.0") {
+            return Response::new(
+                request.id,
+                Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")),
+            );
+        }
+
+        let outcome = self.call(&request.method, &request.params);
+        Response::new(request.id, outcome)
+    }
+
+    fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "hash" => {
+                let preimage = string_param(params, "preimage")?;
+                let rom = self.rom.read().unwrap_or_else(|e| e.into_inner());
+                let (_, hasher) = rom.as_ref().ok_or_else(no_rom)?;
+                let hash = hex::encode(hasher.hash(preimage.as_bytes()));
+                Ok(json!({ "hash": self.hash_case.apply(&hash) }))
+            }
+            "mine" => self.mine(params),
+            "rebuild_rom" => {
+                let seed = string_param(params, "seed")?;
+                validate::hex(seed)
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, format!("seed: {}", e)))?;
+                let rom_build_ms = self.rebuild_rom(seed).map_err(rom_failed)?;
+                Ok(json!({
+                    "seed_fingerprint": hasher::seed_fingerprint(seed),
+                    "rom_build_ms": rom_build_ms,
+                }))
+            }
+            "capabilities" => Ok(json!({
+                "methods": METHODS,
+                "difficulty_modes": miner::difficulty_modes(),
+            })),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    fn mine(&self, params: &Value) -> Result<Value, RpcError> {
+        let problems: Vec<String> = validate::check_params(params)
+            .into_iter()
+            .filter_map(|check| Some(format!("{}: {}", check.field, check.error?)))
+            .collect();
+        if !problems.is_empty() {
+            return Err(RpcError::new(INVALID_PARAMS, problems.join("; ")));
+        }
+        let request: ChallengeRequest = serde_json::from_value(params.clone())
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+        let challenge = request.challenge;
+        let max_attempts = request.max_attempts.unwrap_or(self.defaults.max_attempts);
+        if max_attempts > self.attempt_cap {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!(
+                    "max_attempts: {} exceeds this server's limit of {}",
+                    max_attempts, self.attempt_cap
+                ),
+            ));
+        }
+
+        // Mine under a read lock on the ROM. If it was built for another
+        // seed, replace it under the write lock and look again, since a
+        // request arriving meanwhile may have replaced it once more.
+        let mut rom_build_ms = 0;
+        let outcome = loop {
+            {
+                let rom = self.rom.read().unwrap_or_else(|e| e.into_inner());
+                if let Some((seed, hasher)) = rom.as_ref() {
+                    if *seed == challenge.no_pre_mine {
+                        let options = MineOptions {
+                            max_attempts,
+                            ..self.defaults.clone()
+                        };
+                        break miner::mine_solution(hasher, &challenge, &options)
+                            .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?;
+                    }
+                }
+            }
+            let mut rom = self.rom.write().unwrap_or_else(|e| e.into_inner());
+            if !matches!(rom.as_ref(), Some((seed, _)) if *seed == challenge.no_pre_mine) {
+                rom_build_ms += build_rom(&mut rom, &challenge.no_pre_mine).map_err(rom_failed)?;
+            }
+        };
+        let stats = RunStats::new(
+            &outcome,
+            std::time::Duration::from_millis(rom_build_ms),
+            self.defaults.threads,
+        );
+        let result = MineResult::from_outcome(&outcome, &challenge, self.hash_case, stats);
+        serde_json::to_value(result).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+    }
+}
+
+/// Build the ROM for `seed` into `rom`, freeing the old one first so two
+/// are never resident at once; the build time in milliseconds
+fn build_rom(rom: &mut Rom, seed: &str) -> Result<u64, MinerError> {
+    info!("Building ROM for seed {}", hasher::seed_fingerprint(seed));
+    *rom = None;
+    let started = Instant::now();
+    *rom = Some((seed.to_string(), AshMaizeHasher::try_new(seed)?));
+    Ok(started.elapsed().as_millis() as u64)
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("{} must be a string", name)))
+}
+
+fn no_rom() -> RpcError {
+    RpcError::new(NO_ROM, "No ROM built yet; call rebuild_rom first")
+}
+
+fn rom_failed(e: MinerError) -> RpcError {
+    warn!("{}", e);
+    RpcError::new(ROM_FAILED, e.to_string())
+}
+
+/// Accept connections on `listener`, answering each on its own thread
+///
+/// A connection that fails is logged and dropped; the server keeps going.
+pub fn serve(listener: TcpListener, server: Arc<Server>) -> Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Accept failed: {}", e);
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &server) {
+                warn!("Connection closed with error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, server: &Server) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    info!("RPC client connected: {}", peer);
+    let mut out = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = server.handle_line(&line);
+        writeln!(out, "{}", serde_json::to_string(&response)?)?;
+        out.flush()?;
+    }
+    info!("RPC client disconnected: {}", peer);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(response: &Response) -> Option<i64> {
+        response.error.as_ref().map(|e| e.code)
+    }
+
+    #[test]
+    fn test_request_errors() {
+        let server = Server::new(MineOptions::default(), 100_000_000, HashCase::Lower);
+
+        assert_eq!(
+            error_code(&server.handle_line("{not json")),
+            Some(PARSE_ERROR)
+        );
+        assert_eq!(
+            error_code(&server.handle_line(r#"{"jsonrpc":"2.0","id":1}"#)),
+            Some(INVALID_REQUEST)
+        );
+        assert_eq!(
+            error_code(&server.handle_line(r#"{"id":1,"method":"capabilities"}"#)),
+            Some(INVALID_REQUEST)
+        );
+        assert_eq!(
+            error_code(&server.handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#)),
+            Some(METHOD_NOT_FOUND)
+        );
+
+        // Parameter checks run before anything touches the ROM
+        assert_eq!(
+            error_code(&server.handle_line(r#"{"jsonrpc":"2.0","id":2,"method":"hash"}"#)),
+            Some(INVALID_PARAMS)
+        );
+        assert_eq!(
+            error_code(&server.handle_line(
+                r#"{"jsonrpc":"2.0","id":3,"method":"hash","params":{"preimage":"abc"}}"#
+            )),
+            Some(NO_ROM)
+        );
+        assert_eq!(
+            error_code(&server.handle_line(
+                r#"{"jsonrpc":"2.0","id":4,"method":"rebuild_rom","params":{"seed":"xyz"}}"#
+            )),
+            Some(INVALID_PARAMS)
+        );
+
+        let response = server.handle_line(
+            r#"{"jsonrpc":"2.0","id":5,"method":"mine","params":{"address":"addr1","difficulty":"000FFFFF"}}"#,
+        );
+        let error = response.error.unwrap();
+        assert_eq!(error.code, INVALID_PARAMS);
+        assert!(
+            error.message.contains("challenge_id: missing"),
+            "{}",
+            error.message
+        );
+        assert_eq!(response.id, 5);
+
+        // The attempt cap is checked before the ROM is built
+        let params = json!({
+            "address": "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x",
+            "challenge_id": "**D07C10",
+            "difficulty": "000FFFFF",
+            "no_pre_mine": "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011",
+            "latest_submission": "2025-10-19T08:59:59.000Z",
+            "no_pre_mine_hour": "509681483",
+            "max_attempts": 100_000_001,
+        });
+        let request = json!({"jsonrpc": "2.0", "id": 6, "method": "mine", "params": params});
+        let response = server.handle_line(&request.to_string());
+        let error = response.error.unwrap();
+        assert_eq!(error.code, INVALID_PARAMS);
+        assert!(
+            error.message.contains("limit of 100000000"),
+            "{}",
+            error.message
+        );
+        assert!(server.rom.read().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let server = Server::new(MineOptions::default(), 100_000_000, HashCase::Lower);
+        let response =
+            server.handle_line(r#"{"jsonrpc":"2.0","id":"caps","method":"capabilities"}"#);
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["id"], "caps");
+        assert!(json.get("error").is_none());
+        assert_eq!(
+            json["result"]["methods"].as_array().unwrap().len(),
+            METHODS.len()
+        );
+        assert_eq!(json["result"]["difficulty_modes"][0]["mode"], "mask");
+    }
+}
@@ -0,0 +1,605 @@
+/*!
+# Challenge sessions
+
+Mines a stream of newline-delimited JSON challenges in one long-lived
+process, keeping the ROM alive while `no_pre_mine` stays the same.
+
+The ROM is generated from `no_pre_mine` alone ([`AshMaizeHasher::new`]
+seeds it with nothing else), while the address, challenge id, difficulty,
+deadline and `no_pre_mine_hour` only enter the preimage. Reusing one ROM
+for any number of challenges that share `no_pre_mine` is therefore safe,
+which [`run_addresses`] and [`run_hours`] rely on.
+*/
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::hasher::{self, AshMaizeHasher, HashParams};
+use crate::miner::{self, Challenge, MineOptions, MineOutcome, TerminationReason};
+use crate::output::{tagged, HashCase, MineFailure, MineResult, PreimageEncoding, RunStats};
+use crate::rlimit;
+use crate::rom_cache::{self, RomCache, RomRetry};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SolutionDb;
+use crate::validate;
+
+/// One challenge read from the stream
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    #[serde(flatten)]
+    pub challenge: Challenge,
+    /// Overrides the session's default attempt budget
+    pub max_attempts: Option<u64>,
+}
+
+/// Settings shared by every challenge of a session
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig<'a> {
+    /// Mining options; in a challenge stream, the defaults each line may override
+    pub options: MineOptions,
+    pub rom_cache: Option<RomCache>,
+    pub params: HashParams,
+    pub rom_retry: RomRetry,
+    /// Lock each ROM into RAM once it is built (`--mlock-rom`)
+    pub mlock_rom: bool,
+    pub hash_case: HashCase,
+    pub preimage_encoding: PreimageEncoding,
+    /// Decode hex preimages again and fail unless they round-trip
+    pub verify_preimage_encoding: bool,
+    pub job_id: Option<&'a str>,
+    /// Where found solutions are also inserted
+    #[cfg(feature = "sqlite")]
+    pub solution_db: Option<&'a SolutionDb>,
+}
+
+impl SessionConfig<'_> {
+    /// ROM for `seed`, from the cache or generated, and locked into RAM
+    /// with `mlock_rom`
+    fn hasher(&self, seed: &str) -> Result<AshMaizeHasher> {
+        let hasher =
+            rom_cache::hasher(self.rom_cache.as_ref(), seed, &self.params, self.rom_retry)?;
+        self.lock(&hasher)?;
+        Ok(hasher)
+    }
+
+    /// Lock the ROM of `hasher` into RAM with `mlock_rom`
+    fn lock(&self, hasher: &AshMaizeHasher) -> Result<()> {
+        if self.mlock_rom {
+            rlimit::lock_memory(hasher.rom_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Result for `outcome`, with the preimage in `preimage_encoding`
+    fn result(
+        &self,
+        outcome: &MineOutcome,
+        challenge: &Challenge,
+        stats: RunStats,
+    ) -> Result<MineResult> {
+        let mut result = MineResult::from_outcome(outcome, challenge, self.hash_case, stats);
+        if let MineResult::Success(success) = &mut result {
+            success.preimage = self
+                .preimage_encoding
+                .encode(success.preimage.as_bytes(), self.verify_preimage_encoding)?;
+        }
+        Ok(result)
+    }
+
+    /// Record the solution of `outcome`, if any, in the configured stores
+    fn record(&self, outcome: &MineOutcome, difficulty: &str, stats: &RunStats) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        if let (Some(db), Some(solution)) = (self.solution_db, &outcome.solution) {
+            db.insert(solution, difficulty, stats)?;
+        }
+        #[cfg(not(feature = "sqlite"))]
+        let _ = (outcome, difficulty, stats);
+        Ok(())
+    }
+}
+
+/// Totals written when the stream ends
+#[derive(Debug, Default, Serialize)]
+pub struct SessionSummary {
+    pub challenges: u64,
+    pub solved: u64,
+    pub rejected: u64,
+    pub rom_builds: u64,
+}
+
+/// Mine every challenge in `input`, writing one JSON result line per
+/// challenge to `out` and a `{"summary": ...}` line on EOF or after an
+/// interrupted challenge
+pub fn run(
+    input: impl BufRead,
+    mut out: impl Write,
+    config: &SessionConfig,
+) -> Result<SessionSummary> {
+    let mut summary = SessionSummary::default();
+    // ROM of the previous challenge, keyed by its seed
+    let mut current: Option<(String, AshMaizeHasher)> = None;
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        summary.challenges += 1;
+
+        let request = serde_json::from_str::<ChallengeRequest>(&line)
+            .map_err(anyhow::Error::from)
+            .and_then(|request| validate::challenge(&request.challenge).map(|()| request));
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Rejected challenge line: {:#}", e);
+                summary.rejected += 1;
+                let failure = MineFailure::new(format!("Invalid challenge: {:#}", e));
+                write_failure(&mut out, config.job_id, &failure)?;
+                continue;
+            }
+        };
+        let challenge = request.challenge;
+        let options = MineOptions {
+            max_attempts: request.max_attempts.unwrap_or(config.options.max_attempts),
+            ..config.options.clone()
+        };
+
+        let rom_started = Instant::now();
+        let hasher = match current.take() {
+            Some((seed, hasher)) if seed == challenge.no_pre_mine => hasher,
+            _ => {
+                info!("Building ROM for no_pre_mine {}", challenge.no_pre_mine);
+                summary.rom_builds += 1;
+                let built = rom_cache::hasher(
+                    config.rom_cache.as_ref(),
+                    &challenge.no_pre_mine,
+                    &config.params,
+                    config.rom_retry,
+                );
+                match built {
+                    Ok(hasher) => {
+                        config.lock(&hasher)?;
+                        hasher
+                    }
+                    // Later challenges may need a smaller ROM, or find
+                    // the memory free again
+                    Err(e) => {
+                        warn!("Skipping challenge {}: {:#}", challenge.challenge_id, e);
+                        let failure = MineFailure::new(format!("{:#}", e));
+                        write_failure(&mut out, config.job_id, &failure)?;
+                        continue;
+                    }
+                }
+            }
+        };
+        let rom_build = rom_started.elapsed();
+
+        info!("Challenge: {}", challenge.challenge_id);
+        let outcome = match miner::mine_solution(&hasher, &challenge, &options) {
+            Ok(outcome) => outcome,
+            // E.g. a preimage over --max-preimage-len; the ROM is still good
+            Err(e) => {
+                warn!("Rejected challenge {}: {}", challenge.challenge_id, e);
+                summary.rejected += 1;
+                let failure = MineFailure::new(format!("Invalid challenge: {}", e));
+                write_failure(&mut out, config.job_id, &failure)?;
+                current = Some((challenge.no_pre_mine, hasher));
+                continue;
+            }
+        };
+        if outcome.solution.is_some() {
+            summary.solved += 1;
+        }
+        let stats = RunStats::new(&outcome, rom_build, options.threads);
+        config.record(&outcome, &challenge.difficulty, &stats)?;
+        let result = config.result(&outcome, &challenge, stats)?;
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&tagged(config.job_id, &result))?
+        )?;
+        out.flush()?;
+
+        if outcome.termination == Some(TerminationReason::Interrupted) {
+            break;
+        }
+        current = Some((challenge.no_pre_mine, hasher));
+    }
+
+    let summary_line = tagged(config.job_id, serde_json::json!({ "summary": &summary }));
+    writeln!(out, "{}", serde_json::to_string(&summary_line)?)?;
+    out.flush()?;
+
+    Ok(summary)
+}
+
+/// Write `failure` as one result line, flushed so the caller sees it at once
+fn write_failure(out: &mut impl Write, job_id: Option<&str>, failure: &MineFailure) -> Result<()> {
+    writeln!(out, "{}", serde_json::to_string(&tagged(job_id, failure))?)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Result line for one seed of a seeds-file run
+#[derive(Debug, Serialize)]
+struct SeedResult {
+    seed_fingerprint: String,
+    #[serde(flatten)]
+    result: MineResult,
+}
+
+/// Mine `template` once per seed, substituting each seed as `no_pre_mine`
+/// and building that seed's ROM, writing one JSON line per seed
+pub fn run_seeds(
+    template: &Challenge,
+    seeds: &[String],
+    mut out: impl Write,
+    config: &SessionConfig,
+) -> Result<SessionSummary> {
+    let mut summary = SessionSummary::default();
+
+    for seed in seeds {
+        summary.challenges += 1;
+        let challenge = Challenge {
+            no_pre_mine: seed.clone(),
+            ..template.clone()
+        };
+        let seed_fingerprint = hasher::seed_fingerprint(seed);
+        info!("Seed {}: building ROM", seed_fingerprint);
+
+        let rom_started = Instant::now();
+        let hasher = config.hasher(seed)?;
+        let rom_build = rom_started.elapsed();
+        summary.rom_builds += 1;
+
+        let outcome = miner::mine_solution(&hasher, &challenge, &config.options)?;
+        if outcome.solution.is_some() {
+            summary.solved += 1;
+        }
+        let interrupted = outcome.termination == Some(TerminationReason::Interrupted);
+        let stats = RunStats::new(&outcome, rom_build, config.options.threads);
+        config.record(&outcome, &challenge.difficulty, &stats)?;
+        let line = SeedResult {
+            seed_fingerprint,
+            result: config.result(&outcome, &challenge, stats)?,
+        };
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&tagged(config.job_id, &line))?
+        )?;
+        out.flush()?;
+        if interrupted {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Result line for one address of an addresses-file run
+#[derive(Debug, Serialize)]
+struct AddressResult<'a> {
+    address: &'a str,
+    #[serde(flatten)]
+    result: MineResult,
+}
+
+/// Mine `template` once per address with a single shared ROM, writing one
+/// JSON line per address, until the first solution if `first_success`
+///
+/// Each line is written as soon as its address is done, so a consumer sees
+/// results while later addresses are still mining instead of one array at
+/// the end.
+pub fn run_addresses(
+    template: &Challenge,
+    addresses: &[&String],
+    first_success: bool,
+    mut out: impl Write,
+    config: &SessionConfig,
+) -> Result<SessionSummary> {
+    let mut summary = SessionSummary::default();
+    if addresses.is_empty() {
+        return Ok(summary);
+    }
+
+    let rom_started = Instant::now();
+    let hasher = config.hasher(&template.no_pre_mine)?;
+    let mut rom_build = rom_started.elapsed();
+    summary.rom_builds += 1;
+
+    for address in addresses {
+        summary.challenges += 1;
+        let challenge = Challenge {
+            address: address.to_string(),
+            ..template.clone()
+        };
+
+        info!("Address: {}", address);
+        let outcome = miner::mine_solution(&hasher, &challenge, &config.options)?;
+        if outcome.solution.is_some() {
+            summary.solved += 1;
+        }
+        let interrupted = outcome.termination == Some(TerminationReason::Interrupted);
+        // Only the first address pays for the ROM
+        let rom_build = std::mem::take(&mut rom_build);
+        let stats = RunStats::new(&outcome, rom_build, config.options.threads);
+        config.record(&outcome, &challenge.difficulty, &stats)?;
+        let line = AddressResult {
+            address,
+            result: config.result(&outcome, &challenge, stats)?,
+        };
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&tagged(config.job_id, &line))?
+        )?;
+        out.flush()?;
+        if interrupted || (first_success && outcome.solution.is_some()) {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Most `no_pre_mine_hour` values one `--hours` list may expand to
+pub const MAX_HOURS: usize = 100_000;
+
+/// `no_pre_mine_hour` values to sweep, parsed from a comma-separated list
+/// of numbers and inclusive `first-last` ranges, e.g. `509681483,509681490-509681499`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hours(pub Vec<String>);
+
+impl FromStr for Hours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut hours = Vec::new();
+        for item in s.split(',').map(str::trim) {
+            let (first, last) = item.split_once('-').unwrap_or((item, item));
+            let number = |n: &str| -> Result<u64> {
+                n.trim()
+                    .parse()
+                    .with_context(|| format!("hour '{}' is not a number", n.trim()))
+            };
+            let (first, last) = (number(first)?, number(last)?);
+            if first > last {
+                bail!("hour range '{}' runs backwards", item);
+            }
+            if last - first >= (MAX_HOURS - hours.len()) as u64 {
+                bail!("--hours expands to more than {} values", MAX_HOURS);
+            }
+            hours.extend((first..=last).map(|hour| hour.to_string()));
+        }
+        Ok(Self(hours))
+    }
+}
+
+/// Result line for one hour of an `--hours` sweep
+#[derive(Debug, Serialize)]
+struct HourResult<'a> {
+    no_pre_mine_hour: &'a str,
+    #[serde(flatten)]
+    result: MineResult,
+}
+
+/// Mine `template` once per `no_pre_mine_hour` with a single shared ROM,
+/// writing one JSON line per hour
+pub fn run_hours(
+    template: &Challenge,
+    hours: &[String],
+    mut out: impl Write,
+    config: &SessionConfig,
+) -> Result<SessionSummary> {
+    let mut summary = SessionSummary::default();
+    if hours.is_empty() {
+        return Ok(summary);
+    }
+
+    let rom_started = Instant::now();
+    let hasher = config.hasher(&template.no_pre_mine)?;
+    let mut rom_build = rom_started.elapsed();
+    summary.rom_builds += 1;
+
+    for hour in hours {
+        summary.challenges += 1;
+        let challenge = Challenge {
+            no_pre_mine_hour: hour.clone(),
+            ..template.clone()
+        };
+
+        info!("No pre-mine hour: {}", hour);
+        let outcome = miner::mine_solution(&hasher, &challenge, &config.options)?;
+        if outcome.solution.is_some() {
+            summary.solved += 1;
+        }
+        let interrupted = outcome.termination == Some(TerminationReason::Interrupted);
+        // Only the first hour pays for the ROM
+        let rom_build = std::mem::take(&mut rom_build);
+        let stats = RunStats::new(&outcome, rom_build, config.options.threads);
+        config.record(&outcome, &challenge.difficulty, &stats)?;
+        let line = HourResult {
+            no_pre_mine_hour: hour,
+            result: config.result(&outcome, &challenge, stats)?,
+        };
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&tagged(config.job_id, &line))?
+        )?;
+        out.flush()?;
+        if interrupted {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors::test_challenge;
+
+    #[test]
+    fn test_invalid_lines_are_reported_and_skipped() {
+        let input = "not json\n\n{\"address\": \"addr_test1\"}\n";
+        let mut out = Vec::new();
+
+        let summary = run(
+            input.as_bytes(),
+            &mut out,
+            &SessionConfig {
+                job_id: Some("job-7"),
+                ..SessionConfig::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(summary.challenges, 2);
+        assert_eq!(summary.rejected, 2);
+        assert_eq!(summary.rom_builds, 0);
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["success"], false);
+        assert_eq!(lines[2]["summary"]["challenges"], 2);
+        assert!(lines.iter().all(|l| l["job_id"] == "job-7"));
+    }
+
+    #[test]
+    fn test_mining_errors_skip_only_their_line() {
+        let line = |challenge_id: &str| {
+            format!(
+                r#"{{"address": "addr_test1", "challenge_id": "{}", "difficulty": "FFFFFFFF",
+                "no_pre_mine": "{}", "latest_submission": "2025-10-19T08:59:59.000Z",
+                "no_pre_mine_hour": "509681483", "max_attempts": 1}}"#,
+                challenge_id,
+                "fd651ac2".repeat(8)
+            )
+            .replace('\n', " ")
+        };
+        let input = format!("{}\n{}\n", line(&"*".repeat(5000)), line("**D07C10"));
+        let mut out = Vec::new();
+
+        let summary = run(
+            input.as_bytes(),
+            &mut out,
+            &SessionConfig {
+                params: HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE),
+                ..SessionConfig::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(summary.challenges, 2);
+        assert_eq!(summary.rejected, 1);
+        assert_eq!(summary.solved, 1);
+        assert_eq!(summary.rom_builds, 1);
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["success"], false);
+        assert!(lines[0]["message"].as_str().unwrap().contains("Preimage would be"));
+        assert_eq!(lines[1]["success"], true);
+    }
+
+    #[test]
+    fn test_addresses_share_one_rom() {
+        // Every hash meets this
+        let template = Challenge {
+            address: String::new(),
+            ..test_challenge("FFFFFFFF")
+        };
+        let addresses = [
+            "addr_a".to_string(),
+            "addr_b".to_string(),
+            "addr_c".to_string(),
+        ];
+        let addresses: Vec<&String> = addresses.iter().collect();
+        let config = SessionConfig {
+            options: MineOptions {
+                max_attempts: 1,
+                ..MineOptions::default()
+            },
+            params: HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE),
+            rom_retry: RomRetry::default(),
+            preimage_encoding: PreimageEncoding::Hex,
+            verify_preimage_encoding: true,
+            ..SessionConfig::default()
+        };
+
+        for (first_success, expected) in [(false, 3), (true, 1)] {
+            let mut out = Vec::new();
+            let summary =
+                run_addresses(&template, &addresses, first_success, &mut out, &config).unwrap();
+            assert_eq!(summary.rom_builds, 1);
+            assert_eq!(summary.solved, expected);
+
+            let lines: Vec<serde_json::Value> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect();
+            assert_eq!(lines.len() as u64, expected);
+            assert_eq!(lines[0]["address"], "addr_a");
+            assert_eq!(lines[0]["success"], true);
+            let challenge = Challenge {
+                address: "addr_a".to_string(),
+                ..template.clone()
+            };
+            let preimage = challenge.preimage(lines[0]["nonce"].as_str().unwrap());
+            assert_eq!(lines[0]["preimage"], hex::encode(preimage));
+        }
+    }
+
+    #[test]
+    fn test_hours_share_one_rom() {
+        let hours: Hours = "509681483, 509681490-509681491".parse().unwrap();
+        assert_eq!(hours.0, ["509681483", "509681490", "509681491"]);
+        assert!("5-4".parse::<Hours>().is_err());
+        assert!("soon".parse::<Hours>().is_err());
+        assert!(format!("0-{}", MAX_HOURS).parse::<Hours>().is_err());
+
+        // Every hash meets this
+        let template = Challenge {
+            no_pre_mine_hour: String::new(),
+            ..test_challenge("FFFFFFFF")
+        };
+        let config = SessionConfig {
+            options: MineOptions {
+                max_attempts: 1,
+                ..MineOptions::default()
+            },
+            params: HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE),
+            rom_retry: RomRetry::default(),
+            ..SessionConfig::default()
+        };
+        let mut out = Vec::new();
+        let summary = run_hours(&template, &hours.0, &mut out, &config).unwrap();
+        assert_eq!(summary.rom_builds, 1);
+        assert_eq!(summary.solved, 3);
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        for (line, hour) in lines.iter().zip(&hours.0) {
+            assert_eq!(line["no_pre_mine_hour"], *hour);
+            assert!(line["preimage"].as_str().unwrap().ends_with(hour.as_str()));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+/*!
+# Address sharding
+
+Splits an address list across a fleet of identical workers: worker `i` of
+`N` owns the addresses whose SHA-256 (first 8 bytes, big-endian) is `i`
+modulo `N`. The hash is fixed so every worker, on any platform, computes
+the same partition.
+*/
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use cryptoxide::hashing::sha256;
+
+/// This worker's slot in the fleet, parsed from `i/N`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Whether this shard mines `address`
+    pub fn owns(&self, address: &str) -> bool {
+        let digest = sha256(address.as_bytes());
+        let prefix: [u8; 8] = digest[..8].try_into().expect("8-byte prefix");
+        u64::from_be_bytes(prefix) % self.count == self.index
+    }
+
+    /// The addresses this shard owns, in input order
+    pub fn select<'a>(&self, addresses: &'a [String]) -> Vec<&'a String> {
+        addresses.iter().filter(|a| self.owns(a)).collect()
+    }
+}
+
+impl FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (index, count) = s
+            .split_once('/')
+            .with_context(|| format!("shard '{}' must look like i/N", s))?;
+        let index: u64 = index
+            .trim()
+            .parse()
+            .context("shard index is not a number")?;
+        let count: u64 = count
+            .trim()
+            .parse()
+            .context("shard count is not a number")?;
+        if count == 0 {
+            bail!("shard count must be at least 1");
+        }
+        if index >= count {
+            bail!("shard index {} must be below the count {}", index, count);
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "2/5".parse::<Shard>().unwrap(),
+            Shard { index: 2, count: 5 }
+        );
+        assert_eq!("0/1".parse::<Shard>().unwrap().to_string(), "0/1");
+        assert!("5/5".parse::<Shard>().is_err());
+        assert!("0/0".parse::<Shard>().is_err());
+        assert!("3".parse::<Shard>().is_err());
+        assert!("a/b".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_shards_partition_addresses() {
+        let addresses: Vec<String> = (0..200).map(|i| format!("addr_test1q{:04}", i)).collect();
+        let count = 4;
+
+        let mut owners = vec![0; addresses.len()];
+        for index in 0..count {
+            let shard = Shard { index, count };
+            let owned = shard.select(&addresses);
+            // Roughly even, and never empty for a list this size
+            assert!(owned.len() > 20, "shard {} owns {}", shard, owned.len());
+            for address in owned {
+                owners[addresses.iter().position(|a| a == address).unwrap()] += 1;
+            }
+        }
+        assert!(owners.iter().all(|&n| n == 1));
+
+        // A single shard owns everything
+        assert_eq!(Shard { index: 0, count: 1 }.select(&addresses).len(), 200);
+    }
+}
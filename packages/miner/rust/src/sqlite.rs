@@ -0,0 +1,109 @@
+/*!
+# SQLite solution export
+
+Appends found solutions to a SQLite table so they can be queried later.
+*/
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+
+use crate::miner::Solution;
+use crate::output::RunStats;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS solutions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    nonce TEXT NOT NULL,
+    preimage TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    difficulty TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    elapsed_ms INTEGER NOT NULL,
+    found_at TEXT NOT NULL
+)";
+
+/// Solution store backed by a SQLite database file
+#[derive(Debug)]
+pub struct SolutionDb {
+    conn: Connection,
+}
+
+impl SolutionDb {
+    /// Open (or create) the database and make sure the schema exists
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+        conn.execute(SCHEMA, [])
+            .context("Failed to create solutions table")?;
+
+        Ok(Self { conn })
+    }
+
+    /// Insert a found solution, timestamped with the current UTC time
+    pub fn insert(&self, solution: &Solution, difficulty: &str, stats: &RunStats) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO solutions
+                    (nonce, preimage, hash, difficulty, attempts, elapsed_ms, found_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))",
+                params![
+                    solution.nonce,
+                    solution.preimage,
+                    solution.hash,
+                    difficulty,
+                    stats.attempts as i64,
+                    stats.elapsed_ms as i64,
+                ],
+            )
+            .context("Failed to insert solution into SQLite database")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_creates_schema() {
+        let path = std::env::temp_dir().join(format!("night-cloud-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let solution = Solution {
+            nonce: "0019c96b6a30ee38".to_string(),
+            preimage: "preimage".to_string(),
+            hash: "000694200fb04137".to_string(),
+        };
+        let stats = RunStats {
+            attempts: 42,
+            elapsed_ms: 1500,
+            hashrate: 28.0,
+            rom_build_ms: 0,
+            peak_memory_mb: None,
+            threads: 1,
+        };
+
+        // Reopening must not fail on the existing schema
+        SolutionDb::open(&path)
+            .unwrap()
+            .insert(&solution, "000FFFFF", &stats)
+            .unwrap();
+        let db = SolutionDb::open(&path).unwrap();
+        db.insert(&solution, "000FFFFF", &stats).unwrap();
+
+        let (count, elapsed): (i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*), MAX(elapsed_ms) FROM solutions",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(elapsed, 1500);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+/*!
+# Stats on SIGUSR1
+
+`kill -USR1 <pid>` makes a running miner write one JSON line with its
+attempts, elapsed time, hashrate since the previous dump and the closest
+hash so far to stderr, without stopping the search. The
+handler only writes a byte to a pipe; a background thread reads it and
+formats the line.
+
+Unix only; elsewhere [`StatsDump::install`] does nothing.
+*/
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::miner::BestHash;
+use crate::output::tagged;
+use crate::progress::{self, BestSoFar, Progress};
+
+/// One stats line
+#[derive(Debug, Serialize)]
+struct Stats {
+    #[serde(flatten)]
+    progress: Progress,
+    #[serde(flatten)]
+    best: BestSoFar,
+}
+
+/// Counters the mining threads keep up to date
+#[derive(Debug, Clone, Default)]
+pub struct LiveStats {
+    /// Hashes computed, as in [`MineOptions::progress`](crate::miner::MineOptions::progress)
+    pub attempts: Arc<AtomicU64>,
+    /// As in [`MineOptions::best`](crate::miner::MineOptions::best)
+    pub best: Arc<BestHash>,
+}
+
+/// Thread answering SIGUSR1 with a stats line until dropped
+pub struct StatsDump {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatsDump {
+    /// Install the SIGUSR1 handler, once per process; `job_id` is echoed
+    /// in every line
+    pub fn install(stats: LiveStats, job_id: Option<String>) -> Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        let thread = {
+            let wake = imp::install()?;
+            let stop = stop.clone();
+            Some(std::thread::spawn(move || {
+                run(wake, &stats, job_id.as_deref(), &stop)
+            }))
+        };
+        #[cfg(not(unix))]
+        let thread = {
+            let _ = (stats, job_id);
+            None
+        };
+        Ok(Self { stop, thread })
+    }
+}
+
+impl Drop for StatsDump {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            #[cfg(unix)]
+            imp::wake();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn run(wake: imp::Fd, stats: &LiveStats, job_id: Option<&str>, stop: &AtomicBool) {
+    let started = Instant::now();
+    let mut last = (0, Duration::ZERO);
+
+    while imp::wait(wake) && !stop.load(Ordering::Relaxed) {
+        let now = (stats.attempts.load(Ordering::Relaxed), started.elapsed());
+        let line = Stats {
+            progress: progress::progress(last, now),
+            best: BestSoFar::of(&stats.best),
+        };
+        last = now;
+
+        // Whole lines only, so concurrent stderr writers can't split one
+        if let Ok(json) = serde_json::to_string(&tagged(job_id, &line)) {
+            let _ = io::stderr()
+                .lock()
+                .write_all(format!("{}\n", json).as_bytes());
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use anyhow::{bail, Result};
+
+    pub type Fd = libc::c_int;
+
+    /// Write end of the pipe the handler wakes the dump thread through
+    static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_usr1(_signal: libc::c_int) {
+        wake();
+    }
+
+    /// Also called outside the handler to stop the thread, so it may only
+    /// do what is async-signal-safe
+    pub fn wake() {
+        let fd = WAKE_FD.load(Ordering::Relaxed);
+        if fd >= 0 {
+            // A full pipe already has a dump pending
+            unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+        }
+    }
+
+    /// Install the handler and return the pipe's read end
+    pub fn install() -> Result<Fd> {
+        let existing = WAKE_FD.load(Ordering::Relaxed);
+        if existing >= 0 {
+            bail!("The SIGUSR1 handler is already installed");
+        }
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            bail!(
+                "Failed to create the SIGUSR1 pipe: {}",
+                io::Error::last_os_error()
+            );
+        }
+        unsafe { libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK) };
+        WAKE_FD.store(fds[1], Ordering::Relaxed);
+
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_usr1 as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // Don't fail the blocking reads and writes of other threads
+        action.sa_flags = libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()) } != 0 {
+            bail!(
+                "Failed to install the SIGUSR1 handler: {}",
+                io::Error::last_os_error()
+            );
+        }
+        Ok(fds[0])
+    }
+
+    /// Block until the next wake-up; false once the pipe is unusable
+    pub fn wait(fd: Fd) -> bool {
+        let mut byte = 0u8;
+        loop {
+            match unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } {
+                1 => return true,
+                n if n < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sigusr1_does_not_stop_the_process() {
+        let stats = LiveStats::default();
+        stats.attempts.store(42, Ordering::Relaxed);
+        let dump = StatsDump::install(stats.clone(), None).unwrap();
+        assert!(StatsDump::install(stats, None).is_err());
+
+        // Without the handler, SIGUSR1 would terminate the test binary
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+        std::thread::sleep(Duration::from_millis(50));
+        drop(dump);
+    }
+}
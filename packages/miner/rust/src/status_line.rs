@@ -0,0 +1,104 @@
+/*!
+# Terminal status line
+
+Rewrites a single stderr line with attempts, hashrate and elapsed time while
+mining, for interactive use. Does nothing unless stderr is a terminal, so
+logs and pipes never see carriage returns.
+*/
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the line is redrawn
+const REFRESH: Duration = Duration::from_millis(500);
+
+/// Background thread redrawing the status line until dropped, which clears it
+pub struct StatusLine {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatusLine {
+    /// Start drawing the value of `attempts`, or `None` if stderr is not a TTY
+    pub fn spawn(attempts: Arc<AtomicU64>) -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(&attempts, &stop))
+        };
+        Some(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for StatusLine {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wait for the line to be cleared before the result is printed
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(attempts: &AtomicU64, stop: &AtomicBool) {
+    let started = Instant::now();
+    let mut stderr = io::stderr();
+
+    while !stop.load(Ordering::Relaxed) {
+        let line = render(attempts.load(Ordering::Relaxed), started.elapsed());
+        // \x1b[K clears whatever a longer previous line left behind
+        let _ = write!(stderr, "\r{}\x1b[K", line);
+        let _ = stderr.flush();
+        thread::park_timeout(REFRESH);
+    }
+
+    let _ = write!(stderr, "\r\x1b[K");
+    let _ = stderr.flush();
+}
+
+/// Text of the status line
+fn render(attempts: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let hashrate = if secs > 0.0 {
+        attempts as f64 / secs
+    } else {
+        0.0
+    };
+    let total = elapsed.as_secs();
+    format!(
+        "⛏️  {} attempts | {:.1} H/s | {:02}:{:02}:{:02}",
+        attempts,
+        hashrate,
+        total / 3600,
+        total / 60 % 60,
+        total % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(1500, Duration::from_millis(3_725_000)),
+            "⛏️  1500 attempts | 0.4 H/s | 01:02:05"
+        );
+        assert_eq!(
+            render(0, Duration::ZERO),
+            "⛏️  0 attempts | 0.0 H/s | 00:00:00"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+/*!
+# Syslog output
+
+Sends mining results to the local syslog daemon as compact JSON.
+*/
+
+use anyhow::{anyhow, Result};
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+
+use crate::output::MineResult;
+
+/// Connection to the local syslog socket
+pub struct SyslogSink {
+    logger: Logger<LoggerBackend, Formatter3164>,
+}
+
+impl SyslogSink {
+    /// Connect to the local daemon, e.g. `connect("local0", "night-cloud")`
+    pub fn connect(facility: &str, tag: &str) -> Result<Self> {
+        let facility: Facility = facility
+            .parse()
+            .map_err(|_| anyhow!("Unknown syslog facility '{}'", facility))?;
+        let formatter = Formatter3164 {
+            facility,
+            hostname: None,
+            process: tag.to_string(),
+            pid: std::process::id(),
+        };
+        let logger = syslog::unix(formatter)
+            .map_err(|e| anyhow!("Failed to connect to syslog: {}", e))?;
+
+        Ok(Self { logger })
+    }
+
+    /// Send a result as one JSON line; solutions are logged at notice,
+    /// misses at info
+    pub fn send_result(&mut self, result: &MineResult) -> Result<()> {
+        let json = serde_json::to_string(result)?;
+        let sent = match result {
+            MineResult::Success(_) => self.logger.notice(json),
+            MineResult::Failure(_) => self.logger.info(json),
+        };
+        sent.map_err(|e| anyhow!("Failed to write to syslog: {}", e))
+    }
+}
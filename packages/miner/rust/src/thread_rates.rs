@@ -0,0 +1,118 @@
+/*!
+# Per-thread hashrates
+
+With `--verbose`, every mining thread counts its own hashes and a
+background thread logs the hashrate of each next to their sum every
+interval, so one throttled or starved core shows up instead of hiding in
+the aggregate.
+*/
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+/// Interval between reports without `--progress-interval`
+pub const INTERVAL: Duration = Duration::from_secs(10);
+
+/// One zeroed counter per mining thread, for
+/// [`MineOptions::thread_attempts`](crate::miner::MineOptions::thread_attempts)
+pub fn counters(threads: usize) -> Arc<[AtomicU64]> {
+    (0..threads.max(1)).map(|_| AtomicU64::new(0)).collect()
+}
+
+/// Background thread logging per-thread hashrates until dropped
+pub struct ThreadRateReporter {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ThreadRateReporter {
+    /// Log the rate at which each of `attempts` grows every `interval`
+    pub fn spawn(attempts: Arc<[AtomicU64]>, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run(&attempts, interval, &stop))
+        };
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for ThreadRateReporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(attempts: &[AtomicU64], interval: Duration, stop: &AtomicBool) {
+    let mut last_at = Instant::now();
+    let mut last = vec![0; attempts.len()];
+
+    loop {
+        thread::park_timeout(interval);
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let now: Vec<u64> = attempts.iter().map(|a| a.load(Ordering::Relaxed)).collect();
+        let secs = last_at.elapsed().as_secs_f64();
+        last_at = Instant::now();
+        debug!("{}", render(&rates(&last, &now, secs)));
+        last = now;
+    }
+}
+
+/// Hashes per second of each thread over `secs`, from its counts before
+/// and after
+fn rates(last: &[u64], now: &[u64], secs: f64) -> Vec<f64> {
+    last.iter()
+        .zip(now)
+        .map(|(last, now)| {
+            if secs > 0.0 {
+                now.saturating_sub(*last) as f64 / secs
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Log line for per-thread `rates`, their sum first
+fn render(rates: &[f64]) -> String {
+    let threads: Vec<String> = rates
+        .iter()
+        .enumerate()
+        .map(|(index, rate)| format!("#{} {:.1}", index, rate))
+        .collect();
+    format!(
+        "Hashrate: {:.1} H/s [{}]",
+        rates.iter().sum::<f64>(),
+        threads.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_rates() {
+        let rates = rates(&[100, 100, 0], &[300, 150, 0], 2.0);
+        assert_eq!(rates, [100.0, 25.0, 0.0]);
+        assert_eq!(
+            render(&rates),
+            "Hashrate: 125.0 H/s [#0 100.0, #1 25.0, #2 0.0]"
+        );
+        assert_eq!(super::rates(&[0], &[10], 0.0), [0.0]);
+        assert_eq!(counters(0).len(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+/*!
+# Input validation
+
+Field checks for challenge parameters, shared by the `validate`
+subcommand and the mining entry points.
+*/
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::miner::Challenge;
+use crate::{deadline, hasher};
+
+/// Check applied to a single string field
+type Validator = fn(&str) -> Result<()>;
+
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Outcome of validating one field
+#[derive(Debug, Serialize)]
+pub struct FieldCheck {
+    pub field: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Per-field results for a params object
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub fields: Vec<FieldCheck>,
+}
+
+impl ValidationReport {
+    pub fn new(params: &Value) -> Self {
+        let fields = check_params(params);
+        Self {
+            valid: fields.iter().all(|f| f.ok),
+            fields,
+        }
+    }
+}
+
+/// Check every challenge field in a params object, reporting each
+/// field separately so one bad value doesn't hide the others
+pub fn check_params(params: &Value) -> Vec<FieldCheck> {
+    let required: [(&'static str, Validator); 6] = [
+        ("address", address),
+        ("challenge_id", non_empty),
+        ("difficulty", difficulty),
+        ("no_pre_mine", seed),
+        ("latest_submission", timestamp),
+        ("no_pre_mine_hour", number),
+    ];
+
+    let mut checks: Vec<FieldCheck> = required
+        .into_iter()
+        .map(|(field, check)| {
+            let result = match params.get(field) {
+                None | Some(Value::Null) => Err(anyhow::anyhow!("missing")),
+                Some(Value::String(value)) => check(value),
+                Some(_) => Err(anyhow::anyhow!("must be a string")),
+            };
+            FieldCheck::new(field, result)
+        })
+        .collect();
+
+    if let Some(value) = params.get("max_attempts") {
+        let result = match value.as_u64() {
+            Some(_) => Ok(()),
+            None => Err(anyhow::anyhow!("must be a non-negative integer")),
+        };
+        checks.push(FieldCheck::new("max_attempts", result));
+    }
+
+    checks
+}
+
+/// Check the fields of a challenge about to be mined, naming every bad one
+///
+/// The address is only required to be present here: unlike the `validate`
+/// subcommand, mining doesn't insist on a bech32 checksum.
+pub fn challenge(challenge: &Challenge) -> Result<()> {
+    let checks: [(&str, &str, Validator); 5] = [
+        ("challenge_id", &challenge.challenge_id, non_empty),
+        ("difficulty", &challenge.difficulty, difficulty),
+        ("no_pre_mine", &challenge.no_pre_mine, seed),
+        ("latest_submission", &challenge.latest_submission, timestamp),
+        ("no_pre_mine_hour", &challenge.no_pre_mine_hour, number),
+    ];
+    let problems: Vec<String> = checks
+        .into_iter()
+        .filter_map(|(field, value, check)| check(value).err().map(|e| format!("{} {:#}", field, e)))
+        .collect();
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(())
+}
+
+impl FieldCheck {
+    fn new(field: &'static str, result: Result<()>) -> Self {
+        Self {
+            field,
+            ok: result.is_ok(),
+            error: result.err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+/// Reject empty (or whitespace-only) values
+pub fn non_empty(value: &str) -> Result<()> {
+    if value.trim().is_empty() {
+        bail!("must not be empty");
+    }
+    Ok(())
+}
+
+/// Require a non-empty hex string
+pub fn hex(value: &str) -> Result<()> {
+    non_empty(value)?;
+    if !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("'{}' is not valid hex", value);
+    }
+    Ok(())
+}
+
+/// Hex digits in a no_pre_mine value, which seeds the ROM
+const SEED_HEX_LEN: usize = 64;
+
+/// Require a no_pre_mine value of the spec's length
+pub fn seed(value: &str) -> Result<()> {
+    hex(value)?;
+    if value.len() != SEED_HEX_LEN {
+        bail!(
+            "'{}' is {} hex digits, expected {}",
+            value,
+            value.len(),
+            SEED_HEX_LEN
+        );
+    }
+    Ok(())
+}
+
+/// Read a no_pre_mine value from `path`, without the whitespace around it,
+/// so a trailing newline seeds the same ROM as the inline value
+///
+/// The value is left out of errors, as it was kept off the command line.
+pub fn seed_file(path: &Path) -> Result<String> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let value = text.trim();
+    if value.len() != SEED_HEX_LEN || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!(
+            "{} does not hold a {}-digit hex no_pre_mine value",
+            path.display(),
+            SEED_HEX_LEN
+        );
+    }
+    Ok(value.to_string())
+}
+
+/// Require a hex difficulty mask no longer than a hash
+pub fn difficulty(value: &str) -> Result<()> {
+    hex(value)?;
+    if value.len() > 2 * hasher::DIGEST_SIZE {
+        bail!(
+            "'{}' is longer than the {} hex digits of a hash",
+            value,
+            2 * hasher::DIGEST_SIZE
+        );
+    }
+    Ok(())
+}
+
+/// Require an RFC 3339 timestamp
+pub fn timestamp(value: &str) -> Result<()> {
+    deadline::parse(value).map(|_| ())
+}
+
+/// Require a decimal integer
+pub fn number(value: &str) -> Result<()> {
+    if value.parse::<u64>().is_err() {
+        bail!("'{}' is not a number", value);
+    }
+    Ok(())
+}
+
+/// Require a bech32 string with a valid checksum (Cardano addresses)
+///
+/// Unlike BIP-173 there is no 90 character limit, since Shelley
+/// addresses are longer than that.
+pub fn address(value: &str) -> Result<()> {
+    non_empty(value)?;
+    if value.chars().any(|c| c.is_ascii_lowercase())
+        && value.chars().any(|c| c.is_ascii_uppercase())
+    {
+        bail!("address mixes upper and lower case");
+    }
+    let value = value.to_ascii_lowercase();
+
+    let Some((hrp, data)) = value.rsplit_once('1') else {
+        bail!("address has no bech32 separator");
+    };
+    if hrp.is_empty() || !hrp.bytes().all(|b| (33..=126).contains(&b)) {
+        bail!("address has an invalid human-readable prefix");
+    }
+    if data.len() < 6 {
+        bail!("address is too short to carry a checksum");
+    }
+
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    for c in data.bytes() {
+        match BECH32_CHARSET.iter().position(|&x| x == c) {
+            Some(v) => values.push(v as u8),
+            None => bail!(
+                "address contains '{}', which is not a bech32 character",
+                c as char
+            ),
+        }
+    }
+
+    if bech32_polymod(&values) != 1 {
+        bail!("address checksum is invalid");
+    }
+    Ok(())
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+    values.iter().fold(1u32, |chk, &v| {
+        let top = chk >> 25;
+        let chk = ((chk & 0x1ffffff) << 5) ^ v as u32;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(chk, |chk, (_, g)| chk ^ g)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bech32_address() {
+        assert!(address("A12UEL5L").is_ok());
+        assert!(address("abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw").is_ok());
+        assert!(address("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x").is_ok());
+
+        // Flipped checksum character
+        assert!(address("abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxx").is_err());
+        assert!(address("Abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw").is_err());
+        assert!(address("addr_test").is_err());
+        assert!(address("").is_err());
+    }
+
+    #[test]
+    fn test_check_params() {
+        let params = serde_json::json!({
+            "address": "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x",
+            "challenge_id": "**D07C10",
+            "difficulty": "GGFF",
+            "no_pre_mine": "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011",
+            "latest_submission": "2025-10-19T08:59:59.000Z",
+            "max_attempts": -1,
+        });
+
+        let checks = check_params(&params);
+        let failed: Vec<_> = checks.iter().filter(|c| !c.ok).map(|c| c.field).collect();
+        assert_eq!(failed, ["difficulty", "no_pre_mine_hour", "max_attempts"]);
+        assert_eq!(checks[2].error.as_deref(), Some("'GGFF' is not valid hex"));
+        assert_eq!(checks[5].error.as_deref(), Some("missing"));
+    }
+
+    #[test]
+    fn test_challenge() {
+        let mut challenge = Challenge {
+            no_pre_mine: "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011"
+                .to_string(),
+            ..crate::vectors::test_challenge("000FFFFF")
+        };
+        assert!(super::challenge(&challenge).is_ok());
+
+        challenge.difficulty = "GGFF".to_string();
+        challenge.no_pre_mine = "fd651ac2".to_string();
+        challenge.no_pre_mine_hour = "soon".to_string();
+        let error = super::challenge(&challenge).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "difficulty 'GGFF' is not valid hex; \
+             no_pre_mine 'fd651ac2' is 8 hex digits, expected 64; \
+             no_pre_mine_hour 'soon' is not a number"
+        );
+    }
+
+    #[test]
+    fn test_seed_file() {
+        let seed = "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011";
+        let path = std::env::temp_dir().join(format!("night-cloud-seed-{}", std::process::id()));
+        std::fs::write(&path, format!("{}\n", seed)).unwrap();
+        assert_eq!(seed_file(&path).unwrap(), seed);
+        std::fs::write(&path, format!("  {}\r\n\n", seed)).unwrap();
+        assert_eq!(seed_file(&path).unwrap(), seed);
+
+        std::fs::write(&path, "fd651ac2\n").unwrap();
+        let error = seed_file(&path).unwrap_err().to_string();
+        assert!(error.ends_with("does not hold a 64-digit hex no_pre_mine value"));
+        assert!(!error.contains("fd651ac2"));
+        std::fs::write(&path, seed.replace('f', "g")).unwrap();
+        assert!(seed_file(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(seed_file(&path).is_err());
+    }
+}
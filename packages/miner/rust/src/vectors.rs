@@ -0,0 +1,313 @@
+/*!
+# Test vectors
+
+Canonical (params, preimage, hash) triples generated by the real preimage
+and hashing code, for documentation and cross-implementation tests. Most
+use a small ROM so they are quick to reproduce; the full-size vector uses
+the production ROM and the spec example.
+*/
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::hasher::{self, AshMaizeHasher, HashParams};
+use crate::miner::{Challenge, DifficultyMode};
+
+/// Nonces hashed against every challenge
+const NONCES: [&str; 3] = ["0019c96b6a30ee38", "0000000000000000", "ffffffffffffffff"];
+
+/// Hash of the spec preimage (the spec challenge with the first nonce)
+/// under the production ROM
+///
+/// The spec documents `000694200fb0...` for this preimage, which the
+/// vendored ashmaize reproduces with neither seed encoding (see the
+/// `compare` subcommand), whether `no_pre_mine` goes into the preimage as
+/// hex text or as decoded bytes; this pins what the miner computes today,
+/// so a dependency bump that changes it is caught before mining.
+pub const SPEC_HASH: &str = "eaddeaba4df04eee8909bf8174b6356c39fec3c71a1caf79a4a578033d52b3792a21f895430c025c51cfc083f99ad45cd43971b817afe9881f4f4dd06ad03b72";
+
+/// Hash of the spec preimage under the small ROM
+pub const SPEC_SMALL_HASH: &str = "1fbe4ee193ccf0a2b3e5c427090afba77fbfdafa9adcf31f1d0b4fb1517bc8381de01a208c5c03b301b50377a00795c70538d6d4c2d67198536bbc4e5cc7b79c";
+
+/// ROM and hash parameters a vector was generated with
+#[derive(Debug, Clone, Serialize)]
+pub struct RomSpec {
+    pub pre_size: usize,
+    pub rom_size: usize,
+    pub mixing_numbers: usize,
+    pub nb_loops: u32,
+    pub nb_instrs: u32,
+}
+
+impl RomSpec {
+    fn new(pre_size: usize, rom_size: usize) -> Self {
+        Self {
+            pre_size,
+            rom_size,
+            mixing_numbers: hasher::MIXING_NUMBERS,
+            nb_loops: hasher::NB_LOOPS,
+            nb_instrs: hasher::NB_INSTRS,
+        }
+    }
+}
+
+/// One generated vector
+#[derive(Debug, Serialize)]
+pub struct Vector {
+    pub name: String,
+    pub rom: RomSpec,
+    pub params: Challenge,
+    pub nonce: String,
+    pub preimage: String,
+    pub hash: String,
+    pub meets_difficulty: bool,
+}
+
+/// Generate the small-ROM vectors, plus the full-size spec vector when
+/// `include_full` is set (which builds the 1 GiB ROM)
+pub fn generate(include_full: bool) -> Result<Vec<Vector>> {
+    let mut vectors = Vec::new();
+
+    for (i, challenge) in challenges().iter().enumerate() {
+        let rom = RomSpec::new(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE);
+        let params = HashParams::with_sizes(rom.pre_size, rom.rom_size);
+        let hasher = AshMaizeHasher::try_with_params(&challenge.no_pre_mine, &params)?;
+        for nonce in NONCES {
+            vectors.push(vector(
+                format!("small-{}-{}", i, nonce),
+                rom.clone(),
+                &hasher,
+                challenge,
+                nonce,
+            ));
+        }
+    }
+
+    if include_full {
+        let challenge = spec_challenge();
+        let hasher = AshMaizeHasher::try_new(&challenge.no_pre_mine)?;
+        vectors.push(vector(
+            "spec-full-size".to_string(),
+            RomSpec::new(hasher::PRE_SIZE, hasher::ROM_SIZE),
+            &hasher,
+            &challenge,
+            NONCES[0],
+        ));
+    }
+
+    Ok(vectors)
+}
+
+fn vector(
+    name: String,
+    rom: RomSpec,
+    hasher: &AshMaizeHasher,
+    challenge: &Challenge,
+    nonce: &str,
+) -> Vector {
+    let preimage = challenge.preimage(nonce);
+    let hash = hex::encode(hasher.hash(preimage.as_bytes()));
+    Vector {
+        name,
+        meets_difficulty: DifficultyMode::Mask.check(&hash, &challenge.difficulty),
+        rom,
+        params: challenge.clone(),
+        nonce: nonce.to_string(),
+        preimage,
+        hash,
+    }
+}
+
+/// Vector checked by [`self_test`], the spec one unless overridden for a
+/// deployment whose vector differs
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestVector {
+    /// Preimage hashed instead of the spec one
+    pub preimage: Option<String>,
+    /// Hex hash expected under the production ROM instead of [`SPEC_HASH`]
+    pub expected_hash: Option<String>,
+}
+
+impl SelfTestVector {
+    fn is_spec(&self) -> bool {
+        self.preimage.is_none() && self.expected_hash.is_none()
+    }
+
+    /// Check that `hasher` hashes the preimage to the expected hash,
+    /// `spec_hash` being what the spec preimage hashes to under it
+    fn check(&self, hasher: &AshMaizeHasher, spec_hash: &str) -> Result<()> {
+        let preimage = match &self.preimage {
+            Some(preimage) => preimage.clone(),
+            None => spec_challenge().preimage(NONCES[0]),
+        };
+        let expected = self.expected_hash.as_deref().unwrap_or(spec_hash);
+        let computed = hex::encode(hasher.hash(preimage.as_bytes()));
+        if !computed.eq_ignore_ascii_case(expected) {
+            bail!(
+                "Self-test failed: {} preimage hashed to {}, expected {}",
+                if self.preimage.is_some() {
+                    "test"
+                } else {
+                    "spec"
+                },
+                computed,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Check that `vector` still hashes as expected, building the 1 GiB ROM
+/// from the spec `no_pre_mine`
+///
+/// The spec vector is first checked against [`SPEC_SMALL_HASH`] under the
+/// small ROM, which fails faster; there is no small-ROM hash for an
+/// overridden one.
+pub fn self_test(vector: &SelfTestVector) -> Result<()> {
+    let challenge = spec_challenge();
+    if vector.is_spec() {
+        let params = HashParams::with_sizes(hasher::SMALL_PRE_SIZE, hasher::SMALL_ROM_SIZE);
+        let small = AshMaizeHasher::try_with_params(&challenge.no_pre_mine, &params)?;
+        vector.check(&small, SPEC_SMALL_HASH)?;
+    }
+    vector.check(&AshMaizeHasher::try_new(&challenge.no_pre_mine)?, SPEC_HASH)
+}
+
+/// The example challenge from the Scavenger Mine spec
+pub fn spec_challenge() -> Challenge {
+    Challenge {
+        address: "addr_test1qq4dl3nhr0axurgcrpun9xyp04pd2r2dwu5x7eeam98psv6dhxlde8ucc1v2p46hm077ds4vzelf5565fg3ky794uhrq5up0he".to_string(),
+        challenge_id: "**D07C10".to_string(),
+        difficulty: "000FFFFF".to_string(),
+        no_pre_mine: "fd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f011".to_string(),
+        latest_submission: "2025-10-19T08:59:59.000Z".to_string(),
+        no_pre_mine_hour: "509681483".to_string(),
+    }
+}
+
+/// Challenges covered by the small vectors
+fn challenges() -> Vec<Challenge> {
+    let spec = spec_challenge();
+    let other = Challenge {
+        address: "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x".to_string(),
+        challenge_id: "**D21C03".to_string(),
+        difficulty: "00FFFFFF".to_string(),
+        no_pre_mine: "00".repeat(32),
+        latest_submission: "2025-11-01T00:00:00.000Z".to_string(),
+        no_pre_mine_hour: "0".to_string(),
+    };
+    vec![spec, other]
+}
+
+/// Challenge the unit tests mine, seeded with a short `no_pre_mine` for
+/// [`AshMaizeHasher::small`]
+#[cfg(test)]
+pub(crate) fn test_challenge(difficulty: &str) -> Challenge {
+    Challenge {
+        address: "addr_test1".to_string(),
+        challenge_id: "**D07C10".to_string(),
+        difficulty: difficulty.to_string(),
+        no_pre_mine: "fd651ac2".to_string(),
+        latest_submission: "2025-10-19T08:59:59.000Z".to_string(),
+        no_pre_mine_hour: "509681483".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_vectors() {
+        let vectors = generate(false).unwrap();
+        assert_eq!(vectors.len(), 2 * NONCES.len());
+
+        let first = &vectors[0];
+        assert_eq!(first.name, "small-0-0019c96b6a30ee38");
+        assert!(first.preimage.starts_with("0019c96b6a30ee38addr_test1"));
+        assert!(first
+            .preimage
+            .ends_with("2025-10-19T08:59:59.000Z509681483"));
+        assert_eq!(first.hash.len(), 128);
+        assert_eq!(first.hash, SPEC_SMALL_HASH);
+
+        // Regenerating gives the same fixtures
+        let again = generate(false).unwrap();
+        assert!(vectors.iter().zip(&again).all(|(a, b)| a.hash == b.hash));
+    }
+
+    #[test]
+    fn test_spec_hash_check() {
+        let challenge = spec_challenge();
+        let hasher = AshMaizeHasher::with_sizes(
+            &challenge.no_pre_mine,
+            hasher::SMALL_PRE_SIZE,
+            hasher::SMALL_ROM_SIZE,
+        );
+        let spec = SelfTestVector::default();
+        spec.check(&hasher, SPEC_SMALL_HASH).unwrap();
+        let err = spec.check(&hasher, SPEC_HASH).unwrap_err();
+        assert!(err.to_string().contains(SPEC_SMALL_HASH));
+    }
+
+    #[test]
+    fn test_self_test_overrides() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let preimage = "0000000000000000addr_test1**D07C10";
+        let hash = hex::encode(hasher.hash(preimage.as_bytes()));
+
+        // An operator's own vector, in either case
+        let custom = SelfTestVector {
+            preimage: Some(preimage.to_string()),
+            expected_hash: Some(hash.to_uppercase()),
+        };
+        assert!(!custom.is_spec());
+        custom.check(&hasher, SPEC_SMALL_HASH).unwrap();
+
+        // Overriding only the preimage still expects the spec hash
+        let preimage_only = SelfTestVector {
+            expected_hash: None,
+            ..custom.clone()
+        };
+        let err = preimage_only.check(&hasher, SPEC_SMALL_HASH).unwrap_err();
+        assert!(err.to_string().contains("test preimage hashed to"));
+        assert!(err.to_string().contains(SPEC_SMALL_HASH));
+
+        // Overriding only the hash checks it against the spec preimage
+        let hash_only = SelfTestVector {
+            preimage: None,
+            ..custom
+        };
+        let err = hash_only.check(&hasher, SPEC_SMALL_HASH).unwrap_err();
+        assert!(err.to_string().contains("spec preimage hashed to"));
+        assert!(err.to_string().contains(&hash.to_uppercase()));
+    }
+
+    #[test]
+    fn test_no_pre_mine_is_hex_text_in_preimage() {
+        // The spec's example preimage, byte for byte
+        const SPEC_PREIMAGE: &str = "0019c96b6a30ee38addr_test1qq4dl3nhr0axurgcrpun9xyp04pd2r2dwu5x7eeam98psv6dhxlde8ucc1v2p46hm077ds4vzelf5565fg3ky794uhrq5up0he**D07C10000FFFFFfd651ac2725e3b9d804cc8b161c0709af14d6264f93e8d4afef0fd1142a3f0112025-10-19T08:59:59.000Z509681483";
+        let challenge = spec_challenge();
+        let preimage = challenge.preimage(NONCES[0]);
+        assert_eq!(preimage, SPEC_PREIMAGE);
+
+        // Splicing in the decoded bytes instead gives another preimage and
+        // another hash, so getting this wrong would not go unnoticed
+        let (before, after) = preimage.split_once(&challenge.no_pre_mine).unwrap();
+        let decoded = [
+            before.as_bytes(),
+            &hex::decode(&challenge.no_pre_mine).unwrap(),
+            after.as_bytes(),
+        ]
+        .concat();
+        assert_eq!(decoded.len(), preimage.len() - 32);
+        let hasher = AshMaizeHasher::with_sizes(
+            &challenge.no_pre_mine,
+            hasher::SMALL_PRE_SIZE,
+            hasher::SMALL_ROM_SIZE,
+        );
+        assert_eq!(hex::encode(hasher.hash(preimage.as_bytes())), SPEC_SMALL_HASH);
+        assert_ne!(hex::encode(hasher.hash(&decoded)), SPEC_SMALL_HASH);
+    }
+}
@@ -0,0 +1,61 @@
+/*!
+# Solution verification
+
+Recomputes the hash of a nonce or preimage exactly as mining does and checks
+it against the difficulty, to reproduce a rejected submission locally.
+*/
+
+use serde::Serialize;
+
+use crate::hasher::AshMaizeHasher;
+use crate::miner::DifficultyMode;
+
+/// What `verify` printed for one preimage
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub preimage: String,
+    pub hash: String,
+    pub difficulty: String,
+    pub meets_difficulty: bool,
+}
+
+/// Hash `preimage` and compare it against the `difficulty` mask
+pub fn verify(hasher: &AshMaizeHasher, preimage: String, difficulty: &str) -> Verification {
+    let hash = hex::encode(hasher.hash(preimage.as_bytes()));
+    Verification {
+        meets_difficulty: DifficultyMode::Mask.check(&hash, difficulty),
+        preimage,
+        hash,
+        difficulty: difficulty.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::{self, MineOptions};
+    use crate::vectors::test_challenge;
+
+    #[test]
+    fn test_verify_agrees_with_mining() {
+        let hasher = AshMaizeHasher::small("fd651ac2");
+        let challenge = test_challenge("FFFFFFFF");
+        let options = MineOptions {
+            max_attempts: 1,
+            ..MineOptions::default()
+        };
+        let solution = miner::mine_solution(&hasher, &challenge, &options)
+            .unwrap()
+            .solution
+            .unwrap();
+
+        let verified = verify(&hasher, challenge.preimage(&solution.nonce), "FFFFFFFF");
+        assert_eq!(verified.preimage, solution.preimage);
+        assert_eq!(verified.hash, solution.hash);
+        assert!(verified.meets_difficulty);
+
+        // A mask of all zero bits only accepts an all-zero prefix
+        let strict = verify(&hasher, solution.preimage, "00000000");
+        assert_eq!(strict.meets_difficulty, strict.hash.starts_with("00000000"));
+    }
+}
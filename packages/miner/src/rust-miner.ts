@@ -2,6 +2,11 @@ import { spawn, ChildProcess } from "child_process";
 import * as path from "path";
 import * as os from "os";
 
+/**
+ * Exit code of a miner run that found no solution within its budget
+ */
+const EXIT_NO_SOLUTION = 2;
+
 /**
  * Parameters for mining
  */
@@ -71,6 +76,10 @@ export class RustMinerWrapper {
         params.latestSubmission,
         "--no-pre-mine-hour",
         params.noPreMineHour,
+        // One process per worker already covers the CPUs; the binary would
+        // otherwise start a thread per CPU in each of them
+        "--threads",
+        "1",
       ];
 
       const worker = spawn(this.binaryPath, args, {
@@ -116,7 +125,9 @@ export class RustMinerWrapper {
           return;
         }
 
-        if (code !== 0) {
+        // Exit code 2 means the worker ran out of attempts without a
+        // solution; it still prints a JSON result
+        if (code !== 0 && code !== EXIT_NO_SOLUTION) {
           reject(new Error(`Worker ${workerId} exited with code ${code}: ${stderr}`));
           return;
         }